@@ -0,0 +1,29 @@
+use std::{env, process::ExitCode};
+
+use raconteur::prelude::*;
+
+// Prints a scenario graph stored as a RON file as Graphviz DOT, or as a Mermaid flowchart.
+// usage: cargo run --example export -- [--mermaid] path/to/graph.ron
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let mermaid = args.iter().any(|arg| arg == "--mermaid");
+    let Some(path) = args.iter().find(|arg| !arg.starts_with("--")) else {
+        eprintln!("usage: export [--mermaid] path/to/graph.ron");
+        return ExitCode::FAILURE;
+    };
+
+    let graph = match ScenarioGraph::load(path) {
+        Ok(graph) => graph,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if mermaid {
+        print!("{}", graph.to_mermaid());
+    } else {
+        print!("{}", graph.to_dot());
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,37 @@
+use std::{env, process::ExitCode};
+
+use raconteur::prelude::*;
+
+// Prints every translatable text of scenario graphs stored as RON files, as a RON string table
+// to translate and load with `Localization::insert_table`.
+// usage: cargo run --example extract_strings -- path/to/graph.ron...
+fn main() -> ExitCode {
+    let paths = env::args().skip(1).collect::<Vec<_>>();
+    if paths.is_empty() {
+        eprintln!("usage: extract_strings path/to/graph.ron...");
+        return ExitCode::FAILURE;
+    }
+
+    let mut graphs = vec![];
+    for path in &paths {
+        match ScenarioGraph::load(path) {
+            Ok(graph) => graphs.push(graph),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let table = StringTable::extract(&graphs);
+    match ron::ser::to_string_pretty(&table, ron::ser::PrettyConfig::default()) {
+        Ok(table) => {
+            println!("{}", table);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
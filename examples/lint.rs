@@ -0,0 +1,36 @@
+use std::{env, process::ExitCode};
+
+use raconteur::prelude::*;
+
+// Lints scenario graphs stored as RON files.
+// usage: cargo run --example lint -- path/to/graph.ron...
+fn main() -> ExitCode {
+    let mut has_errors = false;
+
+    for path in env::args().skip(1) {
+        let graph = match ScenarioGraph::load(&path) {
+            Ok(graph) => graph,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                has_errors = true;
+                continue;
+            }
+        };
+
+        for diagnostic in graph.validate() {
+            println!(
+                "{}: {}: {}",
+                path,
+                diagnostic.severity(),
+                diagnostic.describe(&graph)
+            );
+            has_errors |= diagnostic.is_error();
+        }
+    }
+
+    if has_errors {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
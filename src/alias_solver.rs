@@ -0,0 +1,695 @@
+use itertools::Itertools;
+
+use crate::{
+    constraint::AliasRelation,
+    entity::EntityId,
+    narrative_world::NarrativeWorld,
+    property::PropertyMap,
+    query::QueryOptions,
+    scenario_action::{Alias, ConstrainedAlias},
+    scenario_graph::AliasMap,
+};
+
+// A relation constraint, checked once both of its endpoints are bound.
+// Endpoints are stored as positions in the binding order.
+struct RelationCheck<'a> {
+    me: usize,
+    other: usize,
+    relation: &'a AliasRelation,
+}
+
+// A group alias, bound once every other alias is bound
+struct Group<'a> {
+    alias: &'a ConstrainedAlias,
+    candidates: Vec<EntityId>,
+    // pinned entity, which must be a member
+    pinned: Option<EntityId>,
+}
+
+// Lazily enumerates alias bindings through backtracking.
+// Aliases with the fewest candidates are bound first, and every relation is checked as soon as
+// both of its endpoints are bound, pruning whole subtrees instead of filtering a cartesian product.
+// Optional aliases come last, and are only left unbound when none of their candidates completes a binding.
+// Group aliases then take every remaining candidate, up to their maximum size,
+// seating the pinned and focus entities first.
+pub struct AliasBindings<'a> {
+    context: &'a NarrativeWorld,
+    aliases: Vec<&'a ConstrainedAlias>,
+    candidates: Vec<Vec<EntityId>>,
+    // checks[depth] holds the relations whose last bound endpoint is at that depth
+    checks: Vec<Vec<RelationCheck<'a>>>,
+    // entities which must be bound to some alias, along with the last depth they can be bound at.
+    // Only those which no group alias can hold, as they can't be ruled out before the groups are bound.
+    focus: Vec<(EntityId, usize)>,
+    // whether an optional alias may be left unbound, pinned aliases never are
+    optional: Vec<bool>,
+    // whether a binding was completed since the candidates at that depth were last reset
+    completed: Vec<bool>,
+    cursors: Vec<usize>,
+    bound: Vec<Option<EntityId>>,
+    groups: Vec<Group<'a>>,
+    // relations involving group aliases
+    group_relations: Vec<&'a AliasRelation>,
+    // focus entities which a group alias can hold, checked once the groups are bound
+    group_focus: Vec<EntityId>,
+    exhausted: bool,
+}
+
+impl<'a> AliasBindings<'a> {
+    pub(crate) fn new<A, R>(
+        constrained_aliases: A,
+        relations: R,
+        context: &'a NarrativeWorld,
+        options: &QueryOptions,
+    ) -> Self
+    where
+        A: IntoIterator<Item = &'a ConstrainedAlias>,
+        R: IntoIterator<Item = &'a AliasRelation>,
+    {
+        let constrained_aliases = constrained_aliases.into_iter().collect_vec();
+        let candidates_of = |constrained_alias: &ConstrainedAlias| {
+            context
+                .entities()
+                .filter(|entity| constrained_alias.is_satisfied_by(entity))
+                .map(|entity| entity.id())
+                .sorted()
+                .collect_vec()
+        };
+        let (group_aliases, individual_aliases): (Vec<_>, Vec<_>) = constrained_aliases
+            .iter()
+            .partition(|constrained_alias| constrained_alias.group.is_some());
+        let groups = group_aliases
+            .into_iter()
+            .map(|&alias| Group {
+                alias,
+                candidates: candidates_of(alias),
+                pinned: options.pinned_aliases.get(alias.alias()),
+            })
+            .collect_vec();
+
+        let mut by_candidate_count = individual_aliases
+            .into_iter()
+            .map(|&constrained_alias| {
+                let alias = constrained_alias.alias();
+                let pinned = options.pinned_aliases.get(alias);
+                let candidates = match pinned {
+                    Some(pinned) => context
+                        .entity(pinned)
+                        .filter(|entity| constrained_alias.is_satisfied_by(entity))
+                        .map(|entity| entity.id())
+                        .into_iter()
+                        .collect_vec(),
+                    None => candidates_of(constrained_alias),
+                };
+                let optional = constrained_alias.optional && pinned.is_none();
+                (constrained_alias, candidates, optional)
+            })
+            .collect_vec();
+        // stable sort, ties keep their declaration order
+        by_candidate_count.sort_by_key(|(_, candidates, optional)| (*optional, candidates.len()));
+        let (aliases, candidates, optional): (Vec<_>, Vec<_>, Vec<_>) =
+            by_candidate_count.into_iter().multiunzip();
+
+        // pinning an alias this story doesn't declare rules it out
+        let mut exhausted = options.pinned_aliases.iter().any(|(pinned, _)| {
+            !constrained_aliases
+                .iter()
+                .any(|constrained_alias| constrained_alias.alias() == pinned)
+        });
+
+        let mut focus = vec![];
+        let mut group_focus = vec![];
+        for &entity in options.focus.iter().unique() {
+            let last_depth = candidates
+                .iter()
+                .rposition(|candidates| candidates.contains(&entity));
+            let in_group = groups
+                .iter()
+                .any(|group| group.candidates.contains(&entity));
+            match (last_depth, in_group) {
+                (_, true) => group_focus.push(entity),
+                (Some(last_depth), false) => focus.push((entity, last_depth)),
+                (None, false) => exhausted = true,
+            }
+        }
+
+        let mut checks = aliases.iter().map(|_| Vec::default()).collect_vec();
+        let mut group_relations = vec![];
+        for relation in relations {
+            let depth_of = |alias: &Alias| aliases.iter().position(|bound| bound.alias() == alias);
+            let is_group = |alias: &Alias| groups.iter().any(|group| group.alias.alias() == alias);
+            match (depth_of(&relation.me), depth_of(&relation.other)) {
+                (Some(me), Some(other)) => {
+                    checks[me.max(other)].push(RelationCheck {
+                        me,
+                        other,
+                        relation,
+                    });
+                }
+                (me, other)
+                    if (me.is_some() || is_group(&relation.me))
+                        && (other.is_some() || is_group(&relation.other)) =>
+                {
+                    group_relations.push(relation)
+                }
+                // a relation on an undeclared alias can never be satisfied
+                _ => exhausted = true,
+            }
+        }
+
+        Self {
+            context,
+            cursors: vec![0; aliases.len()],
+            completed: vec![false; aliases.len()],
+            bound: Vec::with_capacity(aliases.len()),
+            aliases,
+            candidates,
+            checks,
+            focus,
+            optional,
+            groups,
+            group_relations,
+            group_focus,
+            exhausted,
+        }
+    }
+
+    // binds the group aliases once every other alias is bound, `None` if a group is out of range
+    fn bind_groups(&self) -> Option<AliasMap> {
+        let mut alias_map = self.alias_map();
+        if self.groups.is_empty() {
+            return Some(alias_map);
+        }
+
+        let mut taken = self
+            .aliases
+            .iter()
+            .zip(&self.bound)
+            .filter_map(|(&alias, &entity)| entity.map(|entity| (entity, alias)))
+            .collect_vec();
+        for group in &self.groups {
+            let size = group.alias.group.clone().unwrap_or_default();
+            let mut members = group
+                .candidates
+                .iter()
+                .copied()
+                .filter(|&entity| {
+                    !taken.iter().any(|&(taken, alias)| {
+                        taken == entity && !group.alias.may_coincide_with(alias)
+                    })
+                })
+                .collect_vec();
+            // the pinned entity and unseated focus entities take their seats first
+            let seats_first = |entity: &EntityId| {
+                group.pinned == Some(*entity)
+                    || (self.group_focus.contains(entity)
+                        && !taken.iter().any(|(taken, _)| taken == entity))
+            };
+            members.sort_by_key(|entity| !seats_first(entity));
+            members.truncate(size.end.saturating_sub(1));
+            members.sort();
+            let misses_pinned = group
+                .pinned
+                .is_some_and(|pinned| !members.contains(&pinned));
+            if !size.contains(&members.len()) || misses_pinned {
+                return None;
+            }
+
+            taken.extend(members.iter().map(|&entity| (entity, group.alias)));
+            alias_map.associate_group(group.alias.alias().clone(), members);
+        }
+
+        let misses_focus = self.group_focus.iter().any(|&focus| {
+            !self.bound.contains(&Some(focus))
+                && !self.groups.iter().any(|group| {
+                    alias_map
+                        .group(group.alias.alias())
+                        .unwrap()
+                        .contains(&focus)
+                })
+        });
+        let satisfied = !misses_focus
+            && self
+                .group_relations
+                .iter()
+                .all(|relation| relation.is_satisfied_in(self.context, &alias_map));
+        satisfied.then_some(alias_map)
+    }
+
+    // `None` leaves an optional alias unbound
+    fn accepts(&self, depth: usize, entity: Option<EntityId>) -> bool {
+        // two aliases never bind the same entity, unless allowed to coincide
+        let alias = self.aliases[depth];
+        if let Some(entity) = entity {
+            let taken = self.bound.iter().enumerate().any(|(index, &bound)| {
+                bound == Some(entity) && !alias.may_coincide_with(self.aliases[index])
+            });
+            if taken {
+                return false;
+            }
+        }
+
+        // past its last possible depth, a focus entity must already be bound
+        let misses_focus = self.focus.iter().any(|&(focus, last_depth)| {
+            depth >= last_depth && entity != Some(focus) && !self.bound.contains(&Some(focus))
+        });
+        if misses_focus {
+            return false;
+        }
+
+        let entity_at = |index: usize| {
+            if index == depth {
+                entity
+            } else {
+                self.bound[index]
+            }
+        };
+        let default_props = PropertyMap::default();
+        self.checks[depth].iter().all(|check| {
+            // relations don't apply to unbound optional aliases
+            let (Some(me), Some(other)) = (entity_at(check.me), entity_at(check.other)) else {
+                return true;
+            };
+            let relation_properties = self.context.relation(me, other).unwrap_or(&default_props);
+            check.relation.is_satisfied_by(relation_properties)
+        })
+    }
+
+    fn alias_map(&self) -> AliasMap {
+        let mut alias_map = AliasMap::default();
+        for (alias, &entity) in self.aliases.iter().zip(&self.bound) {
+            match entity {
+                Some(entity) => alias_map.associate(alias.alias().clone(), entity),
+                None => alias_map.leave_unbound(alias.alias().clone()),
+            }
+        }
+        alias_map
+    }
+}
+
+impl<'a> Iterator for AliasBindings<'a> {
+    type Item = AliasMap;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.exhausted {
+            return None;
+        }
+
+        if self.aliases.is_empty() {
+            self.exhausted = true;
+            return self.bind_groups();
+        }
+
+        let mut depth = self.bound.len();
+        // resume right after the last yielded binding
+        if depth == self.aliases.len() {
+            depth -= 1;
+            self.bound.pop();
+            self.cursors[depth] += 1;
+        }
+
+        loop {
+            let cursor = self.cursors[depth];
+            let slots = self.candidates[depth].len() + usize::from(self.optional[depth]);
+            if cursor >= slots {
+                // candidates exhausted at this depth, backtrack
+                self.cursors[depth] = 0;
+                self.completed[depth] = false;
+                if depth == 0 {
+                    self.exhausted = true;
+                    return None;
+                }
+                depth -= 1;
+                self.bound.pop();
+                self.cursors[depth] += 1;
+                continue;
+            }
+
+            // the extra slot of an optional alias leaves it unbound, if no candidate made it
+            let entity = self.candidates[depth].get(cursor).copied();
+            if (entity.is_none() && self.completed[depth]) || !self.accepts(depth, entity) {
+                self.cursors[depth] += 1;
+                continue;
+            }
+
+            self.bound.push(entity);
+            if self.bound.len() == self.aliases.len() {
+                if let Some(alias_map) = self.bind_groups() {
+                    self.completed.fill(true);
+                    return Some(alias_map);
+                }
+                self.bound.pop();
+                self.cursors[depth] += 1;
+                continue;
+            }
+            depth += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use itertools::Itertools;
+
+    use crate::{
+        constraint::{AliasRelation, Quantifier},
+        prelude::{Constraint, Entity, NarrativeWorld, QueryOptions},
+        scenario_action::ConstrainedAlias,
+    };
+
+    use super::AliasBindings;
+
+    fn villagers(count: usize) -> NarrativeWorld {
+        NarrativeWorld::new()
+            .with_entities((0..count).map(|id| Entity::new(id).with("villager", "")))
+    }
+
+    #[test]
+    fn no_aliases_yield_a_single_empty_binding() {
+        let context = villagers(3);
+        let bindings =
+            AliasBindings::new(&[], [], &context, &QueryOptions::default()).collect_vec();
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0].size(), 0);
+    }
+
+    #[test]
+    fn aliases_never_bind_the_same_entity() {
+        let context = villagers(4);
+        let aliases = ["a", "b", "c", "d"]
+            .map(|alias| ConstrainedAlias::new(alias, [Constraint::has("villager")]));
+
+        let bindings =
+            AliasBindings::new(&aliases, [], &context, &QueryOptions::default()).collect_vec();
+
+        // 4! ways to assign four villagers to four aliases
+        assert_eq!(bindings.len(), 24);
+        assert!(bindings.iter().all(|binding| ["a", "b", "c", "d"]
+            .map(|alias| binding.get(alias).unwrap())
+            .iter()
+            .all_unique()));
+    }
+
+    #[test]
+    fn an_alias_without_candidates_yields_nothing() {
+        let context = villagers(10);
+        let aliases = [
+            ConstrainedAlias::new("villager", [Constraint::has("villager")]),
+            ConstrainedAlias::new("king", [Constraint::has("crown")]),
+        ];
+
+        assert_eq!(
+            AliasBindings::new(&aliases, [], &context, &QueryOptions::default()).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn relations_are_checked_while_binding() {
+        let context = villagers(5)
+            .with_relation(0, 3, "sibling", "")
+            .with_relation(4, 1, "sibling", "");
+        let aliases = [
+            ConstrainedAlias::new("older", [Constraint::has("villager")]),
+            ConstrainedAlias::new("younger", [Constraint::has("villager")]),
+        ];
+        let relations = [AliasRelation::new(
+            "older",
+            "younger",
+            [Constraint::has("sibling")],
+        )];
+
+        let bindings = AliasBindings::new(&aliases, &relations, &context, &QueryOptions::default())
+            .map(|binding| {
+                (
+                    binding.get("older").unwrap(),
+                    binding.get("younger").unwrap(),
+                )
+            })
+            .collect_vec();
+
+        assert_eq!(bindings, [(0, 3), (4, 1)]);
+    }
+
+    #[test]
+    fn a_relation_on_an_undeclared_alias_yields_nothing() {
+        let context = villagers(2);
+        let aliases = [ConstrainedAlias::new("villager", [])];
+        let relations = [AliasRelation::new("villager", "ghost", [])];
+
+        assert_eq!(
+            AliasBindings::new(&aliases, &relations, &context, &QueryOptions::default()).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn pinned_aliases_only_bind_their_entity() {
+        let context = villagers(5);
+        let aliases = [
+            ConstrainedAlias::new("host", [Constraint::has("villager")]),
+            ConstrainedAlias::new("guest", [Constraint::has("villager")]),
+        ];
+
+        let options = QueryOptions::new().with_pinned_alias("guest", 3);
+        let bindings = AliasBindings::new(&aliases, [], &context, &options).collect_vec();
+        assert_eq!(bindings.len(), 4);
+        assert!(bindings
+            .iter()
+            .all(|binding| binding.get("guest").unwrap() == 3));
+
+        let options = QueryOptions::new().with_pinned_alias("guest", 42);
+        assert_eq!(
+            AliasBindings::new(&aliases, [], &context, &options).count(),
+            0
+        );
+
+        let options = QueryOptions::new().with_pinned_alias("ghost", 3);
+        assert_eq!(
+            AliasBindings::new(&aliases, [], &context, &options).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn a_focus_entity_is_bound_to_some_alias() {
+        let context = villagers(5);
+        let aliases = [
+            ConstrainedAlias::new("host", [Constraint::has("villager")]),
+            ConstrainedAlias::new("guest", [Constraint::has("villager")]),
+        ];
+
+        let options = QueryOptions::new().with_focus(2);
+        let bindings = AliasBindings::new(&aliases, [], &context, &options).collect_vec();
+        // 2 hosts 4 guests, or 4 hosts 2 guests
+        assert_eq!(bindings.len(), 8);
+        assert!(
+            bindings
+                .iter()
+                .all(|binding| binding.get("host").unwrap() == 2
+                    || binding.get("guest").unwrap() == 2)
+        );
+
+        let options = QueryOptions::new().with_focus(1).with_focus(2);
+        assert_eq!(
+            AliasBindings::new(&aliases, [], &context, &options).count(),
+            2
+        );
+
+        let options = QueryOptions::new().with_focus(42);
+        assert_eq!(
+            AliasBindings::new(&aliases, [], &context, &options).count(),
+            0
+        );
+    }
+
+    fn optional(
+        alias: &str,
+        constraints: impl IntoIterator<Item = Constraint>,
+    ) -> ConstrainedAlias {
+        ConstrainedAlias {
+            optional: true,
+            ..ConstrainedAlias::new(alias, constraints)
+        }
+    }
+
+    #[test]
+    fn optional_aliases_are_bound_whenever_possible() {
+        let context = villagers(2).with_entity(Entity::new(2).with("guard", ""));
+        let aliases = [
+            optional("witness", [Constraint::has("guard")]),
+            ConstrainedAlias::new("thief", [Constraint::has("villager")]),
+        ];
+        let bindings = AliasBindings::new(&aliases, [], &context, &QueryOptions::default())
+            .map(|binding| (binding.get("thief").unwrap(), binding.get("witness")))
+            .collect_vec();
+        assert_eq!(bindings, [(0, Some(2)), (1, Some(2))]);
+
+        // the only guard is the thief
+        let aliases = [
+            optional("witness", [Constraint::has("guard")]),
+            ConstrainedAlias::new("thief", [Constraint::has("guard")]),
+        ];
+        let bindings =
+            AliasBindings::new(&aliases, [], &context, &QueryOptions::default()).collect_vec();
+        assert_eq!(bindings.len(), 1);
+        assert!(bindings[0].is_unbound("witness"));
+        assert_eq!(bindings[0].get("thief"), Some(2));
+    }
+
+    #[test]
+    fn relations_on_unbound_optional_aliases_do_not_apply() {
+        let context = villagers(3).with_relation(1, 0, "sibling", "");
+        let aliases = [
+            ConstrainedAlias::new("thief", [Constraint::has("villager")]),
+            optional("accomplice", [Constraint::has("villager")]),
+        ];
+        let relations = [AliasRelation::new(
+            "accomplice",
+            "thief",
+            [Constraint::has("sibling")],
+        )];
+        let bindings = AliasBindings::new(&aliases, &relations, &context, &QueryOptions::default())
+            .map(|binding| (binding.get("thief").unwrap(), binding.get("accomplice")))
+            .collect_vec();
+        assert_eq!(bindings, [(0, Some(1)), (1, None), (2, None)]);
+    }
+
+    #[test]
+    fn coinciding_aliases_may_bind_the_same_entity() {
+        let context = villagers(2);
+        let mut buyer = ConstrainedAlias::new("buyer", [Constraint::has("villager")]);
+        buyer.may_coincide_with.push("payer".into());
+        let aliases = [
+            buyer,
+            ConstrainedAlias::new("payer", [Constraint::has("villager")]),
+            ConstrainedAlias::new("seller", [Constraint::has("villager")]),
+        ];
+        let bindings = AliasBindings::new(&aliases, [], &context, &QueryOptions::default())
+            .map(|binding| {
+                (
+                    binding.get("buyer").unwrap(),
+                    binding.get("payer").unwrap(),
+                    binding.get("seller").unwrap(),
+                )
+            })
+            .collect_vec();
+        assert_eq!(bindings, [(0, 0, 1), (1, 1, 0)]);
+    }
+
+    fn group(alias: &str, size: std::ops::Range<usize>) -> ConstrainedAlias {
+        ConstrainedAlias {
+            group: Some(size),
+            ..ConstrainedAlias::new(alias, [Constraint::has("villager")])
+        }
+    }
+
+    #[test]
+    fn group_aliases_bind_the_remaining_candidates() {
+        let context = villagers(5);
+        let aliases = [
+            group("crowd", 2..4),
+            ConstrainedAlias::new("mayor", [Constraint::has("villager")]),
+        ];
+        let bindings = AliasBindings::new(&aliases, [], &context, &QueryOptions::default())
+            .map(|binding| {
+                (
+                    binding.get("mayor").unwrap(),
+                    binding.group("crowd").unwrap().to_vec(),
+                )
+            })
+            .collect_vec();
+        // at most 3 villagers gather around the mayor, by increasing id
+        assert_eq!(
+            bindings,
+            [
+                (0, vec![1, 2, 3]),
+                (1, vec![0, 2, 3]),
+                (2, vec![0, 1, 3]),
+                (3, vec![0, 1, 2]),
+                (4, vec![0, 1, 2]),
+            ]
+        );
+
+        let aliases = [group("crowd", 5..10), ConstrainedAlias::new("mayor", [])];
+        assert_eq!(
+            AliasBindings::new(&aliases, [], &villagers(5), &QueryOptions::default()).count(),
+            0
+        );
+
+        // the focus villager takes a seat before the crowd is full
+        let aliases = [group("crowd", 0..3)];
+        let crowds = |options: &QueryOptions| {
+            AliasBindings::new(&aliases, [], &context, options)
+                .map(|binding| binding.group("crowd").unwrap().to_vec())
+                .collect_vec()
+        };
+        assert_eq!(crowds(&QueryOptions::new().with_focus(1)), [vec![0, 1]]);
+        assert_eq!(crowds(&QueryOptions::new().with_focus(4)), [vec![0, 4]]);
+        let options = QueryOptions::new().with_focus(3).with_focus(4);
+        assert_eq!(crowds(&options), [vec![3, 4]]);
+        let options = QueryOptions::new().with_pinned_alias("crowd", 3);
+        assert_eq!(crowds(&options), [vec![0, 3]]);
+    }
+
+    #[test]
+    fn a_focus_entity_may_be_held_by_a_group() {
+        let context = villagers(3).with_entity(Entity::new(3).with("mayor", ""));
+        let aliases = [
+            ConstrainedAlias::new("speaker", [Constraint::has("villager")]),
+            group("crowd", 0..usize::MAX),
+        ];
+        let options = QueryOptions::new().with_focus(2);
+        let bindings = AliasBindings::new(&aliases, [], &context, &options)
+            .map(|binding| {
+                (
+                    binding.get("speaker").unwrap(),
+                    binding.group("crowd").unwrap().to_vec(),
+                )
+            })
+            .collect_vec();
+        // the focus villager speaks, or listens among the crowd
+        assert_eq!(
+            bindings,
+            [(0, vec![1, 2]), (1, vec![0, 2]), (2, vec![0, 1])]
+        );
+
+        // only the crowd can hold the mayor
+        let aliases = [
+            ConstrainedAlias::new("speaker", [Constraint::has("villager")]),
+            ConstrainedAlias {
+                group: Some(0..usize::MAX),
+                ..ConstrainedAlias::new("crowd", [])
+            },
+        ];
+        let options = QueryOptions::new().with_focus(3);
+        assert_eq!(
+            AliasBindings::new(&aliases, [], &context, &options).count(),
+            3
+        );
+    }
+
+    #[test]
+    fn relations_on_group_aliases_are_quantified() {
+        let context = villagers(4)
+            .with_relation(1, 0, "fond", "")
+            .with_relation(2, 0, "fond", "");
+        let aliases = [
+            ConstrainedAlias::new("mayor", [Constraint::has("villager")]),
+            group("crowd", 0..usize::MAX),
+        ];
+        let relation = AliasRelation::new("crowd", "mayor", [Constraint::has("fond")]);
+
+        let relations = [relation.clone()];
+        let mayors = AliasBindings::new(&aliases, &relations, &context, &QueryOptions::default())
+            .map(|binding| binding.get("mayor").unwrap())
+            .collect_vec();
+        assert!(mayors.is_empty());
+
+        let relations = [relation.with_quantifier(Quantifier::Any)];
+        let mayors = AliasBindings::new(&aliases, &relations, &context, &QueryOptions::default())
+            .map(|binding| binding.get("mayor").unwrap())
+            .collect_vec();
+        assert_eq!(mayors, [0]);
+    }
+}
@@ -1,10 +1,12 @@
-use std::ops::Range;
+use std::{fmt, ops::Range};
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    narrative_world::NarrativeWorld,
     property::{Property, PropertyMap, PropertyName},
     scenario_action::Alias,
+    scenario_graph::AliasMap,
     Int, Real,
 };
 
@@ -73,11 +75,39 @@ impl Constraint {
     }
 }
 
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::Has(prop_name) => write!(f, "{}", prop_name),
+            Constraint::HasNot(prop_name) => write!(f, "!{}", prop_name),
+            Constraint::Equals(prop_name, property) => write!(f, "{} = {}", prop_name, property),
+            Constraint::IsInRange(prop_name, range) => {
+                write!(f, "{} in {}..{}", prop_name, range.start, range.end)
+            }
+            Constraint::IsInRangeFloat(prop_name, range) => {
+                write!(f, "{} in {}..{}", prop_name, range.start, range.end)
+            }
+        }
+    }
+}
+
+// How a relation constraint applies to group aliases, which bind several entities
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum Quantifier {
+    // every pair of entities must be related, vacuously true for an empty group
+    #[default]
+    All,
+    // at least one pair of entities must be related
+    Any,
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct AliasRelation {
     pub me: Alias,
     pub other: Alias,
     pub constraints: Vec<Constraint>,
+    #[serde(default)]
+    pub quantifier: Quantifier,
 }
 
 impl AliasRelation {
@@ -90,12 +120,60 @@ impl AliasRelation {
             me: me.into(),
             other: other.into(),
             constraints: Vec::from_iter(constraints),
+            quantifier: Quantifier::default(),
         }
     }
 
+    pub fn with_quantifier(mut self, quantifier: Quantifier) -> Self {
+        self.quantifier = quantifier;
+        self
+    }
+
     pub fn is_satisfied_by(&self, properties: &PropertyMap) -> bool {
         self.constraints
             .iter()
             .all(|constraint| constraint.is_satisfied_by(properties))
     }
+
+    // Checks the relation between the entities bound to both aliases, failing if either is missing.
+    // A relation involving an optional alias left unbound doesn't apply.
+    // Group aliases are checked pairwise, according to the quantifier.
+    pub(crate) fn is_satisfied_in(&self, context: &NarrativeWorld, alias_map: &AliasMap) -> bool {
+        if alias_map.is_unbound(&self.me) || alias_map.is_unbound(&self.other) {
+            return true;
+        }
+        let (Some(mes), Some(others)) =
+            (alias_map.members(&self.me), alias_map.members(&self.other))
+        else {
+            return false;
+        };
+
+        let default_props = PropertyMap::default();
+        let mut pairs = mes
+            .iter()
+            .flat_map(|&me_id| others.iter().map(move |&other_id| (me_id, other_id)));
+        let is_related = |(me_id, other_id)| {
+            let relation_properties = context.relation(me_id, other_id).unwrap_or(&default_props);
+            self.is_satisfied_by(relation_properties)
+        };
+        match self.quantifier {
+            Quantifier::All => pairs.all(is_related),
+            Quantifier::Any => pairs.any(is_related),
+        }
+    }
+}
+
+// such as `guard -> thief: hostile, !bribed`, or `any villager -> mayor: likes` for `Quantifier::Any`
+impl fmt::Display for AliasRelation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.quantifier == Quantifier::Any {
+            write!(f, "any ")?;
+        }
+        write!(f, "{} -> {}:", self.me, self.other)?;
+        for (index, constraint) in self.constraints.iter().enumerate() {
+            let separator = if index == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, constraint)?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,378 @@
+use std::{
+    error, fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use itertools::Itertools;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    line::LineName,
+    scenario_graph::{EditError, NodeName, ScenarioActionId, ScenarioGraph, StoryId},
+    schema::UndefinedName,
+    tag_expr::TagExprError,
+    template::TemplateError,
+};
+
+// Where an error was found, as far as known: the story, the node along with its name if named, and
+// the byte span within the text of the node. Within a line, the span is relative to the text of
+// the variant rendered, `line` being its name and index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    pub story: Option<StoryId>,
+    pub node: Option<ScenarioActionId>,
+    pub node_name: Option<NodeName>,
+    pub line: Option<(LineName, usize)>,
+    pub span: Option<Range<usize>>,
+}
+
+impl Location {
+    pub(crate) fn span(span: Range<usize>) -> Self {
+        Self {
+            span: Some(span),
+            ..Default::default()
+        }
+    }
+
+    fn is_known(&self) -> bool {
+        self.story.is_some() || self.node.is_some() || self.line.is_some() || self.span.is_some()
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some(story) = &self.story {
+            parts.push(format!(r#"story "{}""#, story));
+        }
+        match (self.node, &self.node_name) {
+            (Some(node), Some(name)) => parts.push(format!(r#"node "{}" ({})"#, name, node)),
+            (Some(node), None) => parts.push(format!("node {}", node)),
+            _ => {}
+        }
+        if let Some((line, variant)) = &self.line {
+            parts.push(format!(r#"line "{}" variant {}"#, line, variant));
+        }
+        if let Some(span) = &self.span {
+            parts.push(format!("at {}..{}", span.start, span.end));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+// Every error raconteur reports.
+// Graph edits and tag expressions keep returning their own `EditError` and `TagExprError`, so that
+// editors can match the exact failure of a call which can only fail in those ways; both convert
+// into `Error` with `?`, wrapped by `Error::Graph` and `Error::TagExpr`.
+#[derive(Debug)]
+pub enum Error {
+    // a content file couldn't be read
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    // Content couldn't be loaded, such as malformed RON. `line` and `column` start at 1.
+    Parse {
+        path: Option<PathBuf>,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    // an invalid edit of a graph
+    Graph {
+        story: Option<StoryId>,
+        error: EditError,
+    },
+    // a malformed template, such as a directive built from a string
+    Template {
+        location: Box<Location>,
+        error: TemplateError,
+    },
+    // a missing alias, entity or property, or a line without any variant for the bound entities
+    Alias {
+        location: Box<Location>,
+        message: String,
+    },
+    // an unknown filter, or a filter or grammar choice failing on a value
+    Filter {
+        location: Box<Location>,
+        filter: String,
+        message: String,
+    },
+    // no binding of the aliases of a story satisfies its constraints
+    ConstraintsNotSatisfied {
+        story: StoryId,
+    },
+    TagExpr(TagExprError),
+    // a story inserted under the id of another story
+    DuplicateStory {
+        story: StoryId,
+    },
+    // a choice offered before the scenario moved on, such as within a sub-scenario which has since
+    // returned
+    StaleChoice {
+        story: StoryId,
+        node: ScenarioActionId,
+    },
+    // names a schema uses without defining them
+    Schema {
+        undefined: Vec<UndefinedName>,
+    },
+}
+
+impl Error {
+    pub(crate) fn alias<S>(message: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Error::Alias {
+            location: Box::default(),
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn filter<F, S>(filter: F, message: S) -> Self
+    where
+        F: Into<String>,
+        S: Into<String>,
+    {
+        Error::Filter {
+            location: Box::default(),
+            filter: filter.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Error::Template { location, .. }
+            | Error::Alias { location, .. }
+            | Error::Filter { location, .. } => Some(location.as_ref()),
+            _ => None,
+        }
+    }
+
+    // fills in the parts of the location which aren't known yet
+    pub(crate) fn at(
+        mut self,
+        story: Option<&str>,
+        node: Option<ScenarioActionId>,
+        span: Option<Range<usize>>,
+    ) -> Self {
+        if let Some(location) = self.location_mut() {
+            location.story = location.story.take().or(story.map(String::from));
+            location.node = location.node.or(node);
+            location.span = location.span.take().or(span);
+        }
+        if let Error::Graph {
+            story: error_story, ..
+        } = &mut self
+        {
+            *error_story = error_story.take().or(story.map(String::from));
+        }
+        self
+    }
+
+    // fills in the story, and the node along with its name, unless known yet
+    pub(crate) fn in_node(self, graph: &ScenarioGraph, node: ScenarioActionId) -> Self {
+        let mut error = self.at(Some(graph.id()), Some(node), None);
+        if let Some(location) = error.location_mut() {
+            if location.node == Some(node) && location.node_name.is_none() {
+                location.node_name = graph.node_name(node).map(String::from);
+            }
+        }
+        error
+    }
+
+    // the span of an error found while rendering a line is relative to the text of its variant
+    pub(crate) fn in_line(mut self, line: &str, variant: usize) -> Self {
+        if let Some(location) = self.location_mut() {
+            location.line = location.line.take().or(Some((line.into(), variant)));
+        }
+        self
+    }
+
+    fn location_mut(&mut self) -> Option<&mut Location> {
+        match self {
+            Error::Template { location, .. }
+            | Error::Alias { location, .. }
+            | Error::Filter { location, .. } => Some(location.as_mut()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            Error::Parse {
+                path,
+                line,
+                column,
+                message,
+            } => {
+                if let Some(path) = path {
+                    write!(f, "{}:", path.display())?;
+                }
+                write!(f, "{}:{}: {}", line, column, message)
+            }
+            Error::Graph { story, error } => match story {
+                Some(story) => write!(f, r#"Graph error in story "{}": {}"#, story, error),
+                None => write!(f, "Graph error: {}", error),
+            },
+            Error::Template { error, .. } => write!(f, "Malformed template: {}", error.message),
+            Error::Alias { message, .. } => write!(f, "Alias error: {}", message),
+            Error::Filter {
+                filter, message, ..
+            } => write!(f, r#"Filter error: "{}" {}"#, filter, message),
+            Error::ConstraintsNotSatisfied { story } => {
+                write!(f, r#"Constraints of story "{}" not satisfied"#, story)
+            }
+            Error::TagExpr(error) => write!(f, "{}", error),
+            Error::DuplicateStory { story } => {
+                write!(f, r#"Story "{}" is already inserted"#, story)
+            }
+            Error::StaleChoice { story, node } => write!(
+                f,
+                r#"Choice of node {} in story "{}" is no longer offered"#,
+                node, story
+            ),
+            Error::Schema { undefined } => write!(
+                f,
+                "Schema error: {}",
+                undefined
+                    .iter()
+                    .map(|undefined| format!(
+                        r#"undefined "{}" in "{}""#,
+                        undefined.name, undefined.used_by
+                    ))
+                    .join(", ")
+            ),
+        }?;
+        match self.location() {
+            Some(location) if location.is_known() => write!(f, " ({})", location),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { error, .. } => Some(error),
+            Error::Graph { error, .. } => Some(error),
+            Error::Template { error, .. } => Some(error),
+            Error::TagExpr(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<EditError> for Error {
+    fn from(error: EditError) -> Self {
+        Error::Graph { story: None, error }
+    }
+}
+
+impl From<TemplateError> for Error {
+    fn from(error: TemplateError) -> Self {
+        Error::Template {
+            location: Box::new(Location::span(error.span.clone())),
+            error,
+        }
+    }
+}
+
+impl From<TagExprError> for Error {
+    fn from(error: TagExprError) -> Self {
+        Error::TagExpr(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for Error {
+    fn from(error: ron::error::SpannedError) -> Self {
+        Error::Parse {
+            path: None,
+            line: error.position.line,
+            column: error.position.col,
+            message: error.code.to_string(),
+        }
+    }
+}
+
+// reads content stored as RON, such as a story or a string table
+pub(crate) fn from_ron<T>(content: &str) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    Ok(ron::from_str(content)?)
+}
+
+pub(crate) fn load_ron<T>(path: &Path) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    let content = fs::read_to_string(path).map_err(|error| Error::Io {
+        path: path.into(),
+        error,
+    })?;
+    from_ron(&content).map_err(|error| match error {
+        Error::Parse {
+            line,
+            column,
+            message,
+            ..
+        } => Error::Parse {
+            path: Some(path.into()),
+            line,
+            column,
+            message,
+        },
+        error => error,
+    })
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::prelude::{ScenarioGraph, Template};
+
+    #[test]
+    fn errors_point_to_their_source() {
+        let error = ScenarioGraph::load("missing.ron").err().unwrap();
+        assert!(matches!(error, Error::Io { .. }));
+        assert!(error.to_string().starts_with("missing.ron: "));
+
+        let error = from_ron::<Vec<Template>>("[\n  \"Hi {a.b.c}!\",\n  42,\n]").unwrap_err();
+        assert!(matches!(error, Error::Parse { line: 3, .. }), "{:?}", error);
+
+        let error = Error::alias(r#"missing alias "npc""#).at(
+            Some("market"),
+            Some(ScenarioActionId::default()),
+            Some(3..8),
+        );
+        assert_eq!(
+            error.to_string(),
+            r#"Alias error: missing alias "npc" (story "market", node #0, at 3..8)"#
+        );
+
+        let error = Error::Schema {
+            undefined: vec![
+                UndefinedName {
+                    used_by: "business".into(),
+                    name: "product".into(),
+                },
+                UndefinedName {
+                    used_by: "sale".into(),
+                    name: "what".into(),
+                },
+            ],
+        };
+        assert_eq!(
+            error.to_string(),
+            r#"Schema error: undefined "product" in "business", undefined "what" in "sale""#
+        );
+    }
+}
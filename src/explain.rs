@@ -0,0 +1,619 @@
+use std::{collections::HashMap, fmt, ops::Range};
+
+use itertools::Itertools;
+
+use crate::{
+    constraint::{AliasRelation, Constraint},
+    entity::EntityId,
+    narrative_world::NarrativeWorld,
+    property::{PropertyMap, PropertyName},
+    query::QueryOptions,
+    scenario_action::{Alias, ConstrainedAlias, ScenarioAction},
+    scenario_graph::{
+        is_sub_scenario_playable, AliasMap, ScenarioActionId, ScenarioGraph, StoryId, StoryLibrary,
+        BINDING_BATCH_SIZE,
+    },
+};
+
+// entities listed per alias, closest first
+const NEAR_MISSES: usize = 3;
+
+// paths from the start to a leaf, enumerated depth first, are listed up to this number
+const MAX_PATHS: usize = 32;
+
+// Why a story does or doesn't match a world, as reported by `ScenarioGraph::explain` and
+// `Raconteur::explain`. Its `Display` is a report meant for writers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub story: StoryId,
+    // whether a query returns the story with at least one binding
+    pub matched: bool,
+    // the world excludes the story, or its tags don't match those of the query options
+    pub excluded: bool,
+    // the aliases bound when the story is queried, local aliases of the start node included
+    pub aliases: Vec<AliasReport>,
+    // relation constraints of the start node, checked as the aliases are bound
+    pub relations: Vec<RelationReport>,
+    // bindings satisfying the alias constraints and the start node's relations, counted up to 4096
+    pub bindings: usize,
+    // paths from the start to a leaf through strong edges, up to 32
+    pub paths: Vec<PathReport>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AliasReport {
+    pub alias: Alias,
+    pub optional: bool,
+    pub group: Option<Range<usize>>,
+    // entities satisfying every constraint of the alias
+    pub candidates: usize,
+    // the entities failing the fewest constraints, closest first
+    pub near_misses: Vec<NearMiss>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NearMiss {
+    pub entity: EntityId,
+    pub failing: Vec<Constraint>,
+    // exclusory properties of the entity which the alias doesn't ask for
+    pub excluded_by: Vec<PropertyName>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelationReport {
+    pub relation: AliasRelation,
+    // pairs of distinct candidates of both aliases, and how many of them are related as required
+    pub pairs: usize,
+    pub satisfied: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathReport {
+    pub nodes: Vec<ScenarioActionId>,
+    // how many of the bindings can play the path through
+    pub bindings: usize,
+    pub failures: Vec<PathFailure>,
+}
+
+// where a constraint along a path is declared
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Site {
+    Node(ScenarioActionId),
+    Edge {
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+    },
+}
+
+// A constraint along a path which rules out some or all of the bindings
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathFailure {
+    World {
+        site: Site,
+        constraint: Constraint,
+    },
+    // satisfied by `satisfied` of the bindings
+    Relation {
+        site: Site,
+        relation: AliasRelation,
+        satisfied: usize,
+    },
+    // only checked by `Raconteur::explain`, which knows the stories called into
+    SubScenario {
+        node: ScenarioActionId,
+        story: StoryId,
+        playable: usize,
+    },
+}
+
+impl Explanation {
+    pub(crate) fn new(
+        graph: &ScenarioGraph,
+        context: &NarrativeWorld,
+        options: &QueryOptions,
+        library: Option<&dyn StoryLibrary>,
+    ) -> Self {
+        let excluded = !context.is_included(graph.id())
+            || options
+                .tags
+                .as_ref()
+                .is_some_and(|tags| !tags.is_satisfied_by(graph.tags()));
+
+        let start = graph.start();
+        let start_action = graph.get(start);
+        let constrained_aliases = graph
+            .aliases()
+            .iter()
+            .chain(
+                start_action
+                    .map(ScenarioAction::local_aliases)
+                    .unwrap_or_default(),
+            )
+            .collect_vec();
+        let candidates: HashMap<&str, Vec<EntityId>> = constrained_aliases
+            .iter()
+            .map(|constrained_alias| {
+                let candidates = context
+                    .entities()
+                    .filter(|entity| constrained_alias.is_satisfied_by(entity))
+                    .map(|entity| entity.id())
+                    .collect_vec();
+                (constrained_alias.alias().as_str(), candidates)
+            })
+            .collect();
+        let aliases = constrained_aliases
+            .iter()
+            .map(|constrained_alias| AliasReport::new(constrained_alias, &candidates, context))
+            .collect();
+        let relations = start_action
+            .map(|action| action.relation_constraints.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .map(|relation| RelationReport::new(relation, &candidates, context))
+            .collect();
+
+        let bindings = graph
+            .alias_bindings(context, options)
+            .take(BINDING_BATCH_SIZE)
+            .collect_vec();
+        let walker = PathWalker {
+            graph,
+            context,
+            library,
+            bindings: &bindings,
+            late_bound_aliases: graph.late_bound_aliases().into_iter().collect(),
+        };
+        let paths = leaf_paths(graph)
+            .into_iter()
+            .map(|nodes| walker.report(nodes))
+            .collect();
+
+        Self {
+            story: graph.id().to_string(),
+            matched: !excluded
+                && graph
+                    .valid_alias_bindings_in(context, options, library, 0)
+                    .next()
+                    .is_some(),
+            excluded,
+            aliases,
+            relations,
+            bindings: bindings.len(),
+            paths,
+        }
+    }
+}
+
+impl AliasReport {
+    fn new(
+        constrained_alias: &ConstrainedAlias,
+        candidates: &HashMap<&str, Vec<EntityId>>,
+        context: &NarrativeWorld,
+    ) -> Self {
+        let near_misses = context
+            .entities()
+            .map(|entity| NearMiss {
+                entity: entity.id(),
+                failing: constrained_alias
+                    .failing_constraints(entity)
+                    .cloned()
+                    .collect(),
+                excluded_by: constrained_alias
+                    .excluding_properties(entity)
+                    .cloned()
+                    .sorted()
+                    .collect(),
+            })
+            .filter(|near_miss| !near_miss.failing.is_empty() || !near_miss.excluded_by.is_empty())
+            .sorted_by_key(|near_miss| {
+                (
+                    near_miss.failing.len() + near_miss.excluded_by.len(),
+                    near_miss.entity,
+                )
+            })
+            .take(NEAR_MISSES)
+            .collect();
+        Self {
+            alias: constrained_alias.alias().clone(),
+            optional: constrained_alias.optional,
+            group: constrained_alias.group.clone(),
+            candidates: candidates[constrained_alias.alias().as_str()].len(),
+            near_misses,
+        }
+    }
+}
+
+impl RelationReport {
+    fn new(
+        relation: &AliasRelation,
+        candidates: &HashMap<&str, Vec<EntityId>>,
+        context: &NarrativeWorld,
+    ) -> Self {
+        let no_candidates = vec![];
+        let candidates_of =
+            |alias: &Alias| candidates.get(alias.as_str()).unwrap_or(&no_candidates);
+        let default_props = PropertyMap::default();
+        let pairs = candidates_of(&relation.me)
+            .iter()
+            .cartesian_product(candidates_of(&relation.other))
+            .filter(|(me, other)| me != other)
+            .collect_vec();
+        let satisfied = pairs
+            .iter()
+            .filter(|(&me, &other)| {
+                relation.is_satisfied_by(context.relation(me, other).unwrap_or(&default_props))
+            })
+            .count();
+        Self {
+            relation: relation.clone(),
+            pairs: pairs.len(),
+            satisfied,
+        }
+    }
+}
+
+// Paths from the start to a leaf through strong edges, leaving out dead ends which
+// `ScenarioGraph::validate` reports
+fn leaf_paths(graph: &ScenarioGraph) -> Vec<Vec<ScenarioActionId>> {
+    let start = graph.start();
+    if !graph.contains(start) {
+        return vec![];
+    }
+    let children = graph.edges().into_group_map_by(|&(from, _, _)| from);
+    let mut paths = vec![];
+    let mut stack = vec![vec![start]];
+    while let Some(path) = stack.pop() {
+        if paths.len() == MAX_PATHS {
+            break;
+        }
+        let last = *path.last().unwrap();
+        match children.get(&last) {
+            // pushed in reverse so that paths are listed in edge order
+            Some(edges) => stack.extend(edges.iter().rev().map(|&(_, to, _)| {
+                let mut path = path.clone();
+                path.push(to);
+                path
+            })),
+            None if graph.all_connections(last.into()).is_empty() => paths.push(path),
+            None => {}
+        }
+    }
+    paths
+}
+
+struct PathWalker<'a> {
+    graph: &'a ScenarioGraph,
+    context: &'a NarrativeWorld,
+    library: Option<&'a dyn StoryLibrary>,
+    bindings: &'a [AliasMap],
+    // relations on these can't be checked before the scenario is played, and are assumed satisfied
+    late_bound_aliases: Vec<&'a str>,
+}
+
+impl PathWalker<'_> {
+    fn report(&self, nodes: Vec<ScenarioActionId>) -> PathReport {
+        let mut failures = vec![];
+        // whether each binding can play the path so far
+        let mut playable = vec![true; self.bindings.len()];
+
+        for (index, &node) in nodes.iter().enumerate() {
+            let entering = index.checked_sub(1).and_then(|previous| {
+                let from = nodes[previous];
+                self.graph
+                    .edges()
+                    .find(|&(edge_from, to, _)| edge_from == from && to == node)
+                    .map(|(_, _, transition)| (from, transition))
+            });
+            if let Some((from, transition)) = entering {
+                let site = Site::Edge { from, to: node };
+                failures.extend(self.world_failures(site, &transition.world_constraints));
+                self.check_relations(
+                    site,
+                    &transition.relation_constraints,
+                    &mut playable,
+                    &mut failures,
+                );
+            }
+
+            let Some(action) = self.graph.get(node) else {
+                continue;
+            };
+            let site = Site::Node(node);
+            failures.extend(self.world_failures(site, &action.world_constraints));
+            self.check_relations(
+                site,
+                &action.relation_constraints,
+                &mut playable,
+                &mut failures,
+            );
+            if let (Some(sub_scenario), Some(_)) = (&action.sub_scenario, self.library) {
+                let mut count = 0;
+                for (binding, playable) in self.bindings.iter().zip(playable.iter_mut()) {
+                    if is_sub_scenario_playable(action, self.context, binding, self.library, 0) {
+                        count += 1;
+                    } else {
+                        *playable = false;
+                    }
+                }
+                if count < self.bindings.len() {
+                    failures.push(PathFailure::SubScenario {
+                        node,
+                        story: sub_scenario.story.clone(),
+                        playable: count,
+                    });
+                }
+            }
+        }
+
+        // world constraints rule out every binding
+        let world_failed = failures
+            .iter()
+            .any(|failure| matches!(failure, PathFailure::World { .. }));
+        let bindings = if world_failed {
+            0
+        } else {
+            playable.into_iter().filter(|&playable| playable).count()
+        };
+        PathReport {
+            nodes,
+            bindings,
+            failures,
+        }
+    }
+
+    fn world_failures<'c>(
+        &'c self,
+        site: Site,
+        constraints: &'c [Constraint],
+    ) -> impl Iterator<Item = PathFailure> + 'c {
+        constraints
+            .iter()
+            .filter(|constraint| !constraint.is_satisfied_by(self.context.properties()))
+            .map(move |constraint| PathFailure::World {
+                site,
+                constraint: constraint.clone(),
+            })
+    }
+
+    fn check_relations(
+        &self,
+        site: Site,
+        relations: &[AliasRelation],
+        playable: &mut [bool],
+        failures: &mut Vec<PathFailure>,
+    ) {
+        for relation in relations {
+            if self.late_bound_aliases.contains(&relation.me.as_str())
+                || self.late_bound_aliases.contains(&relation.other.as_str())
+            {
+                continue;
+            }
+            let mut satisfied = 0;
+            for (binding, playable) in self.bindings.iter().zip(playable.iter_mut()) {
+                if relation.is_satisfied_in(self.context, binding) {
+                    satisfied += 1;
+                } else {
+                    *playable = false;
+                }
+            }
+            if satisfied < self.bindings.len() {
+                failures.push(PathFailure::Relation {
+                    site,
+                    relation: relation.clone(),
+                    satisfied,
+                });
+            }
+        }
+    }
+}
+
+impl fmt::Display for Site {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Site::Node(node) => write!(f, "node {}", node),
+            Site::Edge { from, to } => write!(f, "edge {} -> {}", from, to),
+        }
+    }
+}
+
+impl fmt::Display for PathFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathFailure::World { site, constraint } => {
+                write!(f, "{}: world constraint {} fails", site, constraint)
+            }
+            PathFailure::Relation {
+                site,
+                relation,
+                satisfied,
+            } => write!(
+                f,
+                "{}: relation {} holds for {} bindings",
+                site, relation, satisfied
+            ),
+            PathFailure::SubScenario {
+                node,
+                story,
+                playable,
+            } => write!(
+                f,
+                r#"node {}: sub-scenario "{}" is playable with {} bindings"#,
+                node, story, playable
+            ),
+        }
+    }
+}
+
+// such as
+// story "market" doesn't match
+// alias "vendor": 0 candidates
+//   entity 3 fails job = merchant
+// 0 bindings
+// path #0 -> #1: 0 bindings
+//   node #1: world constraint weather = rain fails
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = if self.matched {
+            "matches"
+        } else {
+            "doesn't match"
+        };
+        writeln!(f, r#"story "{}" {}"#, self.story, verdict)?;
+        if self.excluded {
+            writeln!(f, "excluded by the world or the query tags")?;
+        }
+        for alias in &self.aliases {
+            let kind = match (&alias.group, alias.optional) {
+                (Some(size), _) => format!("group alias ({}..{})", size.start, size.end),
+                (None, true) => "optional alias".to_string(),
+                (None, false) => "alias".to_string(),
+            };
+            writeln!(
+                f,
+                r#"{} "{}": {} candidates"#,
+                kind, alias.alias, alias.candidates
+            )?;
+            for near_miss in &alias.near_misses {
+                let mut reasons = vec![];
+                if !near_miss.failing.is_empty() {
+                    reasons.push(format!("fails {}", near_miss.failing.iter().join(", ")));
+                }
+                if !near_miss.excluded_by.is_empty() {
+                    reasons.push(format!(
+                        "is excluded by {}",
+                        near_miss.excluded_by.iter().join(", ")
+                    ));
+                }
+                write!(f, "  entity {} {}", near_miss.entity, reasons.join(" and "))?;
+                writeln!(f)?;
+            }
+        }
+        for relation in &self.relations {
+            writeln!(
+                f,
+                "relation {}: {} of {} candidate pairs",
+                relation.relation, relation.satisfied, relation.pairs
+            )?;
+        }
+        writeln!(f, "{} bindings", self.bindings)?;
+        for path in &self.paths {
+            writeln!(
+                f,
+                "path {}: {} bindings",
+                path.nodes
+                    .iter()
+                    .map(ScenarioActionId::to_string)
+                    .join(" -> "),
+                path.bindings
+            )?;
+            for failure in &path.failures {
+                writeln!(f, "  {}", failure)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::prelude::{Entity, ScenarioAction, ScenarioGraph};
+
+    fn market() -> ScenarioGraph {
+        let mut graph = ScenarioGraph::new();
+        graph.set_id("market");
+        graph.add_alias(
+            "vendor",
+            [
+                Constraint::has("merchant"),
+                Constraint::equals("mood", "calm"),
+            ],
+        );
+        graph.add_alias("buyer", [Constraint::has("villager")]);
+        let start = graph.add(ScenarioAction::new());
+        let haggle =
+            graph.add(ScenarioAction::new().with_world_constraint(Constraint::has("market day")));
+        let chat = graph.add(ScenarioAction::new().with_relation_constraints(
+            "vendor",
+            "buyer",
+            [Constraint::has("friends")],
+        ));
+        graph.set_start_node(start);
+        graph.connect(start, haggle).unwrap();
+        graph.connect(start, chat).unwrap();
+        graph
+    }
+
+    #[test]
+    fn explanations_point_to_the_failing_constraints() {
+        let graph = market();
+        let context = NarrativeWorld::new().with_entities([
+            Entity::new(0).with("villager", ""),
+            Entity::new(1).with("merchant", "").with("mood", "angry"),
+            Entity::new(2)
+                .with("merchant", "")
+                .with("mood", "calm")
+                .with_exclusory("ghost", ""),
+        ]);
+
+        let explanation = graph.explain(&context);
+        assert!(!explanation.matched);
+        assert_eq!(explanation.bindings, 0);
+        let vendor = &explanation.aliases[0];
+        assert_eq!(vendor.candidates, 0);
+        assert_eq!(
+            vendor.near_misses,
+            [
+                NearMiss {
+                    entity: 1,
+                    failing: vec![Constraint::equals("mood", "calm")],
+                    excluded_by: vec![],
+                },
+                NearMiss {
+                    entity: 2,
+                    failing: vec![],
+                    excluded_by: vec!["ghost".into()],
+                },
+                NearMiss {
+                    entity: 0,
+                    failing: vec![
+                        Constraint::has("merchant"),
+                        Constraint::equals("mood", "calm")
+                    ],
+                    excluded_by: vec![],
+                },
+            ]
+        );
+
+        let context = context.with_entity(Entity::new(3).with("merchant", "").with("mood", "calm"));
+        let explanation = graph.explain(&context);
+        assert!(!explanation.matched);
+        assert_eq!(
+            explanation.to_string(),
+            [
+                r#"story "market" doesn't match"#,
+                r#"alias "vendor": 1 candidates"#,
+                "  entity 1 fails mood = calm",
+                "  entity 2 is excluded by ghost",
+                "  entity 0 fails merchant, mood = calm",
+                r#"alias "buyer": 1 candidates"#,
+                "  entity 1 fails villager",
+                "  entity 3 fails villager",
+                "  entity 2 fails villager and is excluded by ghost",
+                "1 bindings",
+                "path #0 -> #1: 0 bindings",
+                "  node #1: world constraint market day fails",
+                "path #0 -> #2: 0 bindings",
+                "  node #2: relation vendor -> buyer: friends holds for 0 bindings",
+                "",
+            ]
+            .join("\n")
+        );
+
+        let context = context.with_relation(3, 0, "friends", "");
+        let explanation = graph.explain(&context);
+        assert!(explanation.matched);
+        assert_eq!(explanation.paths[1].bindings, 1);
+        assert!(explanation.paths[1].failures.is_empty());
+    }
+}
@@ -0,0 +1,402 @@
+use std::fmt::Write;
+
+use fixedbitset::FixedBitSet;
+use itertools::Itertools;
+use petgraph::graph::NodeIndex;
+
+use crate::{
+    lint::Diagnostic,
+    narrative_world::NarrativeWorld,
+    scenario_action::ScenarioAction,
+    scenario_graph::{ScenarioActionId, ScenarioGraph},
+    transition::Transition,
+};
+
+// Renders a scenario graph for review, as Graphviz DOT or as a Mermaid flowchart.
+// Nodes show their description, directive and constraints, edges their weight and constraints.
+// Weak edges are dashed, the start node is drawn bold and unreachable nodes are grayed out.
+pub struct GraphExport<'a> {
+    graph: &'a ScenarioGraph,
+    world: Option<&'a NarrativeWorld>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NodeStatus {
+    Playable,
+    // not reachable from the start node
+    Unreachable,
+    // reachable, but no binding of the aliases can play through it in the world
+    Unsatisfiable,
+}
+
+struct ExportedNode {
+    key: String,
+    lines: Vec<String>,
+    is_start: bool,
+    status: NodeStatus,
+}
+
+struct ExportedEdge {
+    from: String,
+    to: String,
+    lines: Vec<String>,
+    is_weak: bool,
+}
+
+impl<'a> GraphExport<'a> {
+    pub fn new(graph: &'a ScenarioGraph) -> Self {
+        Self { graph, world: None }
+    }
+
+    // also highlights the nodes which can't be played through in `world`
+    pub fn with_world(mut self, world: &'a NarrativeWorld) -> Self {
+        self.world = Some(world);
+        self
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph \"{}\" {{", escape_dot(self.graph.id())).unwrap();
+        writeln!(dot, "    node [shape=box];").unwrap();
+        for node in self.nodes() {
+            let mut attributes = vec![format!("label=\"{}\"", dot_lines(&node.lines))];
+            if node.is_start {
+                attributes.push("penwidth=3".into());
+            }
+            match node.status {
+                NodeStatus::Playable => {}
+                NodeStatus::Unreachable => {
+                    attributes.push("style=filled, fillcolor=lightgray".into())
+                }
+                NodeStatus::Unsatisfiable => {
+                    attributes.push("style=filled, fillcolor=lightpink".into())
+                }
+            }
+            writeln!(dot, "    {} [{}];", node.key, attributes.join(", ")).unwrap();
+        }
+        for edge in self.edges() {
+            let mut attributes = vec![];
+            if !edge.lines.is_empty() {
+                attributes.push(format!("label=\"{}\"", dot_lines(&edge.lines)));
+            }
+            if edge.is_weak {
+                attributes.push("style=dashed".into());
+            }
+            write!(dot, "    {} -> {}", edge.from, edge.to).unwrap();
+            if !attributes.is_empty() {
+                write!(dot, " [{}]", attributes.join(", ")).unwrap();
+            }
+            writeln!(dot, ";").unwrap();
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut mermaid = String::new();
+        if !self.graph.id().is_empty() {
+            writeln!(mermaid, "---\ntitle: {}\n---", self.graph.id()).unwrap();
+        }
+        writeln!(mermaid, "flowchart TD").unwrap();
+        let nodes = self.nodes();
+        for node in &nodes {
+            writeln!(
+                mermaid,
+                "    {}[\"{}\"]",
+                node.key,
+                mermaid_lines(&node.lines)
+            )
+            .unwrap();
+        }
+        for edge in self.edges() {
+            let arrow = if edge.is_weak { "-.->" } else { "-->" };
+            write!(mermaid, "    {} {}", edge.from, arrow).unwrap();
+            if !edge.lines.is_empty() {
+                write!(mermaid, "|\"{}\"|", mermaid_lines(&edge.lines)).unwrap();
+            }
+            writeln!(mermaid, " {}", edge.to).unwrap();
+        }
+
+        let classes = [
+            ("start", "stroke-width:3px"),
+            ("unreachable", "fill:#ddd"),
+            ("unsatisfiable", "fill:#f9c"),
+        ];
+        for (class, style) in classes {
+            let members = nodes
+                .iter()
+                .filter(|node| match class {
+                    "start" => node.is_start,
+                    "unreachable" => node.status == NodeStatus::Unreachable,
+                    _ => node.status == NodeStatus::Unsatisfiable,
+                })
+                .map(|node| node.key.as_str())
+                .join(",");
+            if !members.is_empty() {
+                writeln!(mermaid, "    classDef {} {}", class, style).unwrap();
+                writeln!(mermaid, "    class {} {}", members, class).unwrap();
+            }
+        }
+        mermaid
+    }
+
+    fn nodes(&self) -> Vec<ExportedNode> {
+        let unreachable: FixedBitSet = self
+            .graph
+            .validate()
+            .into_iter()
+            .filter_map(|diagnostic| match diagnostic {
+                Diagnostic::Unreachable(node_id) => Some(NodeIndex::from(node_id).index()),
+                _ => None,
+            })
+            .collect();
+        let unplayable = self
+            .world
+            .map(|world| self.graph.unplayable_nodes(world))
+            .unwrap_or_default();
+        let start = self.graph.start();
+
+        self.graph
+            .nodes()
+            .map(|(node_id, action)| {
+                let index = NodeIndex::from(node_id).index();
+                let status = if unreachable.contains(index) {
+                    NodeStatus::Unreachable
+                } else if unplayable.contains(index) {
+                    NodeStatus::Unsatisfiable
+                } else {
+                    NodeStatus::Playable
+                };
+                ExportedNode {
+                    key: key(node_id),
+                    lines: self.node_lines(node_id, action),
+                    is_start: node_id == start,
+                    status,
+                }
+            })
+            .collect()
+    }
+
+    fn node_lines(&self, node_id: ScenarioActionId, action: &ScenarioAction) -> Vec<String> {
+        let mut lines = vec![self.title(node_id)];
+        if !action.description.is_empty() {
+            lines.push(action.description.clone());
+        }
+        if !action.directive.is_empty() {
+            lines.push(format!("directive: {}", action.directive));
+        }
+        if !action.world_constraints.is_empty() {
+            lines.push(format!(
+                "world: {}",
+                action.world_constraints.iter().join(", ")
+            ));
+        }
+        lines.extend(action.relation_constraints.iter().map(ToString::to_string));
+        for visit_constraint in &action.visit_constraints {
+            let visits = &visit_constraint.visits;
+            lines.push(match visit_constraint.node {
+                Some(node) => format!(
+                    "visits of {}: {}..{}",
+                    self.title(node),
+                    visits.start,
+                    visits.end
+                ),
+                None => format!("visits: {}..{}", visits.start, visits.end),
+            });
+        }
+        for local_alias in action.local_aliases() {
+            lines.push(format!("local: {}", local_alias.alias()));
+        }
+        if let Some(sub_scenario) = &action.sub_scenario {
+            let aliases = sub_scenario
+                .aliases
+                .iter()
+                .map(|(parent, child)| format!("{} as {}", parent, child))
+                .join(", ");
+            lines.push(format!("calls {}({})", sub_scenario.story, aliases));
+        }
+        lines
+    }
+
+    // the name of a node, or its index
+    fn title(&self, node_id: ScenarioActionId) -> String {
+        match self.graph.node_name(node_id) {
+            Some(name) => name.to_string(),
+            None => format!("#{}", NodeIndex::from(node_id).index()),
+        }
+    }
+
+    fn edges(&self) -> Vec<ExportedEdge> {
+        let edge = |is_weak| {
+            move |(from, to, transition): (ScenarioActionId, ScenarioActionId, &Transition)| {
+                ExportedEdge {
+                    from: key(from),
+                    to: key(to),
+                    lines: transition_lines(transition),
+                    is_weak,
+                }
+            }
+        };
+        self.graph
+            .edges()
+            .map(edge(false))
+            .chain(self.graph.weak_edges().map(edge(true)))
+            .collect()
+    }
+}
+
+impl ScenarioGraph {
+    pub fn to_dot(&self) -> String {
+        GraphExport::new(self).to_dot()
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        GraphExport::new(self).to_mermaid()
+    }
+}
+
+fn key(node_id: ScenarioActionId) -> String {
+    format!("n{}", NodeIndex::from(node_id).index())
+}
+
+fn transition_lines(transition: &Transition) -> Vec<String> {
+    let mut lines = vec![];
+    if transition.weight != 0.0 {
+        lines.push(format!("weight: {}", transition.weight));
+    }
+    if !transition.world_constraints.is_empty() {
+        lines.push(format!(
+            "world: {}",
+            transition.world_constraints.iter().join(", ")
+        ));
+    }
+    lines.extend(
+        transition
+            .relation_constraints
+            .iter()
+            .map(ToString::to_string),
+    );
+    if let Some(max_traversals) = transition.max_traversals {
+        lines.push(format!("at most {} times", max_traversals));
+    }
+    lines
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn dot_lines(lines: &[String]) -> String {
+    lines.iter().map(|line| escape_dot(line)).join("\\n")
+}
+
+fn mermaid_lines(lines: &[String]) -> String {
+    lines
+        .iter()
+        .map(|line| {
+            line.replace('#', "#35;")
+                .replace('"', "#quot;")
+                .replace('<', "#lt;")
+                .replace('>', "#gt;")
+        })
+        .join("<br/>")
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::prelude::{Constraint, Entity};
+
+    fn tavern_brawl() -> (ScenarioGraph, [ScenarioActionId; 4]) {
+        let mut graph = ScenarioGraph::new();
+        graph.set_id("tavern_brawl");
+        graph.add_alias("patron", [Constraint::has("drunk")]);
+        graph.add_alias("bouncer", []);
+        let enter = graph
+            .add_named(
+                "enter",
+                ScenarioAction::new()
+                    .with_description("A patron stumbles in")
+                    .with_directive("{patron.name} says \"ale!\""),
+            )
+            .unwrap();
+        let brawl = graph.add(
+            ScenarioAction::new()
+                .with_description("The patron picks a fight")
+                .with_world_constraint(Constraint::has("night")),
+        );
+        let thrown_out = graph.add(
+            ScenarioAction::new()
+                .with_description("The bouncer throws the patron out")
+                .with_relation_constraints("bouncer", "patron", [Constraint::has("hostile")]),
+        );
+        let orphan = graph.add(ScenarioAction::new().with_description("Never reached"));
+        let leave = graph.add(ScenarioAction::new().with_description("The patron leaves"));
+        graph.set_start_node(enter);
+        graph.connect(enter, leave).unwrap();
+        graph
+            .connect_with(enter, brawl, Transition::new().with_weight(2.0))
+            .unwrap();
+        graph.connect(brawl, thrown_out).unwrap();
+        graph.connect_weak(brawl, enter).unwrap();
+        (graph, [enter, brawl, thrown_out, orphan])
+    }
+
+    #[test]
+    fn graphs_are_exported_to_dot() {
+        let (graph, _) = tavern_brawl();
+        let dot = graph.to_dot();
+
+        assert!(dot.starts_with("digraph \"tavern_brawl\" {\n"));
+        assert!(dot.contains(
+            "n0 [label=\"enter\\nA patron stumbles in\\ndirective: {patron.name} says \\\"ale!\\\"\", penwidth=3];"
+        ));
+        assert!(dot.contains("n1 [label=\"#1\\nThe patron picks a fight\\nworld: night\"];"));
+        assert!(dot.contains("bouncer -> patron: hostile"));
+        assert!(
+            dot.contains("n3 [label=\"#3\\nNever reached\", style=filled, fillcolor=lightgray];")
+        );
+        assert!(dot.contains("n0 -> n1 [label=\"weight: 2\"];"));
+        assert!(dot.contains("n1 -> n2;"));
+        assert!(dot.contains("n1 -> n0 [style=dashed];"));
+    }
+
+    #[test]
+    fn graphs_are_exported_to_mermaid() {
+        let (graph, _) = tavern_brawl();
+        let mermaid = graph.to_mermaid();
+
+        assert!(mermaid.starts_with("---\ntitle: tavern_brawl\n---\nflowchart TD\n"));
+        assert!(mermaid.contains(
+            "n0[\"enter<br/>A patron stumbles in<br/>directive: {patron.name} says #quot;ale!#quot;\"]"
+        ));
+        assert!(mermaid.contains(
+            "n2[\"#35;2<br/>The bouncer throws the patron out<br/>bouncer -#gt; patron: hostile\"]"
+        ));
+        assert!(mermaid.contains("n0 -->|\"weight: 2\"| n1"));
+        assert!(mermaid.contains("n1 -.-> n0"));
+        assert!(mermaid.contains("class n0 start"));
+        assert!(mermaid.contains("class n3 unreachable"));
+        assert!(!mermaid.contains("unsatisfiable"));
+    }
+
+    #[test]
+    fn unsatisfiable_nodes_are_highlighted_for_a_world() {
+        let (graph, [_, brawl, thrown_out, _]) = tavern_brawl();
+        let world = NarrativeWorld::new()
+            .with_entity(Entity::new(0).with("drunk", "yes"))
+            .with_entity(Entity::new(1))
+            .with_relation(1, 0, "hostile", "yes");
+
+        let export = GraphExport::new(&graph).with_world(&world).to_mermaid();
+        assert!(export.contains(&format!(
+            "class {},{} unsatisfiable",
+            key(brawl),
+            key(thrown_out)
+        )));
+
+        let world = world.with_world_property("night", "yes");
+        let export = GraphExport::new(&graph).with_world(&world).to_mermaid();
+        assert!(!export.contains("unsatisfiable"));
+    }
+}
@@ -0,0 +1,258 @@
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use crate::{
+    grammar::{Article, English, Grammar, PronounCase},
+    property::Property,
+    Int, Real,
+};
+
+pub type FilterName = String;
+
+// Formats the value of a placeholder, such as `upper` in `{player.name|upper}`.
+// The message of the error is reported along with the filter and the value it failed on.
+pub type Filter = dyn Fn(&Property) -> Result<Property, String>;
+
+// The filters templates can use, by name. Filters are applied in order, from left to right,
+// to every member of a group alias until `list` joins them.
+// Built-in filters:
+// - `upper`, `lower` and `capitalize`, which only capitalizes the first letter
+// - `int`, which truncates numbers
+// - `thousands`, which separates thousands with commas, as in `12,000`
+// - `genitive`, as in `Hialda's` or `Cyrus'`, also applied by `possessive` to anything but an alias
+// The grammar, English by default, provides the `a` and `the` articles, the `subject`, `object`,
+// `possessive` and `reflexive` pronouns of aliases and the choices such as `he/she/they`.
+// Pronouns and choices only apply right after an alias, as in `{<a>npc|possessive}`.
+#[derive(Clone)]
+pub struct Filters {
+    filters: HashMap<FilterName, Rc<Filter>>,
+    grammar: Rc<dyn Grammar>,
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Filters {
+    // the built-in filters
+    pub fn new() -> Self {
+        Self {
+            filters: HashMap::new(),
+            grammar: Rc::new(English::default()),
+        }
+        .with("upper", |value| {
+            Ok(Property::String(value.to_string().to_uppercase()))
+        })
+        .with("lower", |value| {
+            Ok(Property::String(value.to_string().to_lowercase()))
+        })
+        .with("capitalize", |value| {
+            Ok(Property::String(capitalize(&value.to_string())))
+        })
+        .with("int", int)
+        .with("thousands", thousands)
+        .with("genitive", |value| {
+            Ok(Property::String(genitive(&value.to_string())))
+        })
+        // `{npc.name|possessive}`, while `{<a>npc|possessive}` is a pronoun
+        .with("possessive", |value| {
+            Ok(Property::String(genitive(&value.to_string())))
+        })
+    }
+
+    // Adds a filter, replacing any filter of the same name, built-in or not
+    pub fn with<N, F>(mut self, name: N, filter: F) -> Self
+    where
+        N: Into<FilterName>,
+        F: Fn(&Property) -> Result<Property, String> + 'static,
+    {
+        self.insert(name, filter);
+        self
+    }
+
+    pub fn insert<N, F>(&mut self, name: N, filter: F)
+    where
+        N: Into<FilterName>,
+        F: Fn(&Property) -> Result<Property, String> + 'static,
+    {
+        self.filters.insert(name.into(), Rc::new(filter));
+    }
+
+    // the rules of the language of the templates
+    pub fn with_grammar<G>(mut self, grammar: G) -> Self
+    where
+        G: Grammar + 'static,
+    {
+        self.set_grammar(grammar);
+        self
+    }
+
+    pub fn set_grammar<G>(&mut self, grammar: G)
+    where
+        G: Grammar + 'static,
+    {
+        self.grammar = Rc::new(grammar);
+    }
+
+    // whether templates can use `name`, a filter, an article, a pronoun or a choice, the last two
+    // only right after an alias
+    pub fn contains(&self, name: &str) -> bool {
+        self.applies(name, true)
+    }
+
+    // whether `name` renders after `<a>alias` when `to_alias`, or after anything else otherwise
+    pub(crate) fn applies(&self, name: &str, to_alias: bool) -> bool {
+        name == LIST
+            || self.filters.contains_key(name)
+            || Article::from_name(name).is_some()
+            || to_alias && (PronounCase::from_name(name).is_some() || is_choice(name))
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&Filter> {
+        self.filters.get(name).map(Rc::as_ref)
+    }
+
+    pub(crate) fn grammar(&self) -> &dyn Grammar {
+        self.grammar.as_ref()
+    }
+}
+
+impl fmt::Debug for Filters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<_> = self.filters.keys().collect();
+        names.sort();
+        f.debug_set().entries(names).finish()
+    }
+}
+
+// joins the members of a group alias, as in `a, b and c`
+pub(crate) const LIST: &str = "list";
+
+thread_local! {
+    static DEFAULT_FILTERS: Filters = Filters::default();
+}
+
+// runs `f` with the built-in filters, built once per thread
+pub(crate) fn with_default_filters<T>(f: impl FnOnce(&Filters) -> T) -> T {
+    DEFAULT_FILTERS.with(f)
+}
+
+// variants separated by slashes, such as `he/she/they`
+pub(crate) fn is_choice(text: &str) -> bool {
+    text.contains('/') && text.split('/').all(|variant| !variant.is_empty())
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn int(value: &Property) -> Result<Property, String> {
+    match value {
+        Property::Int(value) => Ok(Property::Int(*value)),
+        Property::Float(value) => Ok(Property::Int(value.trunc() as Int)),
+        Property::String(value) => value
+            .trim()
+            .parse::<f64>()
+            .map(|value| Property::Int(value.trunc() as Int))
+            .map_err(|_| "not a number".to_string()),
+    }
+}
+
+fn thousands(value: &Property) -> Result<Property, String> {
+    // strings are parsed first, so that `+1200` or `1e5` are written out as plain numbers
+    let text = match value {
+        Property::String(value) => match value.trim().parse::<Int>() {
+            Ok(value) => value.to_string(),
+            Err(_) => value
+                .trim()
+                .parse::<Real>()
+                .map_err(|_| "not a number".to_string())?
+                .to_string(),
+        },
+        value => value.to_string(),
+    };
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", text.as_str()),
+    };
+    // such as `inf` or `NaN`
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err("not a finite number".to_string());
+    }
+    let (integer, decimals) = match digits.split_once('.') {
+        Some((integer, decimals)) => (integer, Some(decimals)),
+        None => (digits, None),
+    };
+    let mut grouped = String::new();
+    for (index, digit) in integer.chars().enumerate() {
+        if index > 0 && (integer.len() - index) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    let mut formatted = format!("{}{}", sign, grouped);
+    if let Some(decimals) = decimals {
+        formatted.push('.');
+        formatted.push_str(decimals);
+    }
+    Ok(Property::String(formatted))
+}
+
+fn genitive(text: &str) -> String {
+    if text.ends_with('s') || text.ends_with('S') {
+        format!("{}'", text)
+    } else {
+        format!("{}'s", text)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn apply(filter: &str, value: impl Into<Property>) -> Result<String, String> {
+        let filters = Filters::default();
+        filters.get(filter).unwrap()(&value.into()).map(|value| value.to_string())
+    }
+
+    #[test]
+    fn built_in_filters() {
+        assert_eq!(apply("upper", "Hialda"), Ok("HIALDA".into()));
+        assert_eq!(apply("capitalize", "élodie"), Ok("Élodie".into()));
+        assert_eq!(apply("int", 18.7), Ok("18".into()));
+        assert_eq!(apply("int", "3.5"), Ok("3".into()));
+        assert!(apply("int", "three").is_err());
+        assert_eq!(apply("thousands", 1234567), Ok("1,234,567".into()));
+        assert_eq!(apply("thousands", -1234.5), Ok("-1,234.5".into()));
+        assert_eq!(apply("thousands", 999), Ok("999".into()));
+        assert_eq!(apply("thousands", "+123"), Ok("123".into()));
+        assert_eq!(apply("thousands", " -4500 "), Ok("-4,500".into()));
+        assert_eq!(apply("thousands", "1e5"), Ok("100,000".into()));
+        assert_eq!(apply("thousands", "2500.25"), Ok("2,500.25".into()));
+        assert!(apply("thousands", "inf").is_err());
+        assert!(apply("thousands", f64::NEG_INFINITY).is_err());
+        assert!(apply("thousands", "many").is_err());
+        assert_eq!(apply("genitive", "Hialda"), Ok("Hialda's".into()));
+        assert_eq!(apply("genitive", "Cyrus"), Ok("Cyrus'".into()));
+        assert_eq!(apply("possessive", "Hialda"), Ok("Hialda's".into()));
+    }
+
+    #[test]
+    fn pronouns_and_choices_only_apply_to_aliases() {
+        let filters = Filters::default();
+        assert!(filters.contains("possessive"));
+        assert!(filters.applies("possessive", false));
+        assert!(filters.contains("subject"));
+        assert!(!filters.applies("subject", false));
+        assert!(filters.contains("he/she"));
+        assert!(!filters.applies("he/she", false));
+        assert!(!filters.contains("he//she"));
+        assert!(!filters.contains("/"));
+        assert!(filters.applies(LIST, false));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::{entity::Entity, property::PropertyName};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PronounCase {
+    // `{<a>npc|subject}`, as in "she"
+    Subject,
+    // `{<a>npc|object}`, as in "her"
+    Object,
+    // `{<a>npc|possessive}`, as in "her"
+    Possessive,
+    // `{<a>npc|reflexive}`, as in "herself"
+    Reflexive,
+}
+
+impl PronounCase {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "subject" => Some(Self::Subject),
+            "object" => Some(Self::Object),
+            "possessive" => Some(Self::Possessive),
+            "reflexive" => Some(Self::Reflexive),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Article {
+    // `{item.name|a}`, as in "an axe"
+    Indefinite,
+    // `{item.name|the}`, as in "the axe"
+    Definite,
+}
+
+impl Article {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "a" => Some(Self::Indefinite),
+            "the" => Some(Self::Definite),
+            _ => None,
+        }
+    }
+}
+
+// The rules of a language, used by templates to agree with the entities bound to aliases.
+// `entities` holds the members of a group alias, or the single entity bound to an alias.
+pub trait Grammar {
+    fn pronoun(&self, entities: &[&Entity], case: PronounCase) -> String;
+
+    // `noun` preceded by an article
+    fn article(&self, article: Article, noun: &str) -> String;
+
+    // Picks one of the variants of a choice such as `{<a>npc|he/she/they}` or `{<a>guards|is/are}`.
+    // An error explains which variants the language expects.
+    fn agree<'v>(&self, entities: &[&Entity], variants: &[&'v str]) -> Result<&'v str, String>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Gender {
+    Masculine,
+    Feminine,
+    Neuter,
+    // singular they, also used when the gender is unknown
+    Other,
+}
+
+// English grammar, reading the gender of entities from a property, `gender` by default.
+// Genders are `male` or `masculine`, `female` or `feminine` and `neuter`, other values stand for singular they.
+// Choices have either two variants, singular and plural as in `{<a>npc|is/are}`,
+// or three variants, masculine, feminine and other or plural as in `{<a>npc|he/she/they}`.
+#[derive(Debug, Clone)]
+pub struct English {
+    gender_property: PropertyName,
+}
+
+impl Default for English {
+    fn default() -> Self {
+        Self::new("gender")
+    }
+}
+
+impl English {
+    pub fn new<N>(gender_property: N) -> Self
+    where
+        N: Into<PropertyName>,
+    {
+        Self {
+            gender_property: gender_property.into(),
+        }
+    }
+
+    fn gender(&self, entities: &[&Entity]) -> Gender {
+        let [entity] = entities else {
+            return Gender::Other;
+        };
+        let gender = entity
+            .get(self.gender_property.as_str())
+            .map(|gender| gender.to_string().to_lowercase());
+        match gender.as_deref() {
+            Some("male" | "masculine") => Gender::Masculine,
+            Some("female" | "feminine") => Gender::Feminine,
+            Some("neuter") => Gender::Neuter,
+            _ => Gender::Other,
+        }
+    }
+}
+
+impl Grammar for English {
+    fn pronoun(&self, entities: &[&Entity], case: PronounCase) -> String {
+        let pronouns = match self.gender(entities) {
+            Gender::Masculine => ["he", "him", "his", "himself"],
+            Gender::Feminine => ["she", "her", "her", "herself"],
+            Gender::Neuter => ["it", "it", "its", "itself"],
+            Gender::Other if entities.len() == 1 => ["they", "them", "their", "themself"],
+            Gender::Other => ["they", "them", "their", "themselves"],
+        };
+        let pronoun = match case {
+            PronounCase::Subject => pronouns[0],
+            PronounCase::Object => pronouns[1],
+            PronounCase::Possessive => pronouns[2],
+            PronounCase::Reflexive => pronouns[3],
+        };
+        pronoun.to_string()
+    }
+
+    fn article(&self, article: Article, noun: &str) -> String {
+        match article {
+            Article::Definite => format!("the {}", noun),
+            Article::Indefinite => {
+                let starts_with_vowel = noun
+                    .chars()
+                    .next()
+                    .is_some_and(|c| "aeiouAEIOU".contains(c));
+                let article = if starts_with_vowel { "an" } else { "a" };
+                format!("{} {}", article, noun)
+            }
+        }
+    }
+
+    fn agree<'v>(&self, entities: &[&Entity], variants: &[&'v str]) -> Result<&'v str, String> {
+        let gender = self.gender(entities);
+        match variants {
+            // singular they takes plural verbs
+            [singular, plural] => Ok(match gender {
+                Gender::Other => plural,
+                _ => singular,
+            }),
+            [masculine, feminine, other] => Ok(match gender {
+                Gender::Masculine => masculine,
+                Gender::Feminine => feminine,
+                Gender::Neuter | Gender::Other => other,
+            }),
+            _ => Err("expected singular/plural or masculine/feminine/other variants".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn english_agrees_with_gender_and_number() {
+        let grammar = English::default();
+        let mira = Entity::new(0).with("gender", "Female");
+        let tom = Entity::new(1).with("gender", "male");
+        let sam = Entity::new(2);
+
+        assert_eq!(grammar.pronoun(&[&mira], PronounCase::Subject), "she");
+        assert_eq!(grammar.pronoun(&[&tom], PronounCase::Reflexive), "himself");
+        assert_eq!(grammar.pronoun(&[&sam], PronounCase::Reflexive), "themself");
+        assert_eq!(
+            grammar.pronoun(&[&mira, &tom], PronounCase::Reflexive),
+            "themselves"
+        );
+
+        assert_eq!(grammar.agree(&[&tom], &["is", "are"]), Ok("is"));
+        assert_eq!(grammar.agree(&[&sam], &["is", "are"]), Ok("are"));
+        assert_eq!(grammar.agree(&[&mira, &tom], &["is", "are"]), Ok("are"));
+        assert_eq!(
+            grammar.agree(&[&mira], &["lord", "lady", "liege"]),
+            Ok("lady")
+        );
+        assert!(grammar.agree(&[&mira], &["a", "b", "c", "d"]).is_err());
+
+        assert_eq!(grammar.article(Article::Indefinite, "axe"), "an axe");
+        assert_eq!(grammar.article(Article::Indefinite, "sword"), "a sword");
+        assert_eq!(grammar.article(Article::Definite, "sword"), "the sword");
+    }
+}
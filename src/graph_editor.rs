@@ -0,0 +1,593 @@
+use crate::{
+    scenario_action::ScenarioAction,
+    scenario_graph::{EditError, NodeName, ScenarioActionId, ScenarioGraph},
+    transition::Transition,
+};
+
+// A reversible change to a scenario graph. Applying an edit yields the edit undoing it.
+#[derive(Debug, Clone)]
+pub enum Edit {
+    Add {
+        action: ScenarioAction,
+        name: Option<NodeName>,
+    },
+    // Re-adds a removed node under its former id, as when undoing its removal
+    Restore {
+        node: ScenarioActionId,
+        action: ScenarioAction,
+        name: Option<NodeName>,
+    },
+    Remove(ScenarioActionId),
+    Replace(ScenarioActionId, ScenarioAction),
+    Connect {
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+        transition: Transition,
+    },
+    Disconnect {
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+    },
+    ConnectWeak {
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+        transition: Transition,
+    },
+    DisconnectWeak {
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+    },
+    Rewire {
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+        new_from: ScenarioActionId,
+        new_to: ScenarioActionId,
+    },
+    RewireWeak {
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+        new_from: ScenarioActionId,
+        new_to: ScenarioActionId,
+    },
+    SetStart(Option<ScenarioActionId>),
+    Rename(ScenarioActionId, Option<NodeName>),
+    // Applied in order, all or nothing
+    Batch(Vec<Edit>),
+}
+
+impl ScenarioGraph {
+    // Applies an edit and returns its inverse. A failed edit leaves the graph unchanged.
+    pub fn apply(&mut self, edit: Edit) -> Result<Edit, EditError> {
+        match edit {
+            Edit::Add { action, name } => self.apply_add(action, name).map(Edit::Remove),
+            Edit::Restore { node, action, name } => {
+                self.check_name_free(name.as_ref())?;
+                self.restore(node, action)?;
+                if let Some(name) = name {
+                    self.set_node_name(node, name)?;
+                }
+                Ok(Edit::Remove(node))
+            }
+            Edit::Remove(node) => {
+                self.check_nodes([node])?;
+                let mut restore = vec![];
+                let was_start = self.start_id() == Some(node);
+                let name = self.node_name(node).map(NodeName::from);
+                let edges = self
+                    .edges()
+                    .filter(|&(from, to, _)| from == node || to == node)
+                    .map(|(from, to, transition)| Edit::Connect {
+                        from,
+                        to,
+                        transition: transition.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                let weak_edges = self
+                    .weak_edges()
+                    .filter(|&(from, to, _)| from == node || to == node)
+                    .map(|(from, to, transition)| Edit::ConnectWeak {
+                        from,
+                        to,
+                        transition: transition.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                let action = self.remove(node).ok_or(EditError::MissingNode(node))?;
+
+                restore.push(Edit::Restore { node, action, name });
+                if was_start {
+                    restore.push(Edit::SetStart(Some(node)));
+                }
+                restore.extend(edges);
+                restore.extend(weak_edges);
+                Ok(Edit::Batch(restore))
+            }
+            Edit::Replace(node, action) => self
+                .replace(node, action)
+                .map(|previous| Edit::Replace(node, previous))
+                .ok_or(EditError::MissingNode(node)),
+            Edit::Connect {
+                from,
+                to,
+                transition,
+            } => {
+                self.check_nodes([from, to])?;
+                self.connect_with(from, to, transition)?;
+                Ok(Edit::Disconnect { from, to })
+            }
+            Edit::Disconnect { from, to } => self
+                .disconnect(from, to)
+                .map(|transition| Edit::Connect {
+                    from,
+                    to,
+                    transition,
+                })
+                .ok_or(EditError::MissingEdge { from, to }),
+            Edit::ConnectWeak {
+                from,
+                to,
+                transition,
+            } => {
+                self.check_nodes([from, to])?;
+                self.connect_weak_with(from, to, transition)?;
+                Ok(Edit::DisconnectWeak { from, to })
+            }
+            Edit::DisconnectWeak { from, to } => self
+                .disconnect_weak(from, to)
+                .map(|transition| Edit::ConnectWeak {
+                    from,
+                    to,
+                    transition,
+                })
+                .ok_or(EditError::MissingEdge { from, to }),
+            Edit::Rewire {
+                from,
+                to,
+                new_from,
+                new_to,
+            } => {
+                self.rewire((from, to), (new_from, new_to))?;
+                Ok(Edit::Rewire {
+                    from: new_from,
+                    to: new_to,
+                    new_from: from,
+                    new_to: to,
+                })
+            }
+            Edit::RewireWeak {
+                from,
+                to,
+                new_from,
+                new_to,
+            } => {
+                self.rewire_weak((from, to), (new_from, new_to))?;
+                Ok(Edit::RewireWeak {
+                    from: new_from,
+                    to: new_to,
+                    new_from: from,
+                    new_to: to,
+                })
+            }
+            Edit::SetStart(node) => {
+                let previous = self.start_id();
+                match node {
+                    Some(node) => {
+                        self.check_nodes([node])?;
+                        self.set_start_node(node);
+                    }
+                    None => self.clear_start_node(),
+                }
+                Ok(Edit::SetStart(previous))
+            }
+            Edit::Rename(node, name) => {
+                self.check_nodes([node])?;
+                let previous = self.node_name(node).map(NodeName::from);
+                match name {
+                    Some(name) => self.set_node_name(node, name)?,
+                    None => {
+                        self.remove_node_name(node);
+                    }
+                }
+                Ok(Edit::Rename(node, previous))
+            }
+            Edit::Batch(edits) => {
+                // rolled back to a copy of the graph, rather than through inverses which may fail
+                let before = self.clone();
+                let mut inverses = Vec::with_capacity(edits.len());
+                for edit in edits {
+                    match self.apply(edit) {
+                        Ok(inverse) => inverses.push(inverse),
+                        Err(err) => {
+                            *self = before;
+                            return Err(err);
+                        }
+                    }
+                }
+                inverses.reverse();
+                Ok(Edit::Batch(inverses))
+            }
+        }
+    }
+
+    fn apply_add(
+        &mut self,
+        action: ScenarioAction,
+        name: Option<NodeName>,
+    ) -> Result<ScenarioActionId, EditError> {
+        self.check_name_free(name.as_ref())?;
+        let node = self.add(action);
+        if let Some(name) = name {
+            self.set_node_name(node, name)?;
+        }
+        Ok(node)
+    }
+
+    fn check_name_free(&self, name: Option<&NodeName>) -> Result<(), EditError> {
+        match name.filter(|name| self.node_id(name).is_some()) {
+            Some(name) => Err(EditError::DuplicateNodeName(name.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+// Edits a scenario graph while keeping a history of edits to undo and redo
+#[derive(Default)]
+pub struct GraphEditor {
+    graph: ScenarioGraph,
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl GraphEditor {
+    pub fn new(graph: ScenarioGraph) -> Self {
+        Self {
+            graph,
+            ..Default::default()
+        }
+    }
+
+    pub fn graph(&self) -> &ScenarioGraph {
+        &self.graph
+    }
+
+    pub fn into_graph(self) -> ScenarioGraph {
+        self.graph
+    }
+
+    // Applies an edit, clearing the edits to redo
+    pub fn apply(&mut self, edit: Edit) -> Result<(), EditError> {
+        let inverse = self.graph.apply(edit)?;
+        self.record(inverse);
+        Ok(())
+    }
+
+    fn record(&mut self, inverse: Edit) {
+        self.undo.push(inverse);
+        self.redo.clear();
+    }
+
+    pub fn add(&mut self, action: ScenarioAction) -> ScenarioActionId {
+        let node = self.graph.add(action);
+        self.record(Edit::Remove(node));
+        node
+    }
+
+    pub fn add_named<N>(
+        &mut self,
+        name: N,
+        action: ScenarioAction,
+    ) -> Result<ScenarioActionId, EditError>
+    where
+        N: Into<NodeName>,
+    {
+        self.apply_add(action, Some(name.into()))
+    }
+
+    fn apply_add(
+        &mut self,
+        action: ScenarioAction,
+        name: Option<NodeName>,
+    ) -> Result<ScenarioActionId, EditError> {
+        let node = self.graph.apply_add(action, name)?;
+        self.record(Edit::Remove(node));
+        Ok(node)
+    }
+
+    pub fn remove(&mut self, node: ScenarioActionId) -> Result<(), EditError> {
+        self.apply(Edit::Remove(node))
+    }
+
+    pub fn replace(
+        &mut self,
+        node: ScenarioActionId,
+        action: ScenarioAction,
+    ) -> Result<(), EditError> {
+        self.apply(Edit::Replace(node, action))
+    }
+
+    pub fn connect_with<T>(
+        &mut self,
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+        transition: T,
+    ) -> Result<(), EditError>
+    where
+        T: Into<Transition>,
+    {
+        self.apply(Edit::Connect {
+            from,
+            to,
+            transition: transition.into(),
+        })
+    }
+
+    pub fn disconnect(
+        &mut self,
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+    ) -> Result<(), EditError> {
+        self.apply(Edit::Disconnect { from, to })
+    }
+
+    pub fn connect_weak_with<T>(
+        &mut self,
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+        transition: T,
+    ) -> Result<(), EditError>
+    where
+        T: Into<Transition>,
+    {
+        self.apply(Edit::ConnectWeak {
+            from,
+            to,
+            transition: transition.into(),
+        })
+    }
+
+    pub fn disconnect_weak(
+        &mut self,
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+    ) -> Result<(), EditError> {
+        self.apply(Edit::DisconnectWeak { from, to })
+    }
+
+    pub fn rewire(
+        &mut self,
+        (from, to): (ScenarioActionId, ScenarioActionId),
+        (new_from, new_to): (ScenarioActionId, ScenarioActionId),
+    ) -> Result<(), EditError> {
+        self.apply(Edit::Rewire {
+            from,
+            to,
+            new_from,
+            new_to,
+        })
+    }
+
+    pub fn rewire_weak(
+        &mut self,
+        (from, to): (ScenarioActionId, ScenarioActionId),
+        (new_from, new_to): (ScenarioActionId, ScenarioActionId),
+    ) -> Result<(), EditError> {
+        self.apply(Edit::RewireWeak {
+            from,
+            to,
+            new_from,
+            new_to,
+        })
+    }
+
+    pub fn set_start_node(&mut self, node: ScenarioActionId) -> Result<(), EditError> {
+        self.apply(Edit::SetStart(Some(node)))
+    }
+
+    pub fn set_node_name<N>(&mut self, node: ScenarioActionId, name: N) -> Result<(), EditError>
+    where
+        N: Into<NodeName>,
+    {
+        self.apply(Edit::Rename(node, Some(name.into())))
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    // Returns false if there was nothing to undo. A failed undo leaves the graph and the history
+    // unchanged.
+    pub fn undo(&mut self) -> Result<bool, EditError> {
+        Self::revert(&mut self.graph, &mut self.undo, &mut self.redo)
+    }
+
+    // Returns false if there was nothing to redo. A failed redo leaves the graph and the history
+    // unchanged.
+    pub fn redo(&mut self) -> Result<bool, EditError> {
+        Self::revert(&mut self.graph, &mut self.redo, &mut self.undo)
+    }
+
+    fn revert(
+        graph: &mut ScenarioGraph,
+        from: &mut Vec<Edit>,
+        to: &mut Vec<Edit>,
+    ) -> Result<bool, EditError> {
+        let Some(edit) = from.last() else {
+            return Ok(false);
+        };
+        let inverse = graph.apply(edit.clone())?;
+        from.pop();
+        to.push(inverse);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use itertools::Itertools;
+
+    use super::*;
+
+    fn chain() -> (GraphEditor, [ScenarioActionId; 3]) {
+        let mut editor = GraphEditor::new(ScenarioGraph::new());
+        let a = editor
+            .add_named("a", ScenarioAction::new().with_description("a"))
+            .unwrap();
+        let b = editor
+            .add_named("b", ScenarioAction::new().with_description("b"))
+            .unwrap();
+        let c = editor
+            .add_named("c", ScenarioAction::new().with_description("c"))
+            .unwrap();
+        editor.set_start_node(a).unwrap();
+        editor.connect_with(a, b, Transition::new()).unwrap();
+        editor.connect_with(b, c, Transition::new()).unwrap();
+        editor.connect_weak_with(c, b, Transition::new()).unwrap();
+        (editor, [a, b, c])
+    }
+
+    fn snapshot(graph: &ScenarioGraph) -> String {
+        ron::to_string(graph).unwrap()
+    }
+
+    #[test]
+    fn removing_a_node_removes_its_edges() {
+        let (mut editor, [a, b, c]) = chain();
+        editor.remove(b).unwrap();
+
+        let graph = editor.graph();
+        assert!(!graph.contains(b));
+        assert_eq!(graph.node_id("b"), None);
+        assert_eq!(graph.edges().count(), 0);
+        assert_eq!(graph.weak_edges().count(), 0);
+        assert_eq!(graph.nodes().map(|(id, _)| id).collect::<Vec<_>>(), [a, c]);
+        assert_eq!(graph.node_id("c"), Some(c));
+
+        editor.remove(a).unwrap();
+        assert_eq!(editor.graph().start(), c);
+    }
+
+    #[test]
+    fn undo_and_redo_restore_the_graph() {
+        let (mut editor, [a, b, c]) = chain();
+        let before = snapshot(editor.graph());
+
+        editor.remove(b).unwrap();
+        editor.remove(a).unwrap();
+        let after = snapshot(editor.graph());
+
+        assert!(editor.undo().unwrap());
+        assert!(editor.undo().unwrap());
+        assert_eq!(snapshot(editor.graph()), before);
+        assert_eq!(editor.graph().start(), a);
+        assert_eq!(editor.graph().node_id("b"), Some(b));
+        assert_eq!(
+            editor
+                .graph()
+                .weak_edges()
+                .map(|(from, to, _)| (from, to))
+                .collect::<Vec<_>>(),
+            [(c, b)]
+        );
+
+        assert!(editor.redo().unwrap());
+        assert!(editor.redo().unwrap());
+        assert!(!editor.redo().unwrap());
+        assert_eq!(snapshot(editor.graph()), after);
+
+        while editor.undo().unwrap() {}
+        assert_eq!(editor.graph().node_count(), 0);
+    }
+
+    #[test]
+    fn removed_nodes_are_restored_in_any_order() {
+        let (editor, [a, b, c]) = chain();
+        let mut graph = editor.into_graph();
+
+        let restore_a = graph.apply(Edit::Remove(a)).unwrap();
+        let restore_c = graph.apply(Edit::Remove(c)).unwrap();
+        graph.apply(restore_a).unwrap();
+        graph.apply(restore_c).unwrap();
+
+        assert_eq!(
+            graph.nodes().map(|(id, _)| id).collect::<Vec<_>>(),
+            [a, b, c]
+        );
+        assert_eq!(graph.node_id("a"), Some(a));
+        assert_eq!(graph.node_id("c"), Some(c));
+        assert_eq!(graph.start(), a);
+        assert_eq!(
+            graph
+                .edges()
+                .chain(graph.weak_edges())
+                .map(|(from, to, _)| (from, to))
+                .sorted()
+                .collect::<Vec<_>>(),
+            [(a, b), (b, c), (c, b)]
+        );
+
+        let restore = Edit::Restore {
+            node: b,
+            action: ScenarioAction::new(),
+            name: None,
+        };
+        assert_eq!(graph.apply(restore).err(), Some(EditError::NodeTaken(b)));
+    }
+
+    #[test]
+    fn rewiring_keeps_the_graph_acyclic() {
+        let (mut editor, [a, b, c]) = chain();
+
+        assert_eq!(editor.rewire((a, b), (c, b)), Err(EditError::CycleDetected));
+        assert_eq!(
+            editor
+                .graph()
+                .edges()
+                .map(|(from, to, _)| (from, to))
+                .collect::<Vec<_>>(),
+            [(a, b), (b, c)]
+        );
+
+        editor.rewire((b, c), (a, c)).unwrap();
+        assert!(editor.graph().all_connections(b.into()).is_empty());
+        assert!(editor.undo().unwrap());
+        assert_eq!(editor.graph().all_connections(b.into()).len(), 1);
+        assert_eq!(
+            editor.disconnect(a, c),
+            Err(EditError::MissingEdge { from: a, to: c })
+        );
+    }
+
+    #[test]
+    fn failed_batches_are_rolled_back() {
+        let (mut editor, [a, b, c]) = chain();
+        let before = snapshot(editor.graph());
+
+        let batch = Edit::Batch(vec![
+            Edit::Replace(a, ScenarioAction::new().with_description("A")),
+            Edit::Disconnect { from: a, to: b },
+            Edit::Connect {
+                from: c,
+                to: a,
+                transition: Transition::new(),
+            },
+            Edit::Connect {
+                from: a,
+                to: c,
+                transition: Transition::new(),
+            },
+        ]);
+        assert_eq!(editor.apply(batch), Err(EditError::CycleDetected));
+        assert_eq!(snapshot(editor.graph()), before);
+
+        assert_eq!(
+            editor.set_node_name(a, "b"),
+            Err(EditError::DuplicateNodeName("b".into()))
+        );
+        editor.set_node_name(a, "start").unwrap();
+        assert!(editor.undo().unwrap());
+        assert_eq!(editor.graph().node_name(a), Some("a"));
+    }
+}
@@ -1,22 +1,65 @@
+mod alias_solver;
 mod constraint;
 mod entity;
+mod error;
+mod explain;
+mod export;
+mod filter;
+mod grammar;
+mod graph_editor;
 mod instruction;
+mod line;
+mod lint;
+mod locale;
 mod narrative_world;
 mod property;
+mod query;
 mod raconteur;
 mod relationship;
+mod rng;
 mod scenario;
 mod scenario_action;
 mod scenario_graph;
 mod schema;
+mod shuffle_bag;
+mod tag_expr;
+mod template;
+mod transition;
+mod visits;
+
+pub use error::{Error, Location};
 
 pub type Int = i64;
 pub type Real = f64;
 
 pub mod prelude {
     pub use crate::{
-        constraint::Constraint, entity::Entity, narrative_world::NarrativeWorld,
-        property::PropertyName, raconteur::Raconteur, scenario::Scenario,
-        scenario_action::ScenarioAction, scenario_graph::ScenarioGraph, Int, Real,
+        constraint::{Constraint, Quantifier},
+        entity::Entity,
+        error::{Error, Location},
+        explain::{
+            AliasReport, Explanation, NearMiss, PathFailure, PathReport, RelationReport, Site,
+        },
+        export::GraphExport,
+        filter::{Filter, FilterName, Filters},
+        grammar::{Article, English, Grammar, PronounCase},
+        graph_editor::{Edit, GraphEditor},
+        line::{LineName, LineVariant},
+        locale::{Locale, Localization, NodeTexts, StringTable},
+        narrative_world::NarrativeWorld,
+        property::{Property, PropertyName, PropertyType},
+        query::QueryOptions,
+        raconteur::Raconteur,
+        rng::SeededRng,
+        scenario::Scenario,
+        scenario_action::{ScenarioAction, SubScenario},
+        scenario_graph::{EditError, NodeName, ScenarioGraph, StoryId, StoryLibrary},
+        schema::{Schema, UndefinedName},
+        shuffle_bag::ShuffleBags,
+        tag_expr::TagExpr,
+        template::{Template, TemplateError, TemplateSyntax, TextSegment},
+        transition::Transition,
+        visits::{VisitConstraint, Visits},
+        Int, Real,
     };
 }
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constraint::{AliasRelation, Constraint},
+    narrative_world::NarrativeWorld,
+    scenario_action::Alias,
+    scenario_graph::AliasMap,
+    template::Template,
+};
+
+pub type LineName = String;
+
+// One way to say a named line, referenced as `[line name]` in a directive.
+// The first variant of a line whose constraints hold is picked, so a variant without constraints
+// placed last serves as the fallback.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LineVariant {
+    pub text: Template,
+    // constraints on the properties of the entity bound to an alias
+    #[serde(default)]
+    pub alias_constraints: Vec<(Alias, Constraint)>,
+    #[serde(default)]
+    pub relation_constraints: Vec<AliasRelation>,
+    #[serde(default)]
+    pub world_constraints: Vec<Constraint>,
+}
+
+impl LineVariant {
+    pub fn new<S>(text: S) -> Self
+    where
+        S: Into<Template>,
+    {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    // builder methods
+
+    pub fn with_alias_constraint<A>(mut self, alias: A, constraint: Constraint) -> Self
+    where
+        A: Into<Alias>,
+    {
+        self.alias_constraints.push((alias.into(), constraint));
+        self
+    }
+
+    pub fn with_relation_constraints<A, C>(mut self, me: A, other: A, constraints: C) -> Self
+    where
+        A: Into<Alias>,
+        C: IntoIterator<Item = Constraint>,
+    {
+        self.relation_constraints
+            .push(AliasRelation::new(me, other, constraints));
+        self
+    }
+
+    pub fn with_world_constraint(mut self, constraint: Constraint) -> Self {
+        self.world_constraints.push(constraint);
+        self
+    }
+
+    // An alias constraint holds if every entity bound to the alias satisfies it,
+    // and never holds for an unbound alias.
+    pub(crate) fn applies(&self, context: &NarrativeWorld, alias_map: &AliasMap) -> bool {
+        self.world_constraints
+            .iter()
+            .all(|constraint| constraint.is_satisfied_by(context.properties()))
+            && self.alias_constraints.iter().all(|(alias, constraint)| {
+                alias_map.members(alias).is_some_and(|members| {
+                    members.iter().all(|&entity_id| {
+                        context
+                            .entity(entity_id)
+                            .is_some_and(|entity| constraint.is_satisfied_by(&entity.properties))
+                    })
+                })
+            })
+            && self
+                .relation_constraints
+                .iter()
+                .all(|relation| relation.is_satisfied_in(context, alias_map))
+    }
+
+    // aliases named by the constraints of the variant
+    pub(crate) fn constrained_aliases(&self) -> impl Iterator<Item = &str> {
+        self.alias_constraints
+            .iter()
+            .map(|(alias, _)| alias)
+            .chain(
+                self.relation_constraints
+                    .iter()
+                    .flat_map(|relation| [&relation.me, &relation.other]),
+            )
+            .map(Alias::as_str)
+    }
+}
@@ -0,0 +1,803 @@
+use std::{collections::HashSet, fmt};
+
+use fixedbitset::FixedBitSet;
+use itertools::Itertools;
+use petgraph::prelude::NodeIndex;
+
+use crate::{
+    filter::{FilterName, Filters},
+    line::LineName,
+    scenario_action::Alias,
+    scenario_graph::{NodeName, ScenarioActionId, ScenarioGraph, StoryId, StoryLibrary},
+    template::{Template, TemplateError},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    // the story can still be played, but probably not as intended
+    Warning,
+    // the story can never be played, or can get stuck midway
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+// A problem found in a scenario graph by `ScenarioGraph::validate`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diagnostic {
+    Empty,
+    StartNotSet,
+    InvalidStart(ScenarioActionId),
+    DuplicateAlias(Alias),
+    DanglingEdge {
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+    },
+    Unreachable(ScenarioActionId),
+    // a node name referring to a missing node, such as in a hand edited file
+    DanglingNodeName {
+        name: NodeName,
+        node: ScenarioActionId,
+    },
+    NoReachableLeaf,
+    // a node reachable from the start from which no leaf can be reached, such as weak edge loops without exit
+    DeadEnd(ScenarioActionId),
+    UndeclaredRelationAlias {
+        node: ScenarioActionId,
+        alias: Alias,
+    },
+    UndeclaredDirectiveAlias {
+        node: ScenarioActionId,
+        alias: Alias,
+    },
+    // A directive or line variant which couldn't be parsed when built. Within a line, the span of
+    // the error is relative to the text of the variant, `line` being its name and index.
+    MalformedTemplate {
+        node: ScenarioActionId,
+        line: Option<(LineName, usize)>,
+        error: TemplateError,
+    },
+    // an alias allowed to coincide with an undeclared alias
+    UndeclaredCoincidingAlias {
+        alias: Alias,
+        other: Alias,
+    },
+    // a parent alias mapped into a sub-scenario which the story doesn't declare
+    UndeclaredSubScenarioAlias {
+        node: ScenarioActionId,
+        alias: Alias,
+    },
+    // the following are only reported by `Raconteur::validate`
+    UnknownSubScenario {
+        node: ScenarioActionId,
+        story: StoryId,
+    },
+    // a child alias mapped into a sub-scenario which the called story doesn't declare
+    UnknownSubScenarioAlias {
+        node: ScenarioActionId,
+        story: StoryId,
+        alias: Alias,
+    },
+    RecursiveSubScenario {
+        node: ScenarioActionId,
+        story: StoryId,
+    },
+    UnknownFilter {
+        node: ScenarioActionId,
+        filter: FilterName,
+    },
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Diagnostic::StartNotSet | Diagnostic::Unreachable(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity() == Severity::Error
+    }
+
+    // The diagnostic naming the nodes of `graph` it refers to, as in `node "haggle" (#1)`, where
+    // displaying it only gives their ids
+    pub fn describe(&self, graph: &ScenarioGraph) -> String {
+        Described {
+            diagnostic: self,
+            graph: Some(graph),
+        }
+        .to_string()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Described {
+            diagnostic: self,
+            graph: None,
+        }
+        .fmt(f)
+    }
+}
+
+struct Described<'a> {
+    diagnostic: &'a Diagnostic,
+    graph: Option<&'a ScenarioGraph>,
+}
+
+impl fmt::Display for Described<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = |node_id: &ScenarioActionId| match self.graph {
+            Some(graph) => graph.node_label(*node_id),
+            None => node_id.to_string(),
+        };
+        match self.diagnostic {
+            Diagnostic::Empty => write!(f, "the graph has no nodes"),
+            Diagnostic::StartNotSet => {
+                write!(f, "the start node is not set, defaulting to the first node")
+            }
+            Diagnostic::InvalidStart(node) => {
+                write!(f, "the start node {} does not exist", label(node))
+            }
+            Diagnostic::DuplicateAlias(alias) => {
+                write!(f, r#"the alias "{}" is declared more than once"#, alias)
+            }
+            Diagnostic::DanglingEdge { from, to } => {
+                write!(
+                    f,
+                    "node {} connects to missing node {}",
+                    label(from),
+                    label(to)
+                )
+            }
+            Diagnostic::DanglingNodeName { name, node } => {
+                write!(
+                    f,
+                    r#"node name "{}" refers to missing node {}"#,
+                    name,
+                    label(node)
+                )
+            }
+            Diagnostic::Unreachable(node) => {
+                write!(f, "node {} is unreachable from the start node", label(node))
+            }
+            Diagnostic::NoReachableLeaf => {
+                write!(f, "no leaf node is reachable from the start node")
+            }
+            Diagnostic::DeadEnd(node) => {
+                write!(f, "no leaf node is reachable from node {}", label(node))
+            }
+            Diagnostic::UndeclaredRelationAlias { node, alias } => write!(
+                f,
+                r#"node {} has a relation constraint on undeclared alias "{}""#,
+                label(node),
+                alias
+            ),
+            Diagnostic::UndeclaredDirectiveAlias { node, alias } => write!(
+                f,
+                r#"node {} has a directive naming undeclared alias "{}""#,
+                label(node),
+                alias
+            ),
+            Diagnostic::MalformedTemplate { node, line, error } => match line {
+                Some((line, variant)) => write!(
+                    f,
+                    r#"node {} has a malformed template in line "{}" variant {}: {}"#,
+                    label(node),
+                    line,
+                    variant,
+                    error
+                ),
+                None => write!(
+                    f,
+                    "node {} has a malformed template: {}",
+                    label(node),
+                    error
+                ),
+            },
+            Diagnostic::UndeclaredCoincidingAlias { alias, other } => write!(
+                f,
+                r#"alias "{}" may coincide with undeclared alias "{}""#,
+                alias, other
+            ),
+            Diagnostic::UndeclaredSubScenarioAlias { node, alias } => write!(
+                f,
+                r#"node {} maps undeclared alias "{}" into its sub-scenario"#,
+                label(node),
+                alias
+            ),
+            Diagnostic::UnknownSubScenario { node, story } => {
+                write!(f, r#"node {} calls unknown story "{}""#, label(node), story)
+            }
+            Diagnostic::UnknownSubScenarioAlias { node, story, alias } => write!(
+                f,
+                r#"node {} maps into alias "{}" which story "{}" doesn't declare"#,
+                label(node),
+                alias,
+                story
+            ),
+            Diagnostic::RecursiveSubScenario { node, story } => write!(
+                f,
+                r#"node {} calls story "{}" which calls back into this story"#,
+                label(node),
+                story
+            ),
+            Diagnostic::UnknownFilter { node, filter } => {
+                write!(
+                    f,
+                    r#"node {} uses unknown filter "{}""#,
+                    label(node),
+                    filter
+                )
+            }
+        }
+    }
+}
+
+pub(crate) fn validate(graph: &ScenarioGraph) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    // local aliases of different nodes may share a name, as only one of them may be entered
+    let aliases = graph
+        .aliases()
+        .iter()
+        .map(|constrained_alias| constrained_alias.alias())
+        .collect_vec();
+    let local_aliases = |node_id: NodeIndex| {
+        graph
+            .action(node_id)
+            .local_aliases()
+            .iter()
+            .map(|local_alias| local_alias.alias())
+    };
+    let globals: HashSet<_> = aliases.iter().copied().map(Alias::as_str).collect();
+    let declared: HashSet<_> = globals
+        .iter()
+        .copied()
+        .chain(graph.node_ids().flat_map(local_aliases).map(Alias::as_str))
+        .collect();
+    diagnostics.extend(
+        aliases
+            .iter()
+            .copied()
+            .duplicates()
+            .chain(graph.node_ids().flat_map(|node_id| {
+                aliases
+                    .iter()
+                    .copied()
+                    .chain(local_aliases(node_id))
+                    .duplicates()
+            }))
+            .unique()
+            .map(|alias| Diagnostic::DuplicateAlias(alias.clone())),
+    );
+    for constrained_alias in graph.aliases() {
+        diagnostics.extend(
+            constrained_alias
+                .may_coincide_with
+                .iter()
+                .filter(|other| !declared.contains(other.as_str()))
+                .map(|other| Diagnostic::UndeclaredCoincidingAlias {
+                    alias: constrained_alias.alias().clone(),
+                    other: other.clone(),
+                }),
+        );
+    }
+
+    if 0 == graph.node_count() {
+        diagnostics.push(Diagnostic::Empty);
+        return diagnostics;
+    }
+
+    let start: NodeIndex = graph.start().into();
+    if !graph.is_start_set() {
+        diagnostics.push(Diagnostic::StartNotSet);
+    }
+    if !graph.contains(start.into()) {
+        diagnostics.push(Diagnostic::InvalidStart(graph.start()));
+        return diagnostics;
+    }
+
+    let exists = |node_id: &NodeIndex| graph.contains((*node_id).into());
+    diagnostics.extend(
+        graph
+            .node_names()
+            .iter()
+            .filter(|(_, &node)| !exists(&node.into()))
+            .map(|(name, &node)| Diagnostic::DanglingNodeName {
+                name: name.clone(),
+                node,
+            }),
+    );
+    for from in graph.node_ids() {
+        for to in graph.weak_connections(from) {
+            if !exists(&to) {
+                diagnostics.push(Diagnostic::DanglingEdge {
+                    from: from.into(),
+                    to: to.into(),
+                });
+            }
+        }
+    }
+    let connections = |node_id: NodeIndex| {
+        graph
+            .all_connections(node_id)
+            .into_iter()
+            .filter(exists)
+            .collect_vec()
+    };
+
+    // nodes reachable from the start, through strong and weak edges
+    let mut reachable = FixedBitSet::with_capacity(graph.node_bound());
+    let mut stack = vec![start];
+    while let Some(node_id) = stack.pop() {
+        if !reachable.put(node_id.index()) {
+            stack.extend(connections(node_id));
+        }
+    }
+
+    // nodes from which a leaf can be reached
+    let mut parents = vec![vec![]; graph.node_bound()];
+    let mut stack = vec![];
+    for node_id in graph.node_ids() {
+        let children = graph.all_connections(node_id);
+        if children.is_empty() {
+            stack.push(node_id);
+        }
+        for child_id in children.into_iter().filter(exists) {
+            parents[child_id.index()].push(node_id);
+        }
+    }
+    let mut finishing = FixedBitSet::with_capacity(graph.node_bound());
+    while let Some(node_id) = stack.pop() {
+        if !finishing.put(node_id.index()) {
+            stack.extend(&parents[node_id.index()]);
+        }
+    }
+
+    if finishing.contains(start.index()) {
+        diagnostics.extend(
+            reachable
+                .difference(&finishing)
+                .map(|index| Diagnostic::DeadEnd(NodeIndex::new(index).into())),
+        );
+    } else {
+        diagnostics.push(Diagnostic::NoReachableLeaf);
+    }
+
+    // aliases in scope at each node: the globals, plus the local aliases of the node itself
+    // and of every node above it on a path of strong edges, as a weak edge back to a hub would
+    // carry the aliases of one branch into its siblings
+    let mut in_scope = vec![globals; graph.node_bound()];
+    for node_id in graph.node_ids() {
+        let locals = local_aliases(node_id).map(Alias::as_str).collect_vec();
+        if locals.is_empty() {
+            continue;
+        }
+        let mut below = FixedBitSet::with_capacity(graph.node_bound());
+        let mut stack = vec![node_id];
+        while let Some(node_id) = stack.pop() {
+            if !below.put(node_id.index()) {
+                stack.extend(graph.strong_connections(node_id));
+            }
+        }
+        for index in below.ones() {
+            in_scope[index].extend(&locals);
+        }
+    }
+
+    for node_id in graph.node_ids() {
+        let declared = &in_scope[node_id.index()];
+        if !reachable.contains(node_id.index()) {
+            diagnostics.push(Diagnostic::Unreachable(node_id.into()));
+        }
+
+        // relation constraints on the node or on its outgoing edges
+        let action = graph.action(node_id);
+        let transitions = graph.transitions(node_id);
+        let relation_aliases = action
+            .relation_constraints
+            .iter()
+            .chain(
+                transitions
+                    .iter()
+                    .flat_map(|(_, transition)| &transition.relation_constraints),
+            )
+            .flat_map(|relation| [&relation.me, &relation.other])
+            .filter(|alias| !declared.contains(alias.as_str()))
+            .unique();
+        diagnostics.extend(
+            relation_aliases.map(|alias| Diagnostic::UndeclaredRelationAlias {
+                node: node_id.into(),
+                alias: alias.clone(),
+            }),
+        );
+        let line_variants = action.lines.iter().flat_map(|(name, variants)| {
+            variants
+                .iter()
+                .enumerate()
+                .map(move |(index, variant)| (Some((name, index)), &variant.text))
+        });
+        diagnostics.extend(
+            std::iter::once((None, &action.directive))
+                .chain(line_variants)
+                .filter_map(|(line, template)| {
+                    template.error().map(|error| Diagnostic::MalformedTemplate {
+                        node: node_id.into(),
+                        line: line.map(|(name, index)| (name.clone(), index)),
+                        error: error.clone(),
+                    })
+                }),
+        );
+        let directive_aliases = action
+            .directive_aliases()
+            .into_iter()
+            .filter(|alias| !declared.contains(alias))
+            .unique();
+        diagnostics.extend(
+            directive_aliases.map(|alias| Diagnostic::UndeclaredDirectiveAlias {
+                node: node_id.into(),
+                alias: alias.into(),
+            }),
+        );
+        if let Some(sub_scenario) = &action.sub_scenario {
+            diagnostics.extend(
+                sub_scenario
+                    .aliases
+                    .iter()
+                    .map(|(parent, _)| parent)
+                    .filter(|alias| !declared.contains(alias.as_str()))
+                    .unique()
+                    .map(|alias| Diagnostic::UndeclaredSubScenarioAlias {
+                        node: node_id.into(),
+                        alias: alias.clone(),
+                    }),
+            );
+        }
+    }
+
+    diagnostics
+}
+
+pub(crate) fn validate_sub_scenarios(
+    graph: &ScenarioGraph,
+    library: &dyn StoryLibrary,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    for node_id in graph.node_ids() {
+        let Some(sub_scenario) = &graph.action(node_id).sub_scenario else {
+            continue;
+        };
+        let node = node_id.into();
+        let Some(story) = library.story(&sub_scenario.story) else {
+            diagnostics.push(Diagnostic::UnknownSubScenario {
+                node,
+                story: sub_scenario.story.clone(),
+            });
+            continue;
+        };
+
+        let declared: HashSet<_> = story
+            .aliases()
+            .iter()
+            .map(|constrained_alias| constrained_alias.alias())
+            .collect();
+        diagnostics.extend(
+            sub_scenario
+                .aliases
+                .iter()
+                .map(|(_, child)| child)
+                .filter(|alias| !declared.contains(alias))
+                .unique()
+                .map(|alias| Diagnostic::UnknownSubScenarioAlias {
+                    node,
+                    story: sub_scenario.story.clone(),
+                    alias: alias.clone(),
+                }),
+        );
+
+        if calls_into(story, graph.id(), library) {
+            diagnostics.push(Diagnostic::RecursiveSubScenario {
+                node,
+                story: sub_scenario.story.clone(),
+            });
+        }
+    }
+
+    diagnostics
+}
+
+pub(crate) fn validate_filters(graph: &ScenarioGraph, filters: &Filters) -> Vec<Diagnostic> {
+    graph
+        .node_ids()
+        .flat_map(|node_id| {
+            graph
+                .action(node_id)
+                .templates()
+                .flat_map(Template::filters)
+                .filter(|&(filter, to_alias)| !filters.applies(filter, to_alias))
+                .map(|(filter, _)| filter)
+                .unique()
+                .map(move |filter| Diagnostic::UnknownFilter {
+                    node: node_id.into(),
+                    filter: filter.into(),
+                })
+        })
+        .collect()
+}
+
+// whether `story_id` can be reached from a story, including itself, through sub-scenarios
+fn calls_into(story: &ScenarioGraph, story_id: &str, library: &dyn StoryLibrary) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = vec![story];
+    while let Some(story) = stack.pop() {
+        if story.id() == story_id {
+            return true;
+        }
+        if visited.insert(story.id()) {
+            stack.extend(
+                story
+                    .sub_scenario_ids()
+                    .filter_map(|sub_story| library.story(sub_story)),
+            );
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::prelude::{Constraint, LineVariant, ScenarioAction, ScenarioGraph};
+
+    use super::Diagnostic;
+
+    #[test]
+    fn a_well_formed_graph_has_no_diagnostics() {
+        let mut graph = ScenarioGraph::new();
+        graph.add_alias("host", []);
+        graph.add_alias("guest", []);
+        let a = graph.add(
+            ScenarioAction::new()
+                .with_relation_constraints("host", "guest", [])
+                .with_directive("greet {<a>host} {guest.name}"),
+        );
+        let b = graph.add(ScenarioAction::new());
+        let c = graph.add(ScenarioAction::new());
+        graph.set_start_node(a);
+        graph.connect(a, b).unwrap();
+        graph.connect_weak(b, a).unwrap();
+        graph.connect(a, c).unwrap();
+
+        assert_eq!(graph.validate(), []);
+    }
+
+    #[test]
+    fn start_and_reachability_problems_are_reported() {
+        let graph = ScenarioGraph::new();
+        assert_eq!(graph.validate(), [Diagnostic::Empty]);
+
+        let mut graph = ScenarioGraph::new();
+        let a = graph.add(ScenarioAction::new());
+        let b = graph.add(ScenarioAction::new());
+        let c = graph.add(ScenarioAction::new());
+        let d = graph.add(ScenarioAction::new());
+        let e = graph.add(ScenarioAction::new());
+        graph.connect(a, b).unwrap();
+        graph.connect(a, c).unwrap();
+        graph.connect(c, d).unwrap();
+        graph.connect_weak(d, c).unwrap();
+
+        let diagnostics = graph.validate();
+        assert_eq!(
+            diagnostics,
+            [
+                Diagnostic::StartNotSet,
+                Diagnostic::DeadEnd(c),
+                Diagnostic::DeadEnd(d),
+                Diagnostic::Unreachable(e),
+            ]
+        );
+        assert!(diagnostics.iter().any(Diagnostic::is_error));
+
+        graph.set_start_node(c);
+        assert_eq!(
+            graph.validate(),
+            [
+                Diagnostic::NoReachableLeaf,
+                Diagnostic::Unreachable(a),
+                Diagnostic::Unreachable(b),
+                Diagnostic::Unreachable(e),
+            ]
+        );
+    }
+
+    #[test]
+    fn undeclared_aliases_are_reported() {
+        let mut graph = ScenarioGraph::new();
+        graph.add_alias("host", []);
+        graph.add_alias("host", []);
+        let a = graph.add(
+            ScenarioAction::new()
+                .with_relation_constraints("host", "guest", [])
+                .with_directive("{<a>stranger} greets {guest.name} in {location}"),
+        );
+        graph.set_start_node(a);
+
+        assert_eq!(
+            graph.validate(),
+            [
+                Diagnostic::DuplicateAlias("host".into()),
+                Diagnostic::UndeclaredRelationAlias {
+                    node: a,
+                    alias: "guest".into()
+                },
+                Diagnostic::UndeclaredDirectiveAlias {
+                    node: a,
+                    alias: "stranger".into()
+                },
+                Diagnostic::UndeclaredDirectiveAlias {
+                    node: a,
+                    alias: "guest".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn aliases_of_lines_are_checked() {
+        let mut graph = ScenarioGraph::new();
+        graph.add_alias("protag", []);
+        let a = graph.add(
+            ScenarioAction::new()
+                .with_directive("[bring message]")
+                .with_line(
+                    "bring message",
+                    [
+                        LineVariant::new("Lady {lady.name} sends word")
+                            .with_alias_constraint("protag", Constraint::has("renown")),
+                        LineVariant::new("A message").with_relation_constraints(
+                            "messenger",
+                            "protag",
+                            [],
+                        ),
+                    ],
+                ),
+        );
+        graph.set_start_node(a);
+
+        assert_eq!(
+            graph.validate(),
+            ["lady", "messenger"].map(|alias| Diagnostic::UndeclaredDirectiveAlias {
+                node: a,
+                alias: alias.into()
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_templates_are_reported() {
+        let mut graph = ScenarioGraph::new();
+        graph.add_alias("guest", []);
+        let a = graph.add(
+            ScenarioAction::new()
+                .with_directive("greet {guest name}")
+                .with_line("toast", [LineVariant::new("To {guest.name")]),
+        );
+        graph.set_start_node(a);
+
+        let diagnostics = graph.validate();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            r#"node #0 has a malformed template: Malformed property path "guest name" at 6..18"#
+        );
+        assert!(
+            matches!(&diagnostics[1], Diagnostic::MalformedTemplate { node, line: Some((line, 0)), error } if *node == a && line == "toast" && error.span == (3..14))
+        );
+
+        // nodes are named when described along with their graph
+        graph.set_node_name(a, "greet").unwrap();
+        assert_eq!(
+            diagnostics[1].describe(&graph),
+            r#"node "greet" (#0) has a malformed template in line "toast" variant 0: Unclosed placeholder at 3..14"#
+        );
+    }
+
+    #[test]
+    fn local_aliases_are_declared_aliases() {
+        let mut graph = ScenarioGraph::new();
+        graph.add_alias("thief", []);
+        let a = graph.add(ScenarioAction::new());
+        let b = graph.add(
+            ScenarioAction::new()
+                .with_local_alias("guard", [])
+                .with_directive("{guard.name} grabs {thief.name}"),
+        );
+        let c = graph.add(
+            ScenarioAction::new()
+                .with_local_alias("guard", [])
+                .with_local_alias("thief", [])
+                .with_directive("{<a>guard} looks away"),
+        );
+        graph.set_start_node(a);
+        graph.connect(a, b).unwrap();
+        graph.connect(a, c).unwrap();
+
+        assert_eq!(
+            graph.validate(),
+            [Diagnostic::DuplicateAlias("thief".into())]
+        );
+    }
+
+    #[test]
+    fn local_aliases_are_only_in_scope_below_their_node() {
+        let mut graph = ScenarioGraph::new();
+        let a = graph.add(ScenarioAction::new());
+        let b = graph.add(ScenarioAction::new().with_local_alias("guard", []));
+        let c = graph.add(ScenarioAction::new().with_directive("{<a>guard} looks away"));
+        let d = graph.add(ScenarioAction::new().with_directive("{guard.name} shrugs"));
+        graph.set_start_node(a);
+        graph.connect(a, b).unwrap();
+        graph.connect(a, c).unwrap();
+        graph.connect(b, d).unwrap();
+
+        assert_eq!(
+            graph.validate(),
+            [Diagnostic::UndeclaredDirectiveAlias {
+                node: c,
+                alias: "guard".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn local_aliases_are_not_in_scope_through_weak_edges() {
+        let mut graph = ScenarioGraph::new();
+        let hub = graph.add(ScenarioAction::new());
+        let bribe = graph.add(ScenarioAction::new().with_local_alias("guard", []));
+        let sneak = graph.add(ScenarioAction::new().with_directive("{<a>guard} looks away"));
+        let leave = graph.add(ScenarioAction::new());
+        graph.set_start_node(hub);
+        graph.connect(hub, bribe).unwrap();
+        graph.connect(hub, sneak).unwrap();
+        graph.connect(hub, leave).unwrap();
+        graph.connect_weak(bribe, hub).unwrap();
+
+        assert_eq!(
+            graph.validate(),
+            [Diagnostic::UndeclaredDirectiveAlias {
+                node: sneak,
+                alias: "guard".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn coinciding_aliases_must_be_declared() {
+        let mut graph = ScenarioGraph::new();
+        graph.add_alias("buyer", []);
+        graph.add_optional_alias("payer", []);
+        assert!(graph.allow_coinciding("buyer", "payer"));
+        assert!(graph.allow_coinciding("payer", "banker"));
+        assert!(!graph.allow_coinciding("banker", "buyer"));
+        let a = graph.add(ScenarioAction::new());
+        graph.set_start_node(a);
+
+        assert_eq!(
+            graph.validate(),
+            [Diagnostic::UndeclaredCoincidingAlias {
+                alias: "payer".into(),
+                other: "banker".into()
+            }]
+        );
+    }
+}
@@ -0,0 +1,464 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{self, Error},
+    filter::Filters,
+    line::LineName,
+    narrative_world::NarrativeWorld,
+    scenario_graph::{AliasMap, EditError, ScenarioActionId, ScenarioGraph, StoryId},
+    shuffle_bag::draw_from_global_shuffle_bags,
+    template::{concat, Template, TextSegment},
+};
+
+// such as `en`, `fr` or `fr-CA`
+pub type Locale = String;
+
+// The translated texts of a node. Texts left out fall back to the next locale of the chain,
+// and eventually to the text of the node itself.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeTexts {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directive: Option<Template>,
+    // the texts of the variants of each line, in the order of the variants
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub lines: BTreeMap<LineName, Vec<Template>>,
+}
+
+impl NodeTexts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // builder methods
+
+    pub fn with_description<S>(mut self, description: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn with_directive<D>(mut self, directive: D) -> Self
+    where
+        D: Into<Template>,
+    {
+        self.directive = Some(directive.into());
+        self
+    }
+
+    pub fn with_line<N, V>(mut self, name: N, variants: V) -> Self
+    where
+        N: Into<LineName>,
+        V: IntoIterator,
+        V::Item: Into<Template>,
+    {
+        self.lines
+            .insert(name.into(), variants.into_iter().map(Into::into).collect());
+        self
+    }
+
+    fn line(&self, name: &str, variant: usize) -> Option<&Template> {
+        self.lines.get(name)?.get(variant)
+    }
+}
+
+// The texts of one locale, by story id and node key, as returned by `ScenarioGraph::text_key`.
+// Stored as RON, a table reads as
+// `{ "market": { "haggle": (directive: "{buyer.name} marchande"), "#3": (description: "étal") } }`.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct StringTable {
+    stories: BTreeMap<StoryId, BTreeMap<String, NodeTexts>>,
+}
+
+impl StringTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // reads a table stored as RON, such as a translation of an extracted table
+    pub fn load<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        error::load_ron(path.as_ref())
+    }
+
+    // Every translatable text of the graphs, as a table to translate
+    pub fn extract<'a, G>(graphs: G) -> Self
+    where
+        G: IntoIterator<Item = &'a ScenarioGraph>,
+    {
+        let mut table = Self::new();
+        for graph in graphs {
+            for (node_id, action) in graph.nodes() {
+                let texts = NodeTexts {
+                    description: Some(action.description.clone())
+                        .filter(|description| !description.is_empty()),
+                    directive: Some(action.directive.clone())
+                        .filter(|directive| !directive.is_empty()),
+                    lines: action
+                        .lines
+                        .iter()
+                        .map(|(name, variants)| {
+                            let texts = variants.iter().map(|variant| variant.text.clone());
+                            (name.clone(), texts.collect())
+                        })
+                        .collect(),
+                };
+                if texts != NodeTexts::default() {
+                    table.insert(graph.id(), graph.text_key(node_id), texts);
+                }
+            }
+        }
+        table
+    }
+
+    pub fn with<S, K>(mut self, story: S, node_key: K, texts: NodeTexts) -> Self
+    where
+        S: Into<StoryId>,
+        K: Into<String>,
+    {
+        self.insert(story, node_key, texts);
+        self
+    }
+
+    pub fn insert<S, K>(&mut self, story: S, node_key: K, texts: NodeTexts)
+    where
+        S: Into<StoryId>,
+        K: Into<String>,
+    {
+        self.stories
+            .entry(story.into())
+            .or_default()
+            .insert(node_key.into(), texts);
+    }
+
+    pub fn get(&self, story: &str, node_key: &str) -> Option<&NodeTexts> {
+        self.stories.get(story)?.get(node_key)
+    }
+
+    // the number of nodes with texts
+    pub fn len(&self) -> usize {
+        self.stories.values().map(BTreeMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// String tables by locale. A text missing from the table of a locale is looked up along its fallback
+// chain, which is its language for a regional locale without fallbacks, `fr` for `fr-CA`.
+// The text of the node itself comes last.
+#[derive(Default, Debug, Clone)]
+pub struct Localization {
+    tables: HashMap<Locale, StringTable>,
+    fallbacks: HashMap<Locale, Vec<Locale>>,
+}
+
+impl Localization {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // builder methods
+
+    pub fn with_table<L>(mut self, locale: L, table: StringTable) -> Self
+    where
+        L: Into<Locale>,
+    {
+        self.insert_table(locale, table);
+        self
+    }
+
+    pub fn with_fallbacks<L, F>(mut self, locale: L, fallbacks: F) -> Self
+    where
+        L: Into<Locale>,
+        F: IntoIterator,
+        F::Item: Into<Locale>,
+    {
+        self.set_fallbacks(locale, fallbacks);
+        self
+    }
+
+    // replaces the table of the locale, if any
+    pub fn insert_table<L>(&mut self, locale: L, table: StringTable)
+    where
+        L: Into<Locale>,
+    {
+        self.tables.insert(locale.into(), table);
+    }
+
+    pub fn set_fallbacks<L, F>(&mut self, locale: L, fallbacks: F)
+    where
+        L: Into<Locale>,
+        F: IntoIterator,
+        F::Item: Into<Locale>,
+    {
+        self.fallbacks.insert(
+            locale.into(),
+            fallbacks.into_iter().map(Into::into).collect(),
+        );
+    }
+
+    pub fn table(&self, locale: &str) -> Option<&StringTable> {
+        self.tables.get(locale)
+    }
+
+    // the locales whose tables are looked up for `locale`, in order
+    pub fn chain<'a>(&'a self, locale: &'a str) -> Vec<&'a str> {
+        let mut chain = vec![locale];
+        match self.fallbacks.get(locale) {
+            Some(fallbacks) => chain.extend(fallbacks.iter().map(Locale::as_str)),
+            None => chain.extend(locale.split_once('-').map(|(language, _)| language)),
+        }
+        chain
+    }
+
+    // `None` if the graph has no such node
+    pub fn description<'a>(
+        &'a self,
+        graph: &'a ScenarioGraph,
+        node_id: ScenarioActionId,
+        locale: &str,
+    ) -> Option<&'a str> {
+        let action = graph.get(node_id)?;
+        Some(
+            self.node_texts(graph, node_id, Some(locale))
+                .into_iter()
+                .find_map(|texts| texts.description.as_deref())
+                .unwrap_or(&action.description),
+        )
+    }
+
+    // Renders the directive of a node, along with its lines, in a locale.
+    // Alternatives are picked from shuffle bags shared by the thread.
+    pub fn directive(
+        &self,
+        graph: &ScenarioGraph,
+        node_id: ScenarioActionId,
+        locale: &str,
+        alias_map: &AliasMap,
+        context: &NarrativeWorld,
+        filters: &Filters,
+    ) -> Result<String, Error> {
+        self.directive_segments(graph, node_id, locale, alias_map, context, filters)
+            .map(|segments| concat(&segments))
+    }
+
+    pub fn directive_segments(
+        &self,
+        graph: &ScenarioGraph,
+        node_id: ScenarioActionId,
+        locale: &str,
+        alias_map: &AliasMap,
+        context: &NarrativeWorld,
+        filters: &Filters,
+    ) -> Result<Vec<TextSegment>, Error> {
+        let action = graph.get(node_id).ok_or_else(|| Error::Graph {
+            story: Some(graph.id().into()),
+            error: EditError::MissingNode(node_id),
+        })?;
+        draw_from_global_shuffle_bags(&graph.shuffle_owner(node_id), |draws| {
+            action.render_directive(
+                alias_map,
+                context,
+                filters,
+                &self.node_texts(graph, node_id, Some(locale)),
+                draws,
+            )
+        })
+        .map_err(|error| error.in_node(graph, node_id))
+    }
+
+    // the texts of a node along the fallback chain of the locale, none without a locale
+    pub(crate) fn node_texts(
+        &self,
+        graph: &ScenarioGraph,
+        node_id: ScenarioActionId,
+        locale: Option<&str>,
+    ) -> Vec<&NodeTexts> {
+        let Some(locale) = locale else {
+            return vec![];
+        };
+        let node_key = graph.text_key(node_id);
+        self.chain(locale)
+            .into_iter()
+            .filter_map(|locale| self.tables.get(locale)?.get(graph.id(), &node_key))
+            .collect()
+    }
+}
+
+// the translated text of a variant of a line, from the first translation which has it
+pub(crate) fn line_text<'a>(
+    translations: &[&'a NodeTexts],
+    name: &str,
+    variant: usize,
+) -> Option<&'a Template> {
+    translations
+        .iter()
+        .find_map(|texts| texts.line(name, variant))
+}
+
+impl ScenarioGraph {
+    // Identifies a node in string tables, by its name if named, or as `#id` otherwise.
+    // Naming nodes keeps their translations when the graph is rebuilt.
+    pub fn text_key(&self, node_id: ScenarioActionId) -> String {
+        match self.node_name(node_id) {
+            Some(name) => name.to_string(),
+            None => node_id.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::prelude::{Entity, LineVariant, ScenarioAction};
+
+    #[test]
+    fn texts_fall_back_along_the_chain() {
+        let mut graph = ScenarioGraph::new();
+        graph.set_id("market");
+        graph.add_alias("buyer", []);
+        let stall = graph.add(ScenarioAction::new().with_description("stall"));
+        let haggle = graph
+            .add_named(
+                "haggle",
+                ScenarioAction::new()
+                    .with_description("haggle")
+                    .with_directive("{buyer.name} haggles. [retort]")
+                    .with_line("retort", [LineVariant::new("Too much!")]),
+            )
+            .unwrap();
+
+        let table = StringTable::extract([&graph]);
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.get("market", "haggle"),
+            Some(
+                &NodeTexts::new()
+                    .with_description("haggle")
+                    .with_directive("{buyer.name} haggles. [retort]")
+                    .with_line("retort", ["Too much!"])
+            )
+        );
+        let loaded: StringTable = ron::from_str(&ron::to_string(&table).unwrap()).unwrap();
+        assert_eq!(loaded, table);
+
+        let localization = Localization::new()
+            .with_table(
+                "fr",
+                StringTable::new()
+                    .with(
+                        "market",
+                        "haggle",
+                        NodeTexts::new()
+                            .with_description("marchander")
+                            .with_directive("{buyer.name} marchande. [retort]")
+                            .with_line("retort", ["Trop cher !"]),
+                    )
+                    .with("market", "#0", NodeTexts::new().with_description("étal")),
+            )
+            .with_table(
+                "fr-CA",
+                StringTable::new().with(
+                    "market",
+                    "haggle",
+                    NodeTexts::new().with_line("retort", ["Ben voyons !"]),
+                ),
+            )
+            .with_fallbacks("fr-BE", ["fr-CA"]);
+        assert_eq!(localization.chain("fr-CA"), ["fr-CA", "fr"]);
+
+        let mut alias_map = AliasMap::default();
+        alias_map.associate("buyer".into(), 0);
+        let context = NarrativeWorld::new().with_entity(Entity::new(0).with("name", "Ada"));
+        let directive = |locale| {
+            localization
+                .directive(
+                    &graph,
+                    haggle,
+                    locale,
+                    &alias_map,
+                    &context,
+                    &Filters::default(),
+                )
+                .unwrap()
+        };
+        assert_eq!(directive("fr"), "Ada marchande. Trop cher !");
+        assert_eq!(directive("fr-CA"), "Ada marchande. Ben voyons !");
+        // explicit fallbacks replace the language
+        assert_eq!(directive("fr-BE"), "Ada haggles. Ben voyons !");
+        assert_eq!(directive("de"), "Ada haggles. Too much!");
+        assert_eq!(
+            localization.description(&graph, stall, "fr-CA"),
+            Some("étal")
+        );
+        assert_eq!(localization.description(&graph, stall, "de"), Some("stall"));
+
+        graph.remove(stall);
+        assert_eq!(localization.description(&graph, stall, "fr"), None);
+        assert!(matches!(
+            localization.directive(&graph, stall, "fr", &alias_map, &context, &Filters::default()),
+            Err(Error::Graph {
+                error: EditError::MissingNode(node),
+                ..
+            }) if node == stall
+        ));
+    }
+
+    #[test]
+    fn errors_point_to_the_node_and_line() {
+        let mut graph = ScenarioGraph::new();
+        graph.set_id("market");
+        graph.add_alias("buyer", []);
+        graph.add(ScenarioAction::new());
+        let haggle = graph
+            .add_named(
+                "haggle",
+                ScenarioAction::new()
+                    .with_directive("{buyer.name} haggles. [retort]")
+                    .with_line("retort", [LineVariant::new("Too {buyer.gold}!")]),
+            )
+            .unwrap();
+        let mut alias_map = AliasMap::default();
+        alias_map.associate("buyer".into(), 0);
+        let context = NarrativeWorld::new().with_entity(Entity::new(0).with("name", "Ada"));
+
+        let expected = r#"Alias error: Entity "0" bound to "buyer" is missing the property "gold" (story "market", node "haggle" (#1), line "retort" variant 0, at 4..16)"#;
+        let error = Localization::new()
+            .directive(
+                &graph,
+                haggle,
+                "fr",
+                &alias_map,
+                &context,
+                &Filters::default(),
+            )
+            .unwrap_err();
+        assert_eq!(error.to_string(), expected);
+        let error = graph.directive(haggle, &alias_map, &context).unwrap_err();
+        assert_eq!(error.to_string(), expected);
+        // the action alone doesn't know its story nor node
+        let error = graph
+            .get(haggle)
+            .unwrap()
+            .directive(&alias_map, &context)
+            .unwrap_err();
+        assert_eq!(
+            error.location().unwrap().line,
+            Some(("retort".to_string(), 0))
+        );
+        assert_eq!(error.location().unwrap().story, None);
+    }
+}
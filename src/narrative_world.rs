@@ -4,6 +4,7 @@ use std::collections::{HashMap, HashSet};
 use crate::{
     entity::{Entity, EntityId},
     property::{Property, PropertyMap, PropertyName},
+    scenario_graph::StoryId,
 };
 
 // key is a pair of ids, value is property from POV of 1st entity
@@ -14,7 +15,7 @@ pub struct NarrativeWorld {
     entities: HashMap<EntityId, Entity>, // characters, items, locations ... matched against alias_constraints
     relations: RelationMap,
     properties: PropertyMap, // miscellanious world variables, matched agains world_constraints
-                             // exclude: HashSet<StoryId>,
+    exclude: HashSet<StoryId>,
 }
 
 impl NarrativeWorld {
@@ -62,13 +63,18 @@ impl NarrativeWorld {
         self
     }
 
-    // pub fn exclude(&mut self, story_ids: &[StoryId]) {
-    //     self.exclude.extend(story_ids);
-    // }
+    pub fn exclude<I, S>(&mut self, story_ids: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<StoryId>,
+    {
+        self.exclude
+            .extend(story_ids.into_iter().map(|story_id| story_id.into()));
+    }
 
-    // pub(crate) fn is_included(&self, story_id: &StoryId) -> bool {
-    //     !self.exclude.contains(story_id)
-    // }
+    pub(crate) fn is_included(&self, story_id: &str) -> bool {
+        !self.exclude.contains(story_id)
+    }
 
     pub(crate) fn entity(&self, id: EntityId) -> Option<&Entity> {
         self.entities.get(&id)
@@ -86,7 +92,7 @@ impl NarrativeWorld {
         &self.properties
     }
 
-    pub(crate) fn relations(&self) -> &RelationMap {
-        &self.relations
+    pub(crate) fn relation(&self, me: EntityId, other: EntityId) -> Option<&PropertyMap> {
+        self.relations.get(&(me, other))
     }
 }
@@ -0,0 +1,56 @@
+use crate::{
+    entity::EntityId, scenario_action::Alias, scenario_graph::AliasMap, tag_expr::TagExpr,
+};
+
+// Options narrowing down the results of a query.
+// Results come ordered by story, in insertion order, then by binding. Bindings are ordered by entity id,
+// binding the most constrained aliases first, so the same world always yields the same results.
+#[derive(Default, Clone, Debug)]
+pub struct QueryOptions {
+    pub(crate) per_story_limit: Option<usize>,
+    pub(crate) limit: Option<usize>,
+    pub(crate) pinned_aliases: AliasMap,
+    pub(crate) focus: Vec<EntityId>,
+    pub(crate) tags: Option<TagExpr>,
+}
+
+impl QueryOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // builder methods
+
+    // keeps at most the first `limit` bindings of each story
+    pub fn with_per_story_limit(mut self, limit: usize) -> Self {
+        self.per_story_limit = Some(limit);
+        self
+    }
+
+    // stops the query after `limit` scenarios overall
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    // binds `alias` to `entity` ahead of time, stories without that alias are skipped
+    pub fn with_pinned_alias<A>(mut self, alias: A, entity: EntityId) -> Self
+    where
+        A: Into<Alias>,
+    {
+        self.pinned_aliases.associate(alias.into(), entity);
+        self
+    }
+
+    // only keeps bindings where `entity` is bound to one of the aliases
+    pub fn with_focus(mut self, entity: EntityId) -> Self {
+        self.focus.push(entity);
+        self
+    }
+
+    // only queries stories whose tags satisfy `tags`, see `TagExpr::parse`
+    pub fn with_tags(mut self, tags: TagExpr) -> Self {
+        self.tags = Some(tags);
+        self
+    }
+}
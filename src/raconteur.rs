@@ -1,17 +1,31 @@
-use std::rc::Rc;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use itertools::Itertools;
 
 use crate::{
+    error::Error,
+    explain::Explanation,
+    filter::Filters,
+    lint::{self, Diagnostic},
+    locale::{Localization, StringTable},
     narrative_world::NarrativeWorld,
     prelude::Scenario,
-    scenario_graph::{AliasMap, ScenarioGraph},
+    query::QueryOptions,
+    scenario_graph::{ScenarioGraph, StoryId, StoryLibrary},
+    shuffle_bag::ShuffleBags,
 };
 
 // #[derive(Serialize, Deserialize)]
 #[derive(Default)]
 pub struct Raconteur {
+    // kept in insertion order, which is also the order of query results
     stories: Vec<Rc<ScenarioGraph>>,
+    next_anonymous_id: usize,
+    // shared with the scenarios to render their directives
+    filters: Rc<Filters>,
+    localization: Rc<Localization>,
+    // shared by every scenario when set
+    shuffle_bags: Option<Rc<RefCell<ShuffleBags>>>,
 }
 
 impl Raconteur {
@@ -19,56 +33,688 @@ impl Raconteur {
         Self::default()
     }
 
-    pub fn insert(&mut self, scenario_graph: ScenarioGraph) {
+    // Inserts a story and returns its id. A story without an id is given one, while a story whose id
+    // is already taken is reported as `Error::DuplicateStory`, such as when two content files
+    // declare the same story; `replace` replaces a story.
+    pub fn insert(&mut self, mut scenario_graph: ScenarioGraph) -> Result<StoryId, Error> {
+        if scenario_graph.id().is_empty() {
+            loop {
+                let id = format!("#{}", self.next_anonymous_id);
+                self.next_anonymous_id += 1;
+                if self.position(&id).is_none() {
+                    scenario_graph.set_id(id);
+                    break;
+                }
+            }
+        }
+
+        let id = scenario_graph.id().to_string();
+        if self.position(&id).is_some() {
+            return Err(Error::DuplicateStory { story: id });
+        }
         self.stories.push(Rc::new(scenario_graph));
+        Ok(id)
+    }
+
+    // Replaces the story `story_id`, which keeps its id and position. Returns the previous story,
+    // or `None` if there was no such story, in which case nothing is inserted.
+    pub fn replace(
+        &mut self,
+        story_id: &str,
+        mut scenario_graph: ScenarioGraph,
+    ) -> Option<Rc<ScenarioGraph>> {
+        let index = self.position(story_id)?;
+        scenario_graph.set_id(story_id);
+        Some(std::mem::replace(
+            &mut self.stories[index],
+            Rc::new(scenario_graph),
+        ))
+    }
+
+    // Scenarios already returned by a query keep their story alive
+    pub fn remove(&mut self, story_id: &str) -> Option<Rc<ScenarioGraph>> {
+        self.position(story_id)
+            .map(|index| self.stories.remove(index))
+    }
+
+    pub fn get(&self, story_id: &str) -> Option<&ScenarioGraph> {
+        self.position(story_id)
+            .map(|index| self.stories[index].as_ref())
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.stories.iter().map(|story| story.id())
+    }
+
+    pub fn len(&self) -> usize {
+        self.stories.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stories.is_empty()
+    }
+
+    pub fn filters(&self) -> &Filters {
+        &self.filters
+    }
+
+    // Registers the filters of a game, such as `raconteur.filters_mut().insert("shout", ...)`.
+    // Scenarios already returned by a query keep the filters they were given.
+    pub fn filters_mut(&mut self) -> &mut Filters {
+        Rc::make_mut(&mut self.filters)
+    }
+
+    pub fn localization(&self) -> &Localization {
+        &self.localization
+    }
+
+    // Loads the string tables of a game, such as `raconteur.localization_mut().insert_table("fr", table)`.
+    // Scenarios render their choices in the locale set with `Scenario::set_locale`.
+    pub fn localization_mut(&mut self) -> &mut Localization {
+        Rc::make_mut(&mut self.localization)
+    }
+
+    // Scenarios returned by later queries share these bags, so that the alternatives of directives don't
+    // repeat across scenarios. Each scenario has its own bags otherwise.
+    pub fn share_shuffle_bags(&mut self, shuffle_bags: Option<Rc<RefCell<ShuffleBags>>>) {
+        self.shuffle_bags = shuffle_bags;
+    }
+
+    pub fn shared_shuffle_bags(&self) -> Option<&Rc<RefCell<ShuffleBags>>> {
+        self.shuffle_bags.as_ref()
+    }
+
+    // every translatable text of the stories, as a table to translate
+    pub fn extract_strings(&self) -> StringTable {
+        StringTable::extract(self.stories.iter().map(Rc::as_ref))
+    }
+
+    fn position(&self, story_id: &str) -> Option<usize> {
+        self.stories.iter().position(|story| story.id() == story_id)
+    }
+
+    // the stories a story calls into, directly or through other sub-scenarios
+    fn sub_stories(&self, scenario_graph: &ScenarioGraph) -> HashMap<StoryId, Rc<ScenarioGraph>> {
+        let mut sub_stories = HashMap::new();
+        let mut stack = vec![scenario_graph];
+        while let Some(graph) = stack.pop() {
+            for story_id in graph.sub_scenario_ids() {
+                if sub_stories.contains_key(story_id) {
+                    continue;
+                }
+                if let Some(index) = self.position(story_id) {
+                    let story = &self.stories[index];
+                    sub_stories.insert(story_id.to_string(), Rc::clone(story));
+                    stack.push(story);
+                }
+            }
+        }
+        sub_stories
     }
 
     pub fn query(&self, context: &NarrativeWorld) -> Vec<Scenario> {
-        // go through list of story beats, discarding those whose constraints aren't satisfied
+        self.query_with(context, &QueryOptions::default())
+            .collect_vec()
+    }
+
+    // Lazily goes through the list of story beats, discarding those whose constraints aren't satisfied.
+    // Stories are only evaluated as the iterator is advanced, so dropping it early skips the remaining work.
+    pub fn query_with<'a>(
+        &'a self,
+        context: &'a NarrativeWorld,
+        options: &'a QueryOptions,
+    ) -> impl Iterator<Item = Scenario> + 'a {
+        let per_story_limit = options.per_story_limit.unwrap_or(usize::MAX);
+        let limit = options.limit.unwrap_or(usize::MAX);
 
         self.stories
             .iter()
-            .enumerate()
-            // .filter(|&(index, _)| context.is_included(&StoryId(index)))
-            .filter_map(|(index, scenario_graph)| {
-                let result = scenario_graph.alias_candidates(context);
-
-                result.ok().map(|alias_candidates| {
-                    alias_candidates
-                        .iter()
-                        .map(|alias_map| {
-                            Scenario::new(index, Rc::clone(scenario_graph), alias_map.clone())
-                        })
-                        .collect_vec()
-                })
+            .filter(|scenario_graph| context.is_included(scenario_graph.id()))
+            .filter(|scenario_graph| {
+                options
+                    .tags
+                    .as_ref()
+                    .is_none_or(|tags| tags.is_satisfied_by(scenario_graph.tags()))
             })
-            .flatten()
-            .collect_vec()
+            .flat_map(move |scenario_graph| {
+                let sub_stories = Rc::new(self.sub_stories(scenario_graph));
+                scenario_graph
+                    .valid_alias_bindings_in(context, options, Some(self), 0)
+                    .take(per_story_limit)
+                    .map(move |alias_map| {
+                        let scenario = Scenario::new(Rc::clone(scenario_graph), alias_map)
+                            .with_sub_stories(Rc::clone(&sub_stories))
+                            .with_filters(Rc::clone(&self.filters))
+                            .with_localization(Rc::clone(&self.localization));
+                        match &self.shuffle_bags {
+                            Some(shuffle_bags) => {
+                                scenario.with_shuffle_bags(Rc::clone(shuffle_bags))
+                            }
+                            None => scenario,
+                        }
+                    })
+            })
+            .take(limit)
+    }
+
+    // Same as `ScenarioGraph::explain`, also checking the sub-scenarios along each path.
+    // `None` if there is no such story.
+    pub fn explain(&self, story_id: &str, context: &NarrativeWorld) -> Option<Explanation> {
+        self.explain_with(story_id, context, &QueryOptions::default())
+    }
+
+    pub fn explain_with(
+        &self,
+        story_id: &str,
+        context: &NarrativeWorld,
+        options: &QueryOptions,
+    ) -> Option<Explanation> {
+        self.get(story_id)
+            .map(|story| Explanation::new(story, context, options, Some(self)))
+    }
+
+    // Lints every story, along with the sub-scenarios they call into
+    pub fn validate(&self) -> Vec<(StoryId, Diagnostic)> {
+        self.stories
+            .iter()
+            .flat_map(|story| {
+                story
+                    .validate()
+                    .into_iter()
+                    .chain(lint::validate_sub_scenarios(story, self))
+                    .chain(lint::validate_filters(story, &self.filters))
+                    .map(|diagnostic| (story.id().to_string(), diagnostic))
+            })
+            .collect()
+    }
+}
+
+impl StoryLibrary for Raconteur {
+    fn story(&self, story_id: &str) -> Option<&ScenarioGraph> {
+        self.get(story_id)
     }
 }
 
 #[cfg(test)]
 mod unit_tests {
-    use crate::prelude::{NarrativeWorld, ScenarioAction, ScenarioGraph};
+    use std::rc::Rc;
+
+    use itertools::Itertools;
+
+    use crate::prelude::{
+        Constraint, Entity, NarrativeWorld, NodeTexts, PathFailure, QueryOptions, Scenario,
+        ScenarioAction, ScenarioGraph,
+    };
 
-    use super::Raconteur;
+    use petgraph::prelude::NodeIndex;
+
+    use super::{Error, Raconteur};
+    use crate::lint::Diagnostic;
 
     #[test]
     fn a_story_can_be_excluded_from_the_query_result() {
         let mut raconteur = Raconteur::new();
-        raconteur.insert({
-            let mut graph = ScenarioGraph::new();
-            let a = graph.add(ScenarioAction::new());
-            graph.set_start_node(a);
-            graph
-        });
+        raconteur
+            .insert({
+                let mut graph = ScenarioGraph::new();
+                let a = graph.add(ScenarioAction::new());
+                graph.set_start_node(a);
+                graph
+            })
+            .unwrap();
 
         let mut context = NarrativeWorld::new();
 
         let stories = raconteur.query(&context);
         assert!(!stories.is_empty());
-        // context.exclude(&[stories[0].id]);
+        context.exclude([stories[0].id()]);
         let stories = raconteur.query(&context);
         assert!(stories.is_empty());
     }
+
+    fn villager_stories(count: usize) -> Raconteur {
+        let mut raconteur = Raconteur::new();
+        for index in 0..count {
+            raconteur
+                .insert({
+                    let mut graph = ScenarioGraph::new();
+                    graph.set_id(format!("story {}", index));
+                    graph.add_alias("speaker", [Constraint::has("villager")]);
+                    graph.add_alias("listener", [Constraint::has("villager")]);
+                    let a = graph.add(ScenarioAction::new());
+                    graph.set_start_node(a);
+                    graph
+                })
+                .unwrap();
+        }
+        raconteur
+    }
+
+    fn village() -> NarrativeWorld {
+        NarrativeWorld::new()
+            .with_entities((0..10).rev().map(|id| Entity::new(id).with("villager", "")))
+    }
+
+    #[test]
+    fn query_results_are_ordered_by_story_then_binding() {
+        let raconteur = villager_stories(3);
+        let context = village();
+
+        let stories = raconteur.query(&context);
+        assert_eq!(stories.len(), 3 * 10 * 9);
+
+        let order = stories
+            .iter()
+            .map(|story| {
+                (
+                    story.id().to_string(),
+                    story.alias_map.get("speaker").unwrap(),
+                    story.alias_map.get("listener").unwrap(),
+                )
+            })
+            .collect_vec();
+        assert!(order.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let again = raconteur
+            .query(&context)
+            .iter()
+            .map(|story| {
+                (
+                    story.id().to_string(),
+                    story.alias_map.get("speaker").unwrap(),
+                    story.alias_map.get("listener").unwrap(),
+                )
+            })
+            .collect_vec();
+        assert_eq!(order, again);
+    }
+
+    #[test]
+    fn query_results_can_be_bounded() {
+        let raconteur = villager_stories(3);
+        let context = village();
+
+        let stories = raconteur
+            .query_with(&context, &QueryOptions::new().with_per_story_limit(2))
+            .map(|story| story.id().to_string())
+            .collect_vec();
+        assert_eq!(
+            stories,
+            ["story 0", "story 0", "story 1", "story 1", "story 2", "story 2"]
+        );
+
+        let stories = raconteur
+            .query_with(
+                &context,
+                &QueryOptions::new().with_per_story_limit(2).with_limit(3),
+            )
+            .map(|story| story.id().to_string())
+            .collect_vec();
+        assert_eq!(stories, ["story 0", "story 0", "story 1"]);
+    }
+
+    fn tagged_story(id: &str, tags: &[&str]) -> ScenarioGraph {
+        let mut graph = ScenarioGraph::new();
+        graph.set_id(id);
+        for &tag in tags {
+            graph.add_tag(tag);
+        }
+        let a = graph.add(ScenarioAction::new());
+        graph.set_start_node(a);
+        graph
+    }
+
+    #[test]
+    fn stories_are_managed_by_id() {
+        let mut raconteur = Raconteur::new();
+        let anonymous = raconteur.insert(ScenarioGraph::new()).unwrap();
+        raconteur.insert(tagged_story("brawl", &[])).unwrap();
+        raconteur.insert(tagged_story("toast", &[])).unwrap();
+        assert_eq!(
+            raconteur.ids().collect_vec(),
+            [&anonymous, "brawl", "toast"]
+        );
+
+        let previous = raconteur.replace("brawl", tagged_story("ignored id", &["combat"]));
+        assert!(previous.is_some_and(|story| story.tags().is_empty()));
+        assert!(raconteur.get("brawl").unwrap().has_tag("combat"));
+        assert!(raconteur.get("ignored id").is_none());
+        assert!(raconteur.replace("missing", ScenarioGraph::new()).is_none());
+
+        // ids collide, the story in place is kept
+        assert!(matches!(
+            raconteur.insert(tagged_story("toast", &["tavern"])),
+            Err(Error::DuplicateStory { story }) if story == "toast"
+        ));
+        assert_eq!(raconteur.len(), 3);
+        assert!(!raconteur.get("toast").unwrap().has_tag("tavern"));
+
+        assert!(raconteur.remove("brawl").is_some());
+        assert!(raconteur.remove("brawl").is_none());
+        assert_eq!(raconteur.ids().collect_vec(), [&anonymous, "toast"]);
+    }
+
+    #[test]
+    fn queries_can_be_filtered_by_tags() {
+        let mut raconteur = Raconteur::new();
+        raconteur
+            .insert(tagged_story("brawl", &["tavern", "combat"]))
+            .unwrap();
+        raconteur
+            .insert(tagged_story("toast", &["tavern", "festive"]))
+            .unwrap();
+        raconteur.insert(tagged_story("duel", &["combat"])).unwrap();
+
+        let context = NarrativeWorld::new();
+        let options = QueryOptions::new().with_tags("tavern AND NOT combat".parse().unwrap());
+        let stories = raconteur
+            .query_with(&context, &options)
+            .map(|story| story.id().to_string())
+            .collect_vec();
+        assert_eq!(stories, ["toast"]);
+    }
+
+    fn greeting() -> ScenarioGraph {
+        let mut graph = ScenarioGraph::new();
+        graph.set_id("greeting");
+        graph.add_alias("host", [Constraint::has("villager")]);
+        graph.add_alias("guest", []);
+        let a = graph.add(ScenarioAction::new());
+        graph.set_start_node(a);
+        graph
+    }
+
+    fn market(calls: &str, mapping: [(&str, &str); 2]) -> ScenarioGraph {
+        let mut graph = ScenarioGraph::new();
+        graph.set_id("market");
+        graph.add_alias("merchant", []);
+        graph.add_alias("buyer", []);
+        let a = graph.add(ScenarioAction::new());
+        let b = graph.add(ScenarioAction::new().with_sub_scenario(calls, mapping));
+        graph.set_start_node(a);
+        graph.connect(a, b).unwrap();
+        graph
+    }
+
+    #[test]
+    fn sub_scenarios_must_be_playable_with_the_parent_bindings() {
+        let mut raconteur = Raconteur::new();
+        raconteur.insert(greeting()).unwrap();
+        raconteur
+            .insert(market(
+                "greeting",
+                [("merchant", "host"), ("buyer", "guest")],
+            ))
+            .unwrap();
+
+        let context = NarrativeWorld::new()
+            .with_entity(Entity::new(0).with("villager", ""))
+            .with_entity(Entity::new(1));
+        let markets = raconteur
+            .query(&context)
+            .into_iter()
+            .filter(|scenario| scenario.id() == "market")
+            .map(|scenario| {
+                (
+                    scenario.alias_map.get("merchant").unwrap(),
+                    scenario.alias_map.get("buyer").unwrap(),
+                )
+            })
+            .collect_vec();
+        // only the villager can host the greeting
+        assert_eq!(markets, [(0, 1)]);
+
+        // standalone, the sub-scenario isn't checked
+        let market = raconteur.get("market").unwrap();
+        assert_eq!(
+            market
+                .valid_alias_bindings(&context, &QueryOptions::default())
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn explanations_check_sub_scenarios() {
+        let mut raconteur = Raconteur::new();
+        raconteur.insert(greeting()).unwrap();
+        raconteur
+            .insert(market(
+                "greeting",
+                [("merchant", "host"), ("buyer", "guest")],
+            ))
+            .unwrap();
+
+        // nobody can host the greeting
+        let context = NarrativeWorld::new()
+            .with_entity(Entity::new(0))
+            .with_entity(Entity::new(1));
+        let explanation = raconteur.explain("market", &context).unwrap();
+        assert!(!explanation.matched);
+        assert_eq!(explanation.bindings, 2);
+        assert_eq!(
+            explanation.paths[0].failures,
+            [PathFailure::SubScenario {
+                node: NodeIndex::new(1).into(),
+                story: "greeting".into(),
+                playable: 0,
+            }]
+        );
+
+        // standalone, the sub-scenario isn't checked
+        assert!(raconteur.get("market").unwrap().explain(&context).matched);
+        assert!(raconteur.explain("tavern", &context).is_none());
+    }
+
+    #[test]
+    fn sub_scenario_problems_are_reported() {
+        let mut raconteur = Raconteur::new();
+        raconteur.insert(greeting()).unwrap();
+        raconteur
+            .insert(market(
+                "greeting",
+                [("seller", "host"), ("buyer", "visitor")],
+            ))
+            .unwrap();
+        assert_eq!(
+            raconteur.validate(),
+            [
+                (
+                    "market".to_string(),
+                    Diagnostic::UndeclaredSubScenarioAlias {
+                        node: NodeIndex::new(1).into(),
+                        alias: "seller".into()
+                    }
+                ),
+                (
+                    "market".to_string(),
+                    Diagnostic::UnknownSubScenarioAlias {
+                        node: NodeIndex::new(1).into(),
+                        story: "greeting".into(),
+                        alias: "visitor".into()
+                    }
+                ),
+            ]
+        );
+
+        // market and tavern call into each other
+        let mapping = [("merchant", "merchant"), ("buyer", "buyer")];
+        let mut tavern = market("market", mapping);
+        tavern.set_id("tavern");
+        raconteur.insert(tavern).unwrap();
+        raconteur.replace("market", market("tavern", mapping));
+        let diagnostics = raconteur.validate();
+        for (caller, callee) in [("market", "tavern"), ("tavern", "market")] {
+            assert!(diagnostics.contains(&(
+                caller.to_string(),
+                Diagnostic::RecursiveSubScenario {
+                    node: NodeIndex::new(1).into(),
+                    story: callee.into()
+                }
+            )));
+        }
+        assert!(!diagnostics
+            .iter()
+            .any(|(_, diagnostic)| matches!(diagnostic, Diagnostic::UnknownSubScenario { .. })));
+    }
+
+    #[test]
+    fn games_register_their_own_filters() {
+        let mut raconteur = Raconteur::new();
+        raconteur
+            .insert({
+                let mut graph = ScenarioGraph::new();
+                graph.set_id("battle cry");
+                graph.add_alias("hero", [Constraint::has("name")]);
+                let a = graph.add(ScenarioAction::new());
+                let b = graph.add(ScenarioAction::new().with_directive(
+                    "{hero.name|shout|possessive} cry, {hero.name|he/she} {<a>hero|he/she}",
+                ));
+                graph.set_start_node(a);
+                graph.connect(a, b).unwrap();
+                graph
+            })
+            .unwrap();
+        let context = NarrativeWorld::new().with_entity(Entity::new(0).with("name", "Brann"));
+
+        // choices only apply to aliases
+        let unknown_filter = |filter: &str| {
+            (
+                "battle cry".to_string(),
+                Diagnostic::UnknownFilter {
+                    node: NodeIndex::new(1).into(),
+                    filter: filter.into(),
+                },
+            )
+        };
+        assert_eq!(
+            raconteur.validate(),
+            [unknown_filter("shout"), unknown_filter("he/she")]
+        );
+        let mut story = raconteur.get("battle cry").unwrap().clone();
+        story.replace(
+            NodeIndex::new(1).into(),
+            ScenarioAction::new().with_directive("{hero.name|shout|possessive} cry"),
+        );
+        raconteur.replace("battle cry", story);
+
+        raconteur.filters_mut().insert("shout", |value| {
+            Ok(format!("{}!", value.to_string().to_uppercase()).into())
+        });
+        assert!(raconteur.validate().is_empty());
+        let scenario = raconteur.query(&context).remove(0);
+        assert_eq!(
+            scenario.choices(&context).unwrap()[0].directive,
+            "BRANN!'s cry"
+        );
+    }
+
+    #[test]
+    fn scenarios_render_in_their_locale() {
+        let mut raconteur = Raconteur::new();
+        raconteur
+            .insert({
+                let mut graph = ScenarioGraph::new();
+                graph.set_id("toast");
+                graph.add_alias("host", [Constraint::has("name")]);
+                let a = graph.add(ScenarioAction::new());
+                let b = graph
+                    .add_named(
+                        "raise",
+                        ScenarioAction::new()
+                            .with_description("raise a glass")
+                            .with_directive("{host.name} raises a glass"),
+                    )
+                    .unwrap();
+                graph.set_start_node(a);
+                graph.connect(a, b).unwrap();
+                graph
+            })
+            .unwrap();
+        let context = NarrativeWorld::new().with_entity(Entity::new(0).with("name", "Brann"));
+
+        let mut table = raconteur.extract_strings();
+        assert_eq!(table.len(), 1);
+        table.insert(
+            "toast",
+            "raise",
+            NodeTexts::new().with_directive("{host.name} lève son verre"),
+        );
+        raconteur.localization_mut().insert_table("fr", table);
+
+        let mut scenario = raconteur.query(&context).remove(0);
+        assert_eq!(
+            scenario.choices(&context).unwrap()[0].directive,
+            "Brann raises a glass"
+        );
+        scenario.set_locale(Some("fr-CA".into()));
+        let choice = scenario.choices(&context).unwrap().remove(0);
+        assert_eq!(choice.directive, "Brann lève son verre");
+        assert_eq!(choice.description, "raise a glass");
+    }
+
+    #[test]
+    fn alternatives_do_not_repeat_across_shared_scenarios() {
+        let mut raconteur = Raconteur::new();
+        raconteur
+            .insert({
+                let mut graph = ScenarioGraph::new();
+                graph.add_alias("guard", [Constraint::has("guard")]);
+                let a = graph.add(ScenarioAction::new().with_description("wait"));
+                let b =
+                    graph.add(ScenarioAction::new().with_directive("{~Halt|Stop|Who goes there}!"));
+                let c = graph.add(ScenarioAction::new().with_description("leave"));
+                graph.set_start_node(a);
+                graph.connect(a, b).unwrap();
+                graph.connect(b, c).unwrap();
+                graph.connect_weak(b, a).unwrap();
+                graph
+            })
+            .unwrap();
+        let context = NarrativeWorld::new()
+            .with_entity(Entity::new(0).with("guard", ""))
+            .with_entity(Entity::new(1).with("guard", ""))
+            .with_entity(Entity::new(2).with("guard", ""));
+        // barks once, and goes back to the start
+        let bark = |scenario: &mut Scenario| {
+            let choice = scenario.choices(&context).unwrap().remove(0);
+            let directive = choice.directive.clone();
+            scenario.choose(choice).unwrap();
+            let back = scenario
+                .choices(&context)
+                .unwrap()
+                .into_iter()
+                .find(|choice| choice.description == "wait")
+                .unwrap();
+            scenario.choose(back).unwrap();
+            directive
+        };
+
+        // listing the choices draws nothing, choosing does
+        let mut scenario = raconteur.query(&context).remove(0);
+        let listed = scenario.choices(&context).unwrap().remove(0).directive;
+        assert_eq!(scenario.choices(&context).unwrap()[0].directive, listed);
+        assert_eq!(bark(&mut scenario), listed);
+        let own = (0..2)
+            .map(|_| bark(&mut scenario))
+            .chain([listed])
+            .sorted()
+            .collect_vec();
+        assert_eq!(own, ["Halt!", "Stop!", "Who goes there!"]);
+
+        // each scenario has its own bags, seeded from its bindings
+        let barks =
+            |mut scenarios: Vec<Scenario>| scenarios.iter_mut().map(bark).sorted().collect_vec();
+        assert!(barks(raconteur.query(&context)).into_iter().dedup().count() > 1);
+        assert_eq!(
+            barks(raconteur.query(&context)),
+            barks(raconteur.query(&context))
+        );
+
+        raconteur.share_shuffle_bags(Some(Rc::default()));
+        assert_eq!(
+            barks(raconteur.query(&context)),
+            ["Halt!", "Stop!", "Who goes there!"]
+        );
+    }
 }
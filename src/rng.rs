@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+// Small seeded pseudo random number generator (SplitMix64).
+// The same seed always yields the same sequence on every platform, which keeps replays deterministic.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // uniformly distributed in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // uniformly distributed in [0, bound), bound must not be 0
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_f64() * bound as f64) as usize
+    }
+
+    // Picks an index with a probability proportional to its weight, negative weights counting as 0.
+    // Falls back to a uniform pick when no weight is positive. Returns `None` when `weights` is empty.
+    pub fn weighted_index<I>(&mut self, weights: I) -> Option<usize>
+    where
+        I: IntoIterator<Item = f64>,
+    {
+        let weights: Vec<f64> = weights.into_iter().map(|weight| weight.max(0.0)).collect();
+        if weights.is_empty() {
+            return None;
+        }
+
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return Some(self.below(weights.len()));
+        }
+
+        let mut target = self.next_f64() * total;
+        for (index, weight) in weights.iter().enumerate() {
+            if target < *weight {
+                return Some(index);
+            }
+            target -= weight;
+        }
+        // rounding errors, settle for the last positive weight
+        weights.iter().rposition(|&weight| weight > 0.0)
+    }
+}
+
+// A seed derived from some bytes, the same on every platform (FNV-1a)
+pub(crate) fn seed_from(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl Default for SeededRng {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::SeededRng;
+
+    #[test]
+    fn the_same_seed_yields_the_same_sequence() {
+        let mut a = SeededRng::new(42);
+        let mut b = SeededRng::new(42);
+        let mut c = SeededRng::new(43);
+        let a = (0..8).map(|_| a.next_u64()).collect::<Vec<_>>();
+        let b = (0..8).map(|_| b.next_u64()).collect::<Vec<_>>();
+        let c = (0..8).map(|_| c.next_u64()).collect::<Vec<_>>();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn weighted_picks_follow_weights() {
+        let mut rng = SeededRng::new(7);
+        let mut counts = [0; 3];
+        for _ in 0..10000 {
+            counts[rng.weighted_index([0.6, 0.4, 0.0]).unwrap()] += 1;
+        }
+        assert_eq!(counts[2], 0);
+        assert!((5500..6500).contains(&counts[0]), "{:?}", counts);
+
+        assert_eq!(rng.weighted_index([]), None);
+        let mut counts = [0; 2];
+        for _ in 0..1000 {
+            counts[rng.weighted_index([0.0, 0.0]).unwrap()] += 1;
+        }
+        assert!(counts.iter().all(|&count| count > 400), "{:?}", counts);
+    }
+}
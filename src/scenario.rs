@@ -1,65 +1,713 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use itertools::Itertools;
 
 use crate::{
+    error::Error,
+    filter::Filters,
+    locale::{Locale, Localization},
     narrative_world::NarrativeWorld,
     prelude::ScenarioGraph,
-    scenario_graph::{AliasMap, ScenarioActionId},
+    rng::{seed_from, SeededRng},
+    scenario_graph::{AliasMap, ScenarioActionId, StoryId, MAX_SUB_SCENARIO_DEPTH},
+    shuffle_bag::{Draws, ShuffleBags},
+    template::{concat, TextSegment},
+    visits::Visits,
 };
 
 pub struct ScenarioChoice {
     id: ScenarioActionId,
+    // the sub-scenario the choice belongs to, 0 being the scenario itself
+    depth: usize,
+    // the story and node the choice was offered at, to tell stale choices apart
+    story: StoryId,
+    from: ScenarioActionId,
+    // the sub-scenario entered by choosing a call node
+    call: Option<Call>,
+    // including the local aliases bound by entering the node
+    alias_map: AliasMap,
+    pub weight: f64,
     pub description: String,
     pub directive: String,
+    // the directive as typed segments, such as to make entities clickable
+    pub directive_segments: Vec<TextSegment>,
+    // the alternatives picked by the directive, only kept once the choice is made
+    drawn: ShuffleBags,
+}
+
+// A sub-scenario being played, which returns to its caller once it reaches a leaf
+struct Call {
+    graph: Rc<ScenarioGraph>,
+    alias_map: AliasMap,
+    current: ScenarioActionId,
+    visits: Visits,
+}
+
+impl Call {
+    fn new(graph: Rc<ScenarioGraph>, alias_map: AliasMap) -> Self {
+        let current = graph.start();
+        let mut visits = Visits::default();
+        visits.enter(current);
+        Self {
+            graph,
+            alias_map,
+            current,
+            visits,
+        }
+    }
 }
 
 pub struct Scenario {
-    id: usize,
     pub weight: f32,
     graph: Rc<ScenarioGraph>,
     pub alias_map: AliasMap,
     current_action: RefCell<ScenarioActionId>,
+    visits: Visits,
+    // stories which sub-scenarios call into, by id
+    sub_stories: Rc<HashMap<StoryId, Rc<ScenarioGraph>>>,
+    calls: Vec<Call>,
+    // used to render directives
+    filters: Rc<Filters>,
+    localization: Rc<Localization>,
+    // texts are rendered as written without a locale
+    locale: Option<Locale>,
+    // the alternatives picked by the directives, shared with other scenarios or not
+    shuffle_bags: Rc<RefCell<ShuffleBags>>,
 }
 
 impl Scenario {
-    pub fn new(id: usize, graph: Rc<ScenarioGraph>, alias_map: AliasMap) -> Self {
+    pub fn new(graph: Rc<ScenarioGraph>, alias_map: AliasMap) -> Self {
         let start_action = graph.start();
+        let mut visits = Visits::default();
+        visits.enter(start_action);
+        let shuffle_bags = ShuffleBags::new(shuffle_seed(&graph, &alias_map));
         Self {
-            id,
             weight: graph.num_alias_constraints() as f32,
             graph,
             alias_map,
             current_action: RefCell::new(start_action),
+            visits,
+            sub_stories: Rc::default(),
+            calls: vec![],
+            filters: Rc::default(),
+            localization: Rc::default(),
+            locale: None,
+            shuffle_bags: Rc::new(RefCell::new(shuffle_bags)),
         }
     }
 
-    pub fn id(&self) -> usize {
-        self.id
+    // Without its sub-stories, a scenario never offers the nodes calling into them
+    pub fn with_sub_stories(
+        mut self,
+        sub_stories: Rc<HashMap<StoryId, Rc<ScenarioGraph>>>,
+    ) -> Self {
+        self.sub_stories = sub_stories;
+        self
+    }
+
+    // Directives are rendered with the built-in filters otherwise
+    pub fn with_filters(mut self, filters: Rc<Filters>) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    // the string tables the texts of choices are translated with, once a locale is set
+    pub fn with_localization(mut self, localization: Rc<Localization>) -> Self {
+        self.localization = localization;
+        self
+    }
+
+    // Shares shuffle bags with other scenarios, so that the alternatives of their directives don't repeat
+    // across scenarios. Each scenario has its own bags otherwise, seeded from its story and the
+    // entities bound to its aliases.
+    pub fn with_shuffle_bags(mut self, shuffle_bags: Rc<RefCell<ShuffleBags>>) -> Self {
+        self.shuffle_bags = shuffle_bags;
+        self
+    }
+
+    pub fn shuffle_bags(&self) -> &Rc<RefCell<ShuffleBags>> {
+        &self.shuffle_bags
+    }
+
+    pub fn with_locale<L>(mut self, locale: L) -> Self
+    where
+        L: Into<Locale>,
+    {
+        self.set_locale(Some(locale.into()));
+        self
+    }
+
+    // the locale choices are rendered in, `None` for the texts as written
+    pub fn set_locale(&mut self, locale: Option<Locale>) {
+        self.locale = locale;
+    }
+
+    pub fn locale(&self) -> Option<&str> {
+        self.locale.as_deref()
+    }
+
+    pub fn id(&self) -> &str {
+        self.graph.id()
+    }
+
+    // the id of the story being played, which differs from `id` within a sub-scenario
+    pub fn current_story(&self) -> &str {
+        self.frame(self.calls.len()).0.id()
+    }
+
+    // the current node of the story being played
+    pub fn current(&self) -> ScenarioActionId {
+        self.frame(self.calls.len()).2
+    }
+
+    // the name of the current node, if named
+    pub fn current_name(&self) -> Option<&str> {
+        let (graph, _, current, _) = self.frame(self.calls.len());
+        graph.node_name(current)
+    }
+
+    // the visits of the story being played
+    pub fn visits(&self) -> &Visits {
+        self.frame(self.calls.len()).3
     }
 
     pub fn is_completed(&self) -> bool {
-        self.graph.connections(self.current_node()).is_empty()
+        (0..=self.calls.len()).all(|depth| {
+            let (graph, _, current, _) = self.frame(depth);
+            graph.all_connections(current.into()).is_empty()
+        })
     }
 
-    pub fn choices(&self, narrative_world: &NarrativeWorld) -> Vec<ScenarioChoice> {
-        self.graph
-            .connections(self.current_node())
+    // Fails if the directive of a choice can't be rendered, such as when it references an alias
+    // which isn't declared, the error pointing to the story and node at fault.
+    // Listing the choices draws no alternative from the shuffle bags, only making a choice does.
+    pub fn choices(&self, narrative_world: &NarrativeWorld) -> Result<Vec<ScenarioChoice>, Error> {
+        let depth = self.choice_depth();
+        let mut choices = self.open_transitions(depth, narrative_world);
+        choices.sort_by(|(_, lhs), (_, rhs)| rhs.total_cmp(lhs));
+        choices
             .into_iter()
-            .map(|action_id| {
-                let action = self.graph.get(action_id.into());
-                ScenarioChoice {
-                    id: action_id.into(),
-                    description: action.description.clone(),
-                    directive: action.directive(&self.alias_map, narrative_world).unwrap(),
-                }
+            .filter_map(|(action_id, weight)| {
+                self.choice(depth, action_id, weight, narrative_world)
+                    .transpose()
+            })
+            .collect()
+    }
+
+    // picks one of the choices at random, weighted
+    pub fn random_choice(
+        &self,
+        narrative_world: &NarrativeWorld,
+        rng: &mut SeededRng,
+    ) -> Result<Option<ScenarioChoice>, Error> {
+        let depth = self.choice_depth();
+        let mut choices: Vec<_> = self
+            .open_transitions(depth, narrative_world)
+            .into_iter()
+            .filter_map(|(action_id, weight)| {
+                self.choice(depth, action_id, weight, narrative_world)
+                    .transpose()
             })
-            .collect_vec()
+            .try_collect()?;
+        Ok(rng
+            .weighted_index(choices.iter().map(|choice| choice.weight))
+            .map(|index| choices.swap_remove(index)))
     }
 
-    fn current_node(&self) -> petgraph::stable_graph::NodeIndex {
-        self.current_action.clone().take().into()
+    // The graph, alias map, current node and visits of the scenario at some depth of sub-scenarios
+    fn frame(&self, depth: usize) -> (&ScenarioGraph, &AliasMap, ScenarioActionId, &Visits) {
+        match depth {
+            0 => (
+                &self.graph,
+                &self.alias_map,
+                self.current_node(),
+                &self.visits,
+            ),
+            _ => {
+                let call = &self.calls[depth - 1];
+                (&call.graph, &call.alias_map, call.current, &call.visits)
+            }
+        }
+    }
+
+    // a sub-scenario at a leaf returns to its caller, so choices are offered by the innermost
+    // scenario which isn't at a leaf
+    fn choice_depth(&self) -> usize {
+        (1..=self.calls.len())
+            .rev()
+            .find(|&depth| {
+                let (graph, _, current, _) = self.frame(depth);
+                !graph.all_connections(current.into()).is_empty()
+            })
+            .unwrap_or(0)
     }
 
-    pub fn choose(&mut self, choice: ScenarioChoice) {}
+    fn open_transitions(
+        &self,
+        depth: usize,
+        narrative_world: &NarrativeWorld,
+    ) -> Vec<(ScenarioActionId, f64)> {
+        let (graph, alias_map, current, visits) = self.frame(depth);
+        graph.open_transitions(current, narrative_world, alias_map, visits)
+    }
+
+    // `None` for a node calling into a sub-scenario which can't be played
+    fn choice(
+        &self,
+        depth: usize,
+        action_id: ScenarioActionId,
+        weight: f64,
+        narrative_world: &NarrativeWorld,
+    ) -> Result<Option<ScenarioChoice>, Error> {
+        let (graph, alias_map, from, _) = self.frame(depth);
+        let action = graph.action(action_id.into());
+        let Some(alias_map) = action.bind_local_aliases(narrative_world, alias_map) else {
+            return Ok(None);
+        };
+        let call = match &action.sub_scenario {
+            None => None,
+            Some(sub_scenario) => {
+                if depth >= MAX_SUB_SCENARIO_DEPTH {
+                    return Ok(None);
+                }
+                let call = self.sub_stories.get(&sub_scenario.story).and_then(|story| {
+                    let options = action.sub_scenario_options(&alias_map)?;
+                    let child_alias_map = story
+                        .valid_alias_bindings_in(
+                            narrative_world,
+                            &options,
+                            Some(self.sub_stories.as_ref()),
+                            depth + 1,
+                        )
+                        .next()?;
+                    Some(Call::new(Rc::clone(story), child_alias_map))
+                });
+                if call.is_none() {
+                    return Ok(None);
+                }
+                call
+            }
+        };
+
+        let translations = self
+            .localization
+            .node_texts(graph, action_id, self.locale());
+        // the picks are only committed by `choose`
+        let shuffle_bags = self.shuffle_bags.borrow();
+        let mut draws = Draws::new(&shuffle_bags, &graph.shuffle_owner(action_id));
+        let directive_segments = action
+            .render_directive(
+                &alias_map,
+                narrative_world,
+                &self.filters,
+                &translations,
+                &mut draws,
+            )
+            .map_err(|error| error.in_node(graph, action_id))?;
+        Ok(Some(ScenarioChoice {
+            id: action_id,
+            depth,
+            story: graph.id().into(),
+            from,
+            call,
+            weight,
+            description: translations
+                .iter()
+                .find_map(|texts| texts.description.as_deref())
+                .unwrap_or(&action.description)
+                .to_string(),
+            directive: concat(&directive_segments),
+            directive_segments,
+            alias_map,
+            drawn: draws.into_drawn(),
+        }))
+    }
+
+    fn current_node(&self) -> ScenarioActionId {
+        *self.current_action.borrow()
+    }
+
+    // Fails with `Error::StaleChoice` for a choice offered before the scenario moved on, such as
+    // one listed within a sub-scenario which has since returned.
+    pub fn choose(&mut self, choice: ScenarioChoice) -> Result<(), Error> {
+        let is_current = choice.depth == self.choice_depth() && {
+            let (graph, _, current, _) = self.frame(choice.depth);
+            graph.id() == choice.story && current == choice.from
+        };
+        if !is_current {
+            return Err(Error::StaleChoice {
+                story: choice.story,
+                node: choice.id,
+            });
+        }
+
+        self.shuffle_bags.borrow_mut().commit(choice.drawn);
+        // sub-scenarios below the choice have returned
+        self.calls.truncate(choice.depth);
+        match choice.depth {
+            0 => {
+                let from = self.current_action.replace(choice.id);
+                self.visits.traverse(from, choice.id);
+                self.alias_map = choice.alias_map;
+            }
+            _ => {
+                let call = &mut self.calls[choice.depth - 1];
+                let from = std::mem::replace(&mut call.current, choice.id);
+                call.visits.traverse(from, choice.id);
+                call.alias_map = choice.alias_map;
+            }
+        }
+        self.calls.extend(choice.call);
+        Ok(())
+    }
+}
+
+// the same story and bindings always start with the same bags
+fn shuffle_seed(graph: &ScenarioGraph, alias_map: &AliasMap) -> u64 {
+    let mut bytes = graph.id().as_bytes().to_vec();
+    for (alias, entities) in alias_map.bindings() {
+        bytes.push(0);
+        bytes.extend(alias.as_bytes());
+        for entity in entities {
+            bytes.extend((*entity as u64).to_le_bytes());
+        }
+    }
+    seed_from(&bytes)
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    use itertools::Itertools;
+
+    use crate::{
+        error::{Error, Location},
+        prelude::{
+            Constraint, Entity, NarrativeWorld, ScenarioAction, ScenarioGraph, SeededRng,
+            Transition,
+        },
+        scenario_graph::AliasMap,
+        shuffle_bag::ShuffleBags,
+    };
+
+    use super::Scenario;
+
+    #[test]
+    fn misnamed_aliases_are_reported_with_their_location() {
+        let mut graph = ScenarioGraph::new();
+        graph.set_id("market");
+        let start = graph.add(ScenarioAction::new());
+        let haggle = graph
+            .add_named(
+                "haggle",
+                ScenarioAction::new().with_directive("{vendr.name} frowns."),
+            )
+            .unwrap();
+        graph.connect(start, haggle).unwrap();
+
+        let context = NarrativeWorld::new();
+        let scenario = Scenario::new(Rc::new(graph), AliasMap::default());
+        let error = scenario.choices(&context).err().unwrap();
+        assert!(matches!(
+            &error,
+            Error::Alias { location, .. } if **location == Location {
+                story: Some("market".into()),
+                node: Some(haggle),
+                node_name: Some("haggle".into()),
+                line: None,
+                span: Some(0..12),
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            r#"Alias error: missing alias "vendr" (story "market", node "haggle" (#1), at 0..12)"#
+        );
+        assert!(scenario
+            .random_choice(&context, &mut SeededRng::new(0))
+            .is_err());
+    }
+
+    #[test]
+    fn weak_edge_loops_are_bounded_by_visits() {
+        let mut graph = ScenarioGraph::new();
+        let hub = graph.add(ScenarioAction::new().with_description("hub"));
+        let rumors = graph.add(
+            ScenarioAction::new()
+                .with_description("rumors")
+                .with_max_visits(1),
+        );
+        let weather = graph.add(ScenarioAction::new().with_description("weather"));
+        let farewell = graph.add(
+            ScenarioAction::new()
+                .with_description("farewell")
+                .with_visit_constraint(rumors, 1..usize::MAX),
+        );
+        graph.set_start_node(hub);
+        graph.connect(hub, rumors).unwrap();
+        graph.connect(hub, weather).unwrap();
+        graph.connect(hub, farewell).unwrap();
+        graph.connect_weak(rumors, hub).unwrap();
+        graph
+            .connect_weak_with(weather, hub, Transition::new().with_max_traversals(2))
+            .unwrap();
+
+        let context = NarrativeWorld::new();
+        let mut scenario = Scenario::new(Rc::new(graph), AliasMap::default());
+        let take = |scenario: &mut Scenario, description: &str| {
+            let choice = scenario
+                .choices(&context)
+                .unwrap()
+                .into_iter()
+                .find(|choice| choice.description == description)
+                .unwrap_or_else(|| panic!("{} is not offered", description));
+            scenario.choose(choice).unwrap();
+        };
+        let offered = |scenario: &Scenario| {
+            scenario
+                .choices(&context)
+                .unwrap()
+                .into_iter()
+                .map(|choice| choice.description)
+                .sorted()
+                .collect_vec()
+        };
+
+        assert_eq!(offered(&scenario), ["rumors", "weather"]);
+        take(&mut scenario, "rumors");
+        take(&mut scenario, "hub");
+        assert_eq!(offered(&scenario), ["farewell", "weather"]);
+
+        for _ in 0..2 {
+            take(&mut scenario, "weather");
+            take(&mut scenario, "hub");
+        }
+        take(&mut scenario, "weather");
+        assert!(offered(&scenario).is_empty());
+        assert_eq!(scenario.visits().node(hub), 4);
+        assert_eq!(scenario.visits().edge(weather, hub), 2);
+    }
+
+    fn greeting() -> ScenarioGraph {
+        let mut graph = ScenarioGraph::new();
+        graph.set_id("greeting");
+        graph.add_alias("host", [Constraint::has("villager")]);
+        graph.add_alias("guest", []);
+        let a = graph
+            .add_named(
+                "doorstep",
+                ScenarioAction::new().with_description("doorstep"),
+            )
+            .unwrap();
+        let b = graph.add(
+            ScenarioAction::new()
+                .with_description("bow")
+                .with_directive("{host.name} bows to {guest.name}"),
+        );
+        graph.set_start_node(a);
+        graph.connect(a, b).unwrap();
+        graph
+    }
+
+    #[test]
+    fn identical_alternatives_in_different_nodes_have_their_own_bags() {
+        let mut graph = ScenarioGraph::new();
+        let hub = graph.add(ScenarioAction::new().with_description("hub"));
+        let ask = graph.add(
+            ScenarioAction::new()
+                .with_description("ask")
+                .with_directive("{~Yes|No}"),
+        );
+        let plead = graph.add(
+            ScenarioAction::new()
+                .with_description("plead")
+                .with_directive("{~Yes|No}"),
+        );
+        graph.set_start_node(hub);
+        for node in [ask, plead] {
+            graph.connect(hub, node).unwrap();
+            graph.connect_weak(node, hub).unwrap();
+        }
+        let graph = Rc::new(graph);
+
+        let context = NarrativeWorld::new();
+        let take = |scenario: &mut Scenario, description: &str| {
+            let choice = scenario
+                .choices(&context)
+                .unwrap()
+                .into_iter()
+                .find(|choice| choice.description == description)
+                .unwrap();
+            let directive = choice.directive.clone();
+            scenario.choose(choice).unwrap();
+            directive
+        };
+        for seed in 0..8 {
+            let mut scenario = Scenario::new(Rc::clone(&graph), AliasMap::default())
+                .with_shuffle_bags(Rc::new(RefCell::new(ShuffleBags::new(seed))));
+            let first = take(&mut scenario, "ask");
+            take(&mut scenario, "hub");
+            take(&mut scenario, "plead");
+            take(&mut scenario, "hub");
+            // pleading didn't draw from the bag of asking
+            assert_ne!(take(&mut scenario, "ask"), first);
+        }
+    }
+
+    #[test]
+    fn a_sub_scenario_returns_to_its_caller() {
+        let mut graph = ScenarioGraph::new();
+        graph.set_id("market");
+        graph.add_alias("merchant", []);
+        graph.add_alias("buyer", []);
+        let a = graph.add(ScenarioAction::new().with_description("stall"));
+        let b = graph.add(
+            ScenarioAction::new()
+                .with_description("welcome")
+                .with_sub_scenario("greeting", [("merchant", "host"), ("buyer", "guest")]),
+        );
+        let c = graph.add(ScenarioAction::new().with_description("haggle"));
+        graph.set_start_node(a);
+        graph.connect(a, b).unwrap();
+        graph.connect(b, c).unwrap();
+
+        let context = NarrativeWorld::new()
+            .with_entity(Entity::new(0).with("name", "Ada").with("villager", ""))
+            .with_entity(Entity::new(1).with("name", "Bo"));
+        let mut alias_map = AliasMap::default();
+        alias_map.associate("merchant".into(), 0);
+        alias_map.associate("buyer".into(), 1);
+        let sub_stories = HashMap::from([("greeting".to_string(), Rc::new(greeting()))]);
+        let mut scenario =
+            Scenario::new(Rc::new(graph), alias_map).with_sub_stories(Rc::new(sub_stories));
+
+        let choose = |scenario: &mut Scenario, description: &str| {
+            let choice = scenario
+                .choices(&context)
+                .unwrap()
+                .into_iter()
+                .exactly_one()
+                .ok()
+                .unwrap();
+            assert_eq!(choice.description, description);
+            let directive = choice.directive.clone();
+            scenario.choose(choice).unwrap();
+            directive
+        };
+
+        choose(&mut scenario, "welcome");
+        assert_eq!(scenario.current_story(), "greeting");
+        assert_eq!(scenario.current_name(), Some("doorstep"));
+        let stale_bow = scenario.choices(&context).unwrap().remove(0);
+        assert_eq!(choose(&mut scenario, "bow"), "Ada bows to Bo");
+        assert!(!scenario.is_completed());
+
+        choose(&mut scenario, "haggle");
+        assert_eq!(scenario.current_story(), "market");
+        assert_eq!(scenario.current(), c);
+        assert!(scenario.is_completed());
+
+        // offered within the sub-scenario, which has returned since
+        let error = scenario.choose(stale_bow).unwrap_err();
+        assert!(
+            matches!(&error, Error::StaleChoice { story, .. } if story == "greeting"),
+            "{:?}",
+            error
+        );
+        assert_eq!(scenario.current(), c);
+    }
+
+    #[test]
+    fn a_sub_scenario_which_cannot_be_played_is_not_offered() {
+        let mut graph = ScenarioGraph::new();
+        graph.add_alias("merchant", []);
+        graph.add_alias("buyer", []);
+        let a = graph.add(ScenarioAction::new());
+        let b = graph.add(ScenarioAction::new().with_sub_scenario("greeting", [("buyer", "host")]));
+        graph.set_start_node(a);
+        graph.connect(a, b).unwrap();
+
+        let context = NarrativeWorld::new()
+            .with_entity(Entity::new(0).with("villager", ""))
+            .with_entity(Entity::new(1));
+        let mut alias_map = AliasMap::default();
+        alias_map.associate("merchant".into(), 0);
+        alias_map.associate("buyer".into(), 1);
+        let graph = Rc::new(graph);
+
+        // the buyer is no villager
+        let sub_stories = Rc::new(HashMap::from([(
+            "greeting".to_string(),
+            Rc::new(greeting()),
+        )]));
+        let scenario =
+            Scenario::new(Rc::clone(&graph), alias_map.clone()).with_sub_stories(sub_stories);
+        assert!(scenario.choices(&context).unwrap().is_empty());
+
+        // nor is the sub-scenario known
+        let scenario = Scenario::new(graph, alias_map);
+        assert!(scenario.choices(&context).unwrap().is_empty());
+    }
+
+    #[test]
+    fn local_aliases_are_bound_when_their_node_is_entered() {
+        let mut graph = ScenarioGraph::new();
+        graph.add_alias("thief", []);
+        let alley = graph.add(ScenarioAction::new().with_description("alley"));
+        let arrest = graph.add(
+            ScenarioAction::new()
+                .with_description("arrest")
+                .with_local_alias("guard", [Constraint::has("guard")])
+                .with_directive("{guard.name} grabs {thief.name}"),
+        );
+        let cell = graph.add(
+            ScenarioAction::new()
+                .with_description("cell")
+                .with_directive("{guard.name} locks the cell"),
+        );
+        let escape = graph.add(ScenarioAction::new().with_description("escape"));
+        graph.set_start_node(alley);
+        graph.connect(alley, arrest).unwrap();
+        graph.connect(arrest, cell).unwrap();
+        graph.connect(alley, escape).unwrap();
+        let graph = Rc::new(graph);
+
+        let mut alias_map = AliasMap::default();
+        alias_map.associate("thief".into(), 0);
+
+        // without guards in the city the arrest is skipped
+        let context = NarrativeWorld::new().with_entity(Entity::new(0).with("guard", ""));
+        let scenario = Scenario::new(Rc::clone(&graph), alias_map.clone());
+        let offered = scenario
+            .choices(&context)
+            .unwrap()
+            .into_iter()
+            .map(|choice| choice.description)
+            .collect_vec();
+        assert_eq!(offered, ["escape"]);
+
+        let context = NarrativeWorld::new()
+            .with_entity(Entity::new(0).with("name", "Nim").with("guard", ""))
+            .with_entity(Entity::new(1).with("name", "Ulf").with("guard", ""))
+            .with_entity(Entity::new(2).with("name", "Vera").with("guard", ""));
+        let mut scenario = Scenario::new(graph, alias_map);
+        let arrest = scenario
+            .choices(&context)
+            .unwrap()
+            .into_iter()
+            .find(|choice| choice.description == "arrest")
+            .unwrap();
+        assert_eq!(arrest.directive, "Ulf grabs Nim");
+        scenario.choose(arrest).unwrap();
+        assert_eq!(scenario.alias_map.get("guard"), Some(1));
+
+        let cell = scenario
+            .choices(&context)
+            .unwrap()
+            .into_iter()
+            .exactly_one()
+            .ok()
+            .unwrap();
+        assert_eq!(cell.directive, "Ulf locks the cell");
+    }
 }
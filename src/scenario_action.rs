@@ -1,36 +1,40 @@
-use std::{
-    error::Error,
-    fmt::{self, Write},
-};
+use std::{collections::BTreeMap, ops::Range};
 
 use itertools::Itertools;
-use once_cell::sync::Lazy;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    constraint::{AliasRelation, Constraint},
+    alias_solver::AliasBindings,
+    constraint::{AliasRelation, Constraint, Quantifier},
+    error::Error,
+    filter::{with_default_filters, Filters},
+    line::{LineName, LineVariant},
+    locale::NodeTexts,
     narrative_world::NarrativeWorld,
     prelude::Entity,
-    property::{PropertyMap, PropertyName},
-    scenario_graph::{AliasError, AliasMap},
+    property::PropertyName,
+    query::QueryOptions,
+    scenario_graph::{AliasMap, ScenarioActionId, StoryId},
+    shuffle_bag::{draw_from_global_shuffle_bags, Draws},
+    template::{concat, Template, TextSegment},
+    visits::{VisitConstraint, Visits},
 };
 
 pub type Alias = String;
 
-#[derive(Debug)]
-pub struct NotSatisfied;
-impl fmt::Display for NotSatisfied {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Story node constraints not satisfied")
-    }
-}
-impl Error for NotSatisfied {}
-
 #[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub(crate) struct ConstrainedAlias {
     pub(crate) alias: Alias,
     pub(crate) constraints: Vec<Constraint>,
+    // bound whenever possible, left unbound otherwise
+    #[serde(default)]
+    pub(crate) optional: bool,
+    // aliases which may be bound to the same entity as this one
+    #[serde(default)]
+    pub(crate) may_coincide_with: Vec<Alias>,
+    // a group alias binds every matching entity, as long as their number is within range
+    #[serde(default)]
+    pub(crate) group: Option<Range<usize>>,
 }
 
 impl ConstrainedAlias {
@@ -42,6 +46,7 @@ impl ConstrainedAlias {
         Self {
             alias: alias.into(),
             constraints: Vec::from_iter(constraints),
+            ..Default::default()
         }
     }
 
@@ -49,14 +54,36 @@ impl ConstrainedAlias {
         &self.alias
     }
 
-    //
+    pub(crate) fn may_coincide_with(&self, other: &ConstrainedAlias) -> bool {
+        self.may_coincide_with.contains(&other.alias)
+            || other.may_coincide_with.contains(&self.alias)
+    }
+
     pub(crate) fn is_satisfied_by(&self, entity: &Entity) -> bool {
-        entity
-            .exclusory_properties
-            .iter()
-            .all(|(exclusory_prop_name, exclusory_property)| {
+        self.excluding_properties(entity).next().is_none()
+            && self.failing_constraints(entity).next().is_none()
+    }
+
+    // the constraints satisfied by neither the properties nor the exclusory properties of the entity
+    pub(crate) fn failing_constraints<'a>(
+        &'a self,
+        entity: &'a Entity,
+    ) -> impl Iterator<Item = &'a Constraint> {
+        self.constraints.iter().filter(|constraint| {
+            !constraint.is_satisfied_by(&entity.properties)
+                && !constraint.is_satisfied_by(&entity.exclusory_properties)
+        })
+    }
+
+    // exclusory properties of the entity which none of the constraints asks for
+    pub(crate) fn excluding_properties<'a>(
+        &'a self,
+        entity: &'a Entity,
+    ) -> impl Iterator<Item = &'a PropertyName> {
+        entity.exclusory_properties.iter().filter_map(
+            move |(exclusory_prop_name, exclusory_property)| {
                 // TODO: make constraints a hashmap of prop_name to constraint
-                self.constraints.iter().any(|constraint| match constraint {
+                let asked_for = self.constraints.iter().any(|constraint| match constraint {
                     Constraint::Has(prop_name) => prop_name == exclusory_prop_name,
                     Constraint::Equals(prop_name, property) => {
                         prop_name == exclusory_prop_name && property == exclusory_property
@@ -69,21 +96,38 @@ impl ConstrainedAlias {
                             && exclusory_property.is_in_range_float(range)
                     }
                     _ => false,
-                })
-            })
-            && self.constraints.iter().all(|constraint| {
-                constraint.is_satisfied_by(&entity.properties)
-                    || constraint.is_satisfied_by(&entity.exclusory_properties)
-            })
+                });
+                (!asked_for).then_some(exclusory_prop_name)
+            },
+        )
     }
 }
 
-#[derive(Default, Serialize, Deserialize, Debug, PartialEq)]
+// Calls into another story registered in the same `Raconteur` once its action is entered.
+// The scenario returns to that action when the sub-scenario completes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SubScenario {
+    pub story: StoryId,
+    // pairs of parent alias and child alias, child aliases left out are bound freely
+    pub aliases: Vec<(Alias, Alias)>,
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ScenarioAction {
     pub description: String,
     pub relation_constraints: Vec<AliasRelation>,
     pub world_constraints: Vec<Constraint>,
-    pub directive: String, // TODO, some DSL instead of just strings? maybe this approach https://github.com/clap-rs/clap/blob/053c778e986d99b4f53afdb666d9398e75d8d2fb/examples/repl.rs
+    #[serde(default)]
+    pub visit_constraints: Vec<VisitConstraint>,
+    #[serde(default)]
+    pub sub_scenario: Option<SubScenario>,
+    // aliases bound when the node is entered rather than when the story is queried
+    #[serde(default)]
+    local_aliases: Vec<ConstrainedAlias>,
+    // variants of the lines referenced as `[line name]` in the directive
+    #[serde(default)]
+    pub lines: BTreeMap<LineName, Vec<LineVariant>>,
+    pub directive: Template, // TODO, some DSL instead of just strings? maybe this approach https://github.com/clap-rs/clap/blob/053c778e986d99b4f53afdb666d9398e75d8d2fb/examples/repl.rs
 }
 
 impl ScenarioAction {
@@ -111,126 +155,254 @@ impl ScenarioAction {
         self
     }
 
+    // relation constraints involving group aliases, holding for all or any of their members
+    pub fn with_quantified_relation_constraints<A, C>(
+        mut self,
+        quantifier: Quantifier,
+        me: A,
+        other: A,
+        constraints: C,
+    ) -> Self
+    where
+        A: Into<Alias>,
+        C: IntoIterator<Item = Constraint>,
+    {
+        self.relation_constraints
+            .push(AliasRelation::new(me, other, constraints).with_quantifier(quantifier));
+        self
+    }
+
     pub fn with_world_constraint(mut self, constraint: Constraint) -> Self {
         self.world_constraints.push(constraint);
         self
     }
 
+    // only offers this node while it was entered fewer than `visits` times, such as a dialogue topic offered once
+    pub fn with_max_visits(mut self, visits: usize) -> Self {
+        self.visit_constraints
+            .push(VisitConstraint::new(None, 0..visits));
+        self
+    }
+
+    // only offers this node while `node` was visited a number of times within `visits`
+    pub fn with_visit_constraint(mut self, node: ScenarioActionId, visits: Range<usize>) -> Self {
+        self.visit_constraints
+            .push(VisitConstraint::new(Some(node), visits));
+        self
+    }
+
+    // plays the story `story` when this action is entered, binding its aliases to the entities of the mapped aliases
+    pub fn with_sub_scenario<S, A, I>(mut self, story: S, aliases: I) -> Self
+    where
+        S: Into<StoryId>,
+        A: Into<Alias>,
+        I: IntoIterator<Item = (A, A)>,
+    {
+        self.sub_scenario = Some(SubScenario {
+            story: story.into(),
+            aliases: aliases
+                .into_iter()
+                .map(|(parent, child)| (parent.into(), child.into()))
+                .collect(),
+        });
+        self
+    }
+
+    // Binds `alias` when this node is entered, to an entity not bound to any other alias yet.
+    // The node isn't offered when no entity satisfies the alias and relation constraints.
+    // Once bound, the alias keeps its entity for the rest of the scenario.
+    pub fn with_local_alias<A, C>(mut self, alias: A, constraints: C) -> Self
+    where
+        A: Into<Alias>,
+        C: IntoIterator<Item = Constraint>,
+    {
+        self.local_aliases
+            .push(ConstrainedAlias::new(alias, constraints));
+        self
+    }
+
+    // a malformed directive fails to render, and is reported by `ScenarioGraph::validate`
     pub fn with_directive<D>(mut self, directive: D) -> Self
     where
-        D: Into<String>,
+        D: Into<Template>,
     {
         self.directive = directive.into();
         self
     }
 
+    // Declares the variants of a line, tried in order when the line is rendered.
+    // End them with a variant without constraints to always have something to say.
+    pub fn with_line<N, I>(mut self, name: N, variants: I) -> Self
+    where
+        N: Into<LineName>,
+        I: IntoIterator<Item = LineVariant>,
+    {
+        self.lines.entry(name.into()).or_default().extend(variants);
+        self
+    }
+
     pub(crate) fn are_world_constraints_satisfied(&self, context: &NarrativeWorld) -> bool {
         self.world_constraints
             .iter()
             .all(|constraint| constraint.is_satisfied_by(context.properties()))
     }
 
+    // Pins the aliases of the sub-scenario to the entities bound to their parent aliases.
+    // `None` without a sub-scenario or when a parent alias is unbound.
+    pub(crate) fn sub_scenario_options(&self, alias_map: &AliasMap) -> Option<QueryOptions> {
+        let sub_scenario = self.sub_scenario.as_ref()?;
+        sub_scenario
+            .aliases
+            .iter()
+            .try_fold(QueryOptions::new(), |options, (parent, child)| {
+                if alias_map.is_unbound(parent) {
+                    // the child alias is bound freely
+                    return Some(options);
+                }
+                alias_map
+                    .get(parent)
+                    .map(|entity| options.with_pinned_alias(child.clone(), entity))
+            })
+    }
+
+    pub(crate) fn are_visit_constraints_satisfied(
+        &self,
+        node_id: ScenarioActionId,
+        visits: &Visits,
+    ) -> bool {
+        self.visit_constraints
+            .iter()
+            .all(|constraint| constraint.is_satisfied_by(node_id, visits))
+    }
+
+    pub(crate) fn local_aliases(&self) -> &[ConstrainedAlias] {
+        &self.local_aliases
+    }
+
+    pub(crate) fn can_enter(&self, context: &NarrativeWorld, alias_map: &AliasMap) -> bool {
+        if self.local_aliases.is_empty() {
+            self.are_relation_constraints_satisfied(context, alias_map)
+        } else {
+            self.bind_local_aliases(context, alias_map).is_some()
+        }
+    }
+
+    // Extends the alias map with the first binding of the local aliases satisfying the relation
+    // constraints, candidates being ordered by entity id. Local aliases already bound are kept.
+    pub(crate) fn bind_local_aliases(
+        &self,
+        context: &NarrativeWorld,
+        alias_map: &AliasMap,
+    ) -> Option<AliasMap> {
+        let unbound = self
+            .local_aliases
+            .iter()
+            .filter(|local_alias| alias_map.get(local_alias.alias()).is_none())
+            .collect_vec();
+        let bound = alias_map.iter().map(|(_, entity)| entity).collect_vec();
+
+        AliasBindings::new(unbound, [], context, &QueryOptions::default())
+            .filter(|local_map| local_map.iter().all(|(_, entity)| !bound.contains(&entity)))
+            .map(|local_map| {
+                let mut alias_map = alias_map.clone();
+                alias_map.extend(&local_map);
+                alias_map
+            })
+            .find(|alias_map| self.are_relation_constraints_satisfied(context, alias_map))
+    }
+
     pub(crate) fn are_relation_constraints_satisfied(
         &self,
         context: &NarrativeWorld,
         alias_entities: &AliasMap,
     ) -> bool {
-        self.relation_constraints.iter().all(|relation| {
-            let me_id = if let Some(id) = alias_entities.get(&relation.me) {
-                id
-            } else {
-                return false;
-            };
-            let other_id = if let Some(id) = alias_entities.get(&relation.other) {
-                id
-            } else {
-                return false;
-            };
-
-            let default_props = PropertyMap::default();
-            let relation_properties = context
-                .relations()
-                .get(&(me_id, other_id))
-                .unwrap_or(&default_props);
-            relation.is_satisfied_by(relation_properties)
-        })
+        self.relation_constraints
+            .iter()
+            .all(|relation| relation.is_satisfied_in(context, alias_entities))
+    }
+
+    // aliases named by the directive's placeholders and by its lines
+    pub(crate) fn directive_aliases(&self) -> Vec<&str> {
+        self.templates()
+            .flat_map(Template::aliases)
+            .chain(
+                self.lines
+                    .values()
+                    .flatten()
+                    .flat_map(LineVariant::constrained_aliases),
+            )
+            .collect()
+    }
+
+    // the directive and the texts of the line variants
+    pub(crate) fn templates(&self) -> impl Iterator<Item = &Template> {
+        std::iter::once(&self.directive)
+            .chain(self.lines.values().flatten().map(|variant| &variant.text))
     }
 
+    // Renders the directive with the built-in filters. Errors only locate the faulty part of the
+    // directive or line, `ScenarioGraph::directive` also reports the story and node.
+    // Alternatives are picked from shuffle bags shared by the thread.
     pub fn directive(
         &self,
         alias_map: &AliasMap,
         context: &NarrativeWorld,
-    ) -> Result<String, AliasError> {
-        static PATTERNS_RE: Lazy<Regex> =
-            Lazy::new(|| Regex::new(r"\{([<>[:word:]\.]+)\}").unwrap());
-        static ALIAS_RE: Lazy<Regex> = Lazy::new(|| Regex::new("<a>([[:word:]]+)").unwrap());
-        static ALIAS_PROP_RE: Lazy<Regex> =
-            Lazy::new(|| Regex::new("([[:word:]]+)\\.(?P<property_name>[[:word:]]+)").unwrap());
-        static WORLD_PROP_RE: Lazy<Regex> = Lazy::new(|| Regex::new("([[:word:]]+)").unwrap());
-
-        let mut result_directive = String::new();
-
-        let parts: Vec<&str> = PATTERNS_RE.split(&self.directive).collect_vec();
-        let matches = PATTERNS_RE.captures_iter(&self.directive).collect_vec();
-        for i in 0..matches.len() {
-            write!(&mut result_directive, "{}", parts[i]).unwrap();
-            let pattern = &matches[i][1];
-
-            let unaliased_text: Result<_, _>;
-            if let Some(cap) = ALIAS_RE.captures(pattern) {
-                let alias = &cap[1];
-                unaliased_text = alias_map
-                    .get(alias)
-                    .ok_or(AliasError::new(format!(r#"missing alias "{}""#, alias)))
-                    .map(|entity_id: usize| entity_id.to_string());
-            } else if let Some(cap) = ALIAS_PROP_RE.captures(pattern) {
-                let alias = &cap[1];
-                let property_name = &cap[2];
-                unaliased_text = alias_map
-                    .get(alias)
-                    .ok_or(AliasError::new(format!(r#"missing alias "{}""#, alias)))
-                    .and_then(|entity_id| {
-                        context
-                            .entity(entity_id)
-                            .ok_or(AliasError::new(format!(
-                                r#"Entity "{}" bound to "{}" is missing"#,
-                                entity_id, alias
-                            )))
-                            .and_then(|entity| {
-                                entity
-                                    .get(property_name)
-                                    .ok_or(AliasError::new(format!(
-                                        r#"Entity "{}" bound to "{}" is missing the property "{}""#,
-                                        entity_id, alias, property_name
-                                    )))
-                                    .map(|property| property.to_string())
-                            })
-                    });
-            } else if let Some(cap) = WORLD_PROP_RE.captures(pattern) {
-                let property_name = &cap[1];
-                unaliased_text = context
-                    .world_property(property_name)
-                    .ok_or(AliasError::new(format!(
-                        r#"Missing world property "{}""#,
-                        property_name
-                    )))
-                    .map(|property| property.to_string());
-            } else {
-                return Err(AliasError::new(format!(
-                    r#"Malformed property path "{}""#,
-                    pattern
-                )));
-            }
-
-            match unaliased_text {
-                Ok(property) => write!(&mut result_directive, "{}", property).unwrap(),
-                Err(err) => return Err(err),
-            };
-        }
-        if parts.len() > matches.len() {
-            write!(&mut result_directive, "{}", parts[matches.len()]).unwrap();
-        }
-        Ok(result_directive)
+    ) -> Result<String, Error> {
+        with_default_filters(|filters| self.directive_with(alias_map, context, filters))
+    }
+
+    pub fn directive_with(
+        &self,
+        alias_map: &AliasMap,
+        context: &NarrativeWorld,
+        filters: &Filters,
+    ) -> Result<String, Error> {
+        self.directive_segments_with(alias_map, context, filters)
+            .map(|segments| concat(&segments))
+    }
+
+    // Renders the directive as segments, keeping entities and property values typed
+    pub fn directive_segments(
+        &self,
+        alias_map: &AliasMap,
+        context: &NarrativeWorld,
+    ) -> Result<Vec<TextSegment>, Error> {
+        with_default_filters(|filters| self.directive_segments_with(alias_map, context, filters))
+    }
+
+    pub fn directive_segments_with(
+        &self,
+        alias_map: &AliasMap,
+        context: &NarrativeWorld,
+        filters: &Filters,
+    ) -> Result<Vec<TextSegment>, Error> {
+        // without a node to tell it apart, the directive owns the bags of its alternatives
+        draw_from_global_shuffle_bags(self.directive.source(), |draws| {
+            self.render_directive(alias_map, context, filters, &[], draws)
+        })
+    }
+
+    // renders the directive, or the first of its translations
+    pub(crate) fn render_directive(
+        &self,
+        alias_map: &AliasMap,
+        context: &NarrativeWorld,
+        filters: &Filters,
+        translations: &[&NodeTexts],
+        draws: &mut Draws,
+    ) -> Result<Vec<TextSegment>, Error> {
+        translations
+            .iter()
+            .find_map(|texts| texts.directive.as_ref())
+            .unwrap_or(&self.directive)
+            .render(
+                alias_map,
+                context,
+                &self.lines,
+                filters,
+                translations,
+                draws,
+            )
     }
 }
 
@@ -238,7 +410,8 @@ impl ScenarioAction {
 mod unit_tests {
     use crate::{
         entity::EntityId,
-        prelude::{Entity, NarrativeWorld},
+        prelude::{Constraint, Entity, LineVariant, NarrativeWorld, TextSegment},
+        property::Property,
         scenario_graph::AliasMap,
     };
 
@@ -322,4 +495,241 @@ mod unit_tests {
             r#"speak 1 0 "Hello Umberto the explorer! Although I am only 18 years old, I am the namesake of this Calvinton shop: Hialda's Goods!""#
         );
     }
+
+    #[test]
+    fn unbound_optional_aliases_render_as_nothing() {
+        let node = ScenarioAction::new().with_directive("{thief.name} runs{witness.name}");
+        let context = NarrativeWorld::default().with_entity(Entity::new(0).with("name", "Nim"));
+        let mut alias_map = AliasMap::default();
+        alias_map.associate("thief".into(), 0);
+        assert!(node.directive(&alias_map, &context).is_err());
+
+        alias_map.leave_unbound("witness".into());
+        assert_eq!(node.directive(&alias_map, &context).unwrap(), "Nim runs");
+    }
+
+    #[test]
+    fn group_aliases_are_counted_and_listed() {
+        let context = NarrativeWorld::default().with_entities(
+            ["Ada", "Bo", "Cy"]
+                .into_iter()
+                .enumerate()
+                .map(|(id, name)| Entity::new(id).with("name", name)),
+        );
+        let mut alias_map = AliasMap::default();
+        alias_map.associate_group("villagers".into(), vec![0, 1, 2]);
+        alias_map.associate_group("guards".into(), vec![]);
+
+        let node = ScenarioAction::new()
+            .with_directive("{villagers.count} gather: {villagers.name|list}.{guards.name|list}");
+        assert_eq!(
+            node.directive(&alias_map, &context).unwrap(),
+            "3 gather: Ada, Bo and Cy."
+        );
+
+        for directive in ["{villagers.name}", "{villagers.name|shout}"] {
+            let node = ScenarioAction::new().with_directive(directive);
+            assert!(node.directive(&alias_map, &context).is_err());
+        }
+    }
+
+    #[test]
+    fn line_variants_depend_on_the_bound_entities() {
+        const PROTAG: EntityId = 0;
+        const MESSENGER: EntityId = 1;
+        let node = ScenarioAction::new()
+            .with_directive("say {<a>messenger} \"[bring message]\" [sic]")
+            .with_line(
+                "bring message",
+                [
+                    LineVariant::new("My lord {protag.name}, a message for you.")
+                        .with_alias_constraint("protag", Constraint::equals("wealth", "rich")),
+                    LineVariant::new("Pardon, are you {protag.name}? You owe us.")
+                        .with_relation_constraints(
+                            "messenger",
+                            "protag",
+                            [Constraint::has("owed")],
+                        ),
+                    LineVariant::new("Here, {protag.name}."),
+                ],
+            );
+        let mut alias_map = AliasMap::default();
+        alias_map.associate("protag".into(), PROTAG);
+        alias_map.associate("messenger".into(), MESSENGER);
+
+        let world = |wealth: &str| {
+            NarrativeWorld::default()
+                .with_entity(
+                    Entity::new(PROTAG)
+                        .with("name", "Ysolde")
+                        .with("wealth", wealth),
+                )
+                .with_entity(Entity::new(MESSENGER))
+        };
+        assert_eq!(
+            node.directive(&alias_map, &world("rich")).unwrap(),
+            r#"say 1 "My lord Ysolde, a message for you." [sic]"#
+        );
+        assert_eq!(
+            node.directive(&alias_map, &world("poor")).unwrap(),
+            r#"say 1 "Here, Ysolde." [sic]"#
+        );
+        let indebted = world("poor").with_relation(MESSENGER, PROTAG, "owed", 10);
+        assert_eq!(
+            node.directive(&alias_map, &indebted).unwrap(),
+            r#"say 1 "Pardon, are you Ysolde? You owe us." [sic]"#
+        );
+
+        let node = ScenarioAction::new()
+            .with_directive("[greeting]")
+            .with_line(
+                "greeting",
+                [LineVariant::new("Hail").with_world_constraint(Constraint::has("festival"))],
+            );
+        assert!(node.directive(&alias_map, &world("rich")).is_err());
+    }
+
+    #[test]
+    fn filters_format_placeholders() {
+        let mut alias_map = AliasMap::default();
+        alias_map.associate("vendor".into(), 0);
+        alias_map.associate_group("guards".into(), vec![1, 2]);
+        let context = NarrativeWorld::default()
+            .with_entity(Entity::new(0).with("name", "hialda").with("age", 18.5))
+            .with_entity(Entity::new(1).with("name", "Ada"))
+            .with_entity(Entity::new(2).with("name", "Bo"))
+            .with_world_property("gold", 12500);
+
+        let node = ScenarioAction::new().with_directive(
+            "{vendor.name|capitalize|genitive} shop, {vendor.age|int} years, {gold|thousands} gold, {guards.name|upper|list}",
+        );
+        assert_eq!(
+            node.directive(&alias_map, &context).unwrap(),
+            "Hialda's shop, 18 years, 12,500 gold, ADA and BO"
+        );
+
+        let node = ScenarioAction::new().with_directive("{vendor.name|thousands}");
+        assert_eq!(
+            node.directive(&alias_map, &context)
+                .unwrap_err()
+                .to_string(),
+            r#"Filter error: "thousands" failed on "hialda": not a number (at 0..23)"#
+        );
+    }
+
+    #[test]
+    fn templates_agree_with_the_bound_entities() {
+        let mut alias_map = AliasMap::default();
+        alias_map.associate("vendor".into(), 0);
+        alias_map.associate_group("guards".into(), vec![1, 2]);
+        let context = NarrativeWorld::default()
+            .with_entity(Entity::new(0).with("gender", "female"))
+            .with_entity(Entity::new(1).with("gender", "male"))
+            .with_entity(Entity::new(2))
+            .with_world_property("item", "axe");
+
+        let node = ScenarioAction::new().with_directive(
+            "{<a>vendor|subject|capitalize} {<a>vendor|is/are} selling {item|a}, the guards {<a>guards|is/are} watching {<a>vendor|object}. Greet {<a>vendor|him/her/them}",
+        );
+        assert_eq!(
+            node.directive(&alias_map, &context).unwrap(),
+            "She is selling an axe, the guards are watching her. Greet her"
+        );
+        let node = ScenarioAction::new().with_directive("{<a>vendor} {<a>guards|possessive}");
+        assert_eq!(node.directive(&alias_map, &context).unwrap(), "0 their");
+        // the genitive of anything but an alias
+        let node = ScenarioAction::new()
+            .with_directive("{item|possessive} {<a>vendor|subject|possessive} {<a>vendor|list}");
+        assert_eq!(
+            node.directive(&alias_map, &context).unwrap(),
+            "axe's she's 0"
+        );
+        let node = ScenarioAction::new().with_directive("{item|subject}");
+        assert_eq!(
+            node.directive(&alias_map, &context)
+                .unwrap_err()
+                .to_string(),
+            r#"Filter error: "subject" is unknown (at 0..14)"#
+        );
+
+        let node = ScenarioAction::new().with_directive("{item|he/she/they}");
+        assert_eq!(
+            node.directive(&alias_map, &context)
+                .unwrap_err()
+                .to_string(),
+            r#"Filter error: "he/she/they" must follow an alias, such as in "{<a>npc|he/she/they}" (at 0..18)"#
+        );
+
+        // a bare name is a world property, even when an alias is bound under that name
+        let context = context.with_world_property("vendor", "closed");
+        let node = ScenarioAction::new().with_directive("{vendor} {<a>vendor|he/she/they}");
+        assert_eq!(node.directive(&alias_map, &context).unwrap(), "closed she");
+    }
+
+    #[test]
+    fn directives_render_as_typed_segments() {
+        let mut alias_map = AliasMap::default();
+        alias_map.associate("vendor".into(), 0);
+        alias_map.associate_group("guards".into(), vec![1, 2, 3]);
+        let context = NarrativeWorld::default()
+            .with_entity(
+                Entity::new(0)
+                    .with("name", "Hialda")
+                    .with("gender", "female"),
+            )
+            .with_entity(Entity::new(1))
+            .with_entity(Entity::new(2))
+            .with_entity(Entity::new(3))
+            .with_world_property("gold", 12);
+
+        let node = ScenarioAction::new().with_directive(
+            "speak {<a>vendor} [ask] {<a>guards|list}, {<a>vendor|he/she/they} said",
+        );
+        let node = node.with_line(
+            "ask",
+            [LineVariant::new("\"{gold} for {vendor.name|upper}?\"")],
+        );
+        assert_eq!(
+            node.directive_segments(&alias_map, &context).unwrap(),
+            [
+                TextSegment::Text("speak ".into()),
+                TextSegment::Entity {
+                    alias: "vendor".into(),
+                    id: 0
+                },
+                TextSegment::Text(" \"".into()),
+                TextSegment::Property {
+                    alias: None,
+                    name: "gold".into(),
+                    value: Property::Int(12)
+                },
+                TextSegment::Text(" for ".into()),
+                TextSegment::Property {
+                    alias: Some("vendor".into()),
+                    name: "name".into(),
+                    value: Property::String("HIALDA".into())
+                },
+                TextSegment::Text("?\" ".into()),
+                TextSegment::Entity {
+                    alias: "guards".into(),
+                    id: 1
+                },
+                TextSegment::Text(", ".into()),
+                TextSegment::Entity {
+                    alias: "guards".into(),
+                    id: 2
+                },
+                TextSegment::Text(" and ".into()),
+                TextSegment::Entity {
+                    alias: "guards".into(),
+                    id: 3
+                },
+                TextSegment::Text(", she said".into()),
+            ]
+        );
+        assert_eq!(
+            node.directive(&alias_map, &context).unwrap(),
+            "speak 0 \"12 for HIALDA?\" 1, 2 and 3, she said"
+        );
+    }
 }
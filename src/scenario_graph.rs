@@ -1,86 +1,171 @@
 use itertools::Itertools;
 
+use fixedbitset::FixedBitSet;
 use petgraph::{
     algo::toposort,
-    prelude::{Graph, NodeIndex},
+    prelude::{NodeIndex, StableGraph},
+    visit::{DfsPostOrder, EdgeRef, IntoEdgeReferences, NodeIndexable},
+};
+use serde::{
+    de::{self, value::MapAccessDeserializer, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
 };
-use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
-    error::Error,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt,
-    ops::Index,
+    ops::Range,
+    path::Path,
+    rc::Rc,
 };
 
 use crate::{
+    alias_solver::AliasBindings,
+    constraint::AliasRelation,
     entity::EntityId,
+    error::{self, Error},
+    explain::Explanation,
+    filter::{with_default_filters, Filters},
+    lint::{self, Diagnostic},
     prelude::{Constraint, NarrativeWorld},
+    query::QueryOptions,
+    rng::SeededRng,
     scenario_action::{Alias, ConstrainedAlias, ScenarioAction},
+    shuffle_bag::draw_from_global_shuffle_bags,
+    tag_expr::Tag,
+    template::{concat, TextSegment},
+    transition::Transition,
+    visits::Visits,
 };
 
-#[derive(Default, Clone)]
-pub struct AliasMap(HashMap<Alias, EntityId>);
+// Optional aliases left unbound are kept, so they can be told apart from missing aliases
+#[derive(Default, Clone, Debug)]
+pub struct AliasMap {
+    entities: HashMap<Alias, Option<EntityId>>,
+    groups: HashMap<Alias, Vec<EntityId>>,
+}
 
 impl AliasMap {
     pub(crate) fn associate(&mut self, alias: Alias, entity: EntityId) {
-        self.0.insert(alias, entity);
+        self.entities.insert(alias, Some(entity));
+    }
+
+    pub(crate) fn leave_unbound(&mut self, alias: Alias) {
+        self.entities.insert(alias, None);
+    }
+
+    pub(crate) fn associate_group(&mut self, alias: Alias, members: Vec<EntityId>) {
+        self.groups.insert(alias, members);
+    }
+
+    pub(crate) fn extend(&mut self, other: &AliasMap) {
+        self.entities.extend(
+            other
+                .entities
+                .iter()
+                .map(|(alias, &entity)| (alias.clone(), entity)),
+        );
+        self.groups.extend(
+            other
+                .groups
+                .iter()
+                .map(|(alias, members)| (alias.clone(), members.clone())),
+        );
     }
 
     pub fn get(&self, alias: &str) -> Option<EntityId> {
-        self.0.get(alias).cloned()
+        self.entities.get(alias).copied().flatten()
     }
 
-    pub fn size(&self) -> usize {
-        self.0.len()
+    // the entities bound to a group alias, ordered by id
+    pub fn group(&self, alias: &str) -> Option<&[EntityId]> {
+        self.groups.get(alias).map(Vec::as_slice)
     }
-}
 
-impl Index<&str> for AliasMap {
-    type Output = EntityId;
+    // the entities bound to an alias, whether a group alias or not
+    pub(crate) fn members(&self, alias: &str) -> Option<&[EntityId]> {
+        self.entities
+            .get(alias)
+            .and_then(Option::as_ref)
+            .map(std::slice::from_ref)
+            .or_else(|| self.group(alias))
+    }
 
-    fn index(&self, key: &str) -> &EntityId {
-        self.0.get(key).expect("no entry found for key")
+    // every alias along with its entities, by alias, unbound optional aliases having none
+    pub(crate) fn bindings(&self) -> Vec<(&Alias, &[EntityId])> {
+        self.entities
+            .iter()
+            .map(|(alias, entity)| (alias, entity.as_slice()))
+            .chain(
+                self.groups
+                    .iter()
+                    .map(|(alias, members)| (alias, members.as_slice())),
+            )
+            .sorted_by_key(|&(alias, _)| alias)
+            .collect()
     }
-}
 
-#[derive(Debug)]
-pub struct CycleDetected;
-impl fmt::Display for CycleDetected {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Cycle detected")
+    // whether `alias` is an optional alias which couldn't be bound
+    pub fn is_unbound(&self, alias: &str) -> bool {
+        matches!(self.entities.get(alias), Some(None))
     }
-}
-impl Error for CycleDetected {}
 
-#[derive(Debug)]
-pub struct ConstraintsNotSatisfied;
-impl fmt::Display for ConstraintsNotSatisfied {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Constraints not satisfied")
+    // the number of bound aliases, group aliases aside
+    pub fn size(&self) -> usize {
+        self.entities.values().flatten().count()
     }
-}
-impl Error for ConstraintsNotSatisfied {}
 
-#[derive(Debug)]
-pub struct AliasError(String);
-impl AliasError {
-    pub(crate) fn new<S>(message: S) -> Self
-    where
-        S: Into<String>,
-    {
-        Self(message.into())
+    pub fn iter(&self) -> impl Iterator<Item = (&Alias, EntityId)> {
+        self.entities
+            .iter()
+            .filter_map(|(alias, entity)| entity.map(|entity| (alias, entity)))
     }
 }
-impl fmt::Display for AliasError {
+
+// Why an edit of a graph failed, returned as is by the editing methods and wrapped by
+// `Error::Graph` elsewhere
+#[derive(Debug, PartialEq)]
+pub enum EditError {
+    MissingNode(ScenarioActionId),
+    MissingEdge {
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+    },
+    DuplicateNodeName(NodeName),
+    UnknownNodeName(NodeName),
+    CycleDetected,
+    // restoring a node under an id which is in use
+    NodeTaken(ScenarioActionId),
+}
+impl fmt::Display for EditError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Alias error: {}", self.0)
+        match self {
+            EditError::MissingNode(node) => write!(f, "Missing node {}", node),
+            EditError::MissingEdge { from, to } => {
+                write!(f, "Missing edge from {} to {}", from, to)
+            }
+            EditError::DuplicateNodeName(name) => {
+                write!(f, r#"Node name "{}" is already taken"#, name)
+            }
+            EditError::UnknownNodeName(name) => write!(f, r#"Unknown node "{}""#, name),
+            EditError::CycleDetected => write!(f, "Cycle detected"),
+            EditError::NodeTaken(node) => write!(f, "Node {} already exists", node),
+        }
     }
 }
-impl Error for AliasError {}
+impl std::error::Error for EditError {}
 
-#[derive(Default, Serialize, Deserialize, Clone, Copy)]
+#[derive(
+    Default, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
 pub struct ScenarioActionId(usize);
 
+// `#1`, as nodes without a name are keyed in string tables
+impl fmt::Display for ScenarioActionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
 impl From<NodeIndex> for ScenarioActionId {
     fn from(value: NodeIndex) -> Self {
         ScenarioActionId(value.index())
@@ -93,12 +178,220 @@ impl From<ScenarioActionId> for NodeIndex {
     }
 }
 
-#[derive(Default, Serialize, Deserialize)]
+pub type StoryId = String;
+// unique within a graph, unlike `ScenarioActionId` it can be referenced from content files
+pub type NodeName = String;
+
+// bindings are validated by batches, sharing the work of path validation
+pub(crate) const BINDING_BATCH_SIZE: usize = 4096;
+
+// Sub-scenarios can nest, but a story calling itself would never end
+pub(crate) const MAX_SUB_SCENARIO_DEPTH: usize = 16;
+
+// Resolves the stories called by sub-scenario actions
+pub trait StoryLibrary {
+    fn story(&self, story_id: &str) -> Option<&ScenarioGraph>;
+}
+
+impl StoryLibrary for HashMap<StoryId, Rc<ScenarioGraph>> {
+    fn story(&self, story_id: &str) -> Option<&ScenarioGraph> {
+        self.get(story_id).map(Rc::as_ref)
+    }
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct ScenarioGraph {
+    #[serde(default)]
+    id: StoryId,
+    #[serde(default)]
+    tags: BTreeSet<Tag>,
+    // free form information such as author, version or description
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
     aliases: Vec<ConstrainedAlias>,
-    start_id: ScenarioActionId,
-    graph: Graph<ScenarioAction, f64>,
-    weak_edges: HashMap<NodeIndex, Vec<NodeIndex>>,
+    // defaults to the first node when unset
+    #[serde(default, deserialize_with = "deserialize_start_id")]
+    start_id: Option<ScenarioActionId>,
+    #[serde(default)]
+    node_names: BTreeMap<NodeName, ScenarioActionId>,
+    // node ids stay valid when other nodes are removed
+    #[serde(deserialize_with = "deserialize_graph")]
+    graph: StableGraph<ScenarioAction, Transition>,
+    #[serde(deserialize_with = "deserialize_weak_edges")]
+    weak_edges: WeakEdges,
+}
+
+type WeakEdges = BTreeMap<NodeIndex, Vec<(NodeIndex, Transition)>>;
+
+// Graphs saved before the start node could be unset and edges carried transitions still load, their
+// start node saved as `start_id: (0)`, their edges as plain weights and their weak edges as the
+// target node alone. They are saved in the current format.
+#[derive(Clone)]
+enum SavedTransition {
+    Current(Transition),
+    Legacy(f64),
+}
+
+impl From<SavedTransition> for Transition {
+    fn from(saved: SavedTransition) -> Self {
+        match saved {
+            SavedTransition::Current(transition) => transition,
+            SavedTransition::Legacy(weight) => Transition::new().with_weight(weight),
+        }
+    }
+}
+
+// Visits the value itself rather than a buffered copy, which couldn't hold the enums of constraints
+impl<'de> Deserialize<'de> for SavedTransition {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct TransitionVisitor;
+
+        impl<'de> Visitor<'de> for TransitionVisitor {
+            type Value = SavedTransition;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a transition or a weight")
+            }
+
+            fn visit_f64<E>(self, weight: f64) -> Result<Self::Value, E> {
+                Ok(SavedTransition::Legacy(weight))
+            }
+
+            fn visit_i64<E>(self, weight: i64) -> Result<Self::Value, E> {
+                Ok(SavedTransition::Legacy(weight as f64))
+            }
+
+            fn visit_u64<E>(self, weight: u64) -> Result<Self::Value, E> {
+                Ok(SavedTransition::Legacy(weight as f64))
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                Transition::deserialize(MapAccessDeserializer::new(map))
+                    .map(SavedTransition::Current)
+            }
+        }
+
+        deserializer.deserialize_any(TransitionVisitor)
+    }
+}
+
+// `(to, transition)`, or `to` alone
+struct SavedWeakEdge(NodeIndex, Transition);
+
+impl<'de> Deserialize<'de> for SavedWeakEdge {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct WeakEdgeVisitor;
+
+        impl<'de> Visitor<'de> for WeakEdgeVisitor {
+            type Value = SavedWeakEdge;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a node id along with a transition")
+            }
+
+            fn visit_u64<E>(self, to: u64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                let to = usize::try_from(to).map_err(E::custom)?;
+                Ok(SavedWeakEdge(NodeIndex::new(to), Transition::new()))
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let to = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let transition = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                Ok(SavedWeakEdge(to, transition))
+            }
+        }
+
+        deserializer.deserialize_any(WeakEdgeVisitor)
+    }
+}
+
+fn deserialize_start_id<'de, D>(deserializer: D) -> Result<Option<ScenarioActionId>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct StartIdVisitor;
+
+    impl<'de> Visitor<'de> for StartIdVisitor {
+        type Value = Option<ScenarioActionId>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "an optional node id")
+        }
+
+        fn visit_none<E>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            ScenarioActionId::deserialize(deserializer).map(Some)
+        }
+
+        // `(0)`
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let index = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+            Ok(Some(ScenarioActionId(index)))
+        }
+    }
+
+    deserializer.deserialize_any(StartIdVisitor)
+}
+
+fn deserialize_graph<'de, D>(
+    deserializer: D,
+) -> Result<StableGraph<ScenarioAction, Transition>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let graph = StableGraph::<ScenarioAction, SavedTransition>::deserialize(deserializer)?;
+    // keeps the node and edge ids
+    Ok(graph.map(
+        |_, action| action.clone(),
+        |_, transition| transition.clone().into(),
+    ))
+}
+
+fn deserialize_weak_edges<'de, D>(deserializer: D) -> Result<WeakEdges, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let weak_edges = BTreeMap::<NodeIndex, Vec<SavedWeakEdge>>::deserialize(deserializer)?;
+    Ok(weak_edges
+        .into_iter()
+        .map(|(from, edges)| {
+            let edges = edges
+                .into_iter()
+                .map(|SavedWeakEdge(to, transition)| (to, transition))
+                .collect();
+            (from, edges)
+        })
+        .collect())
 }
 
 impl ScenarioGraph {
@@ -106,8 +399,125 @@ impl ScenarioGraph {
         Self::default()
     }
 
+    // Reads a graph stored as RON, errors pointing to the line and column at fault, as well as
+    // malformed templates
+    pub fn load<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        error::load_ron(path.as_ref())
+    }
+
+    pub fn from_ron(content: &str) -> Result<Self, Error> {
+        error::from_ron(content)
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    // stable identifier, used by `Raconteur` to look up, replace and remove stories
+    pub fn set_id<S>(&mut self, id: S)
+    where
+        S: Into<StoryId>,
+    {
+        self.id = id.into();
+    }
+
+    pub fn tags(&self) -> &BTreeSet<Tag> {
+        &self.tags
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    pub fn add_tag<T>(&mut self, tag: T)
+    where
+        T: Into<Tag>,
+    {
+        self.tags.insert(tag.into());
+    }
+
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        self.tags.remove(tag)
+    }
+
+    pub fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+
+    pub fn set_metadata<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.metadata.insert(key.into(), value.into());
+    }
+
     pub fn start(&self) -> ScenarioActionId {
         self.start_id
+            .or_else(|| self.graph.node_indices().next().map(ScenarioActionId::from))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn is_start_set(&self) -> bool {
+        self.start_id.is_some()
+    }
+
+    pub(crate) fn aliases(&self) -> &[ConstrainedAlias] {
+        &self.aliases
+    }
+
+    pub(crate) fn node_ids(&self) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.graph.node_indices()
+    }
+
+    pub(crate) fn node_count(&self) -> usize {
+        self.graph.node_count()
+    }
+
+    // upper bound of node indices, which may have holes once nodes are removed
+    pub(crate) fn node_bound(&self) -> usize {
+        self.graph.node_bound()
+    }
+
+    pub fn contains(&self, node_id: ScenarioActionId) -> bool {
+        self.graph.contains_node(node_id.into())
+    }
+
+    // ids of the stories called by sub-scenario actions
+    pub(crate) fn sub_scenario_ids(&self) -> impl Iterator<Item = &str> {
+        self.graph
+            .node_weights()
+            .filter_map(|action| action.sub_scenario.as_ref())
+            .map(|sub_scenario| sub_scenario.story.as_str())
+            .unique()
+    }
+
+    pub(crate) fn weak_connections(&self, node_id: NodeIndex) -> Vec<NodeIndex> {
+        self.weak_edges
+            .get(&node_id)
+            .map(|edges| edges.iter().map(|(to, _)| *to).collect_vec())
+            .unwrap_or_default()
+    }
+
+    // outgoing edges of a node, weak edges last
+    pub(crate) fn transitions(&self, node_id: NodeIndex) -> Vec<(NodeIndex, &Transition)> {
+        let mut transitions = self
+            .graph
+            .edges(node_id)
+            .map(|edge| (edge.target(), edge.weight()))
+            .collect_vec();
+
+        transitions.extend(
+            self.weak_edges
+                .get(&node_id)
+                .into_iter()
+                .flatten()
+                .map(|(to, transition)| (*to, transition)),
+        );
+        transitions
     }
 
     pub fn add_alias<A, C>(&mut self, alias: A, constraints: C)
@@ -118,18 +528,123 @@ impl ScenarioGraph {
         self.aliases.push(ConstrainedAlias::new(alias, constraints));
     }
 
-    pub fn get(&self, node_id: ScenarioActionId) -> &ScenarioAction {
-        &self.graph[NodeIndex::from(node_id)]
+    // An alias bound whenever an entity satisfies its constraints, and left unbound otherwise.
+    // Directive placeholders naming it render as nothing and relation constraints on it don't apply.
+    pub fn add_optional_alias<A, C>(&mut self, alias: A, constraints: C)
+    where
+        A: Into<Alias>,
+        C: IntoIterator<Item = Constraint>,
+    {
+        self.aliases.push(ConstrainedAlias {
+            optional: true,
+            ..ConstrainedAlias::new(alias, constraints)
+        });
+    }
+
+    // An alias bound to every entity satisfying its constraints and not bound to another alias,
+    // by increasing id and up to the end of `size`. The story isn't playable with fewer than its start.
+    pub fn add_group_alias<A, C>(&mut self, alias: A, constraints: C, size: Range<usize>)
+    where
+        A: Into<Alias>,
+        C: IntoIterator<Item = Constraint>,
+    {
+        self.aliases.push(ConstrainedAlias {
+            group: Some(size),
+            ..ConstrainedAlias::new(alias, constraints)
+        });
+    }
+
+    // Lets two aliases bind the same entity. Returns `false` if `alias` isn't declared.
+    pub fn allow_coinciding<A>(&mut self, alias: &str, other: A) -> bool
+    where
+        A: Into<Alias>,
+    {
+        match self
+            .aliases
+            .iter_mut()
+            .find(|constrained_alias| constrained_alias.alias() == alias)
+        {
+            Some(constrained_alias) => {
+                constrained_alias.may_coincide_with.push(other.into());
+                true
+            }
+            None => false,
+        }
+    }
+
+    // `None` if there is no such node
+    pub fn get(&self, node_id: ScenarioActionId) -> Option<&ScenarioAction> {
+        self.graph.node_weight(node_id.into())
+    }
+
+    // Renders the directive of a node with the built-in filters, errors pointing to the story and
+    // node. Alternatives are picked from shuffle bags shared by the thread.
+    pub fn directive(
+        &self,
+        node_id: ScenarioActionId,
+        alias_map: &AliasMap,
+        context: &NarrativeWorld,
+    ) -> Result<String, Error> {
+        with_default_filters(|filters| self.directive_with(node_id, alias_map, context, filters))
+    }
+
+    pub fn directive_with(
+        &self,
+        node_id: ScenarioActionId,
+        alias_map: &AliasMap,
+        context: &NarrativeWorld,
+        filters: &Filters,
+    ) -> Result<String, Error> {
+        self.directive_segments_with(node_id, alias_map, context, filters)
+            .map(|segments| concat(&segments))
+    }
+
+    pub fn directive_segments_with(
+        &self,
+        node_id: ScenarioActionId,
+        alias_map: &AliasMap,
+        context: &NarrativeWorld,
+        filters: &Filters,
+    ) -> Result<Vec<TextSegment>, Error> {
+        let action = self.get(node_id).ok_or_else(|| Error::Graph {
+            story: Some(self.id().into()),
+            error: EditError::MissingNode(node_id),
+        })?;
+        draw_from_global_shuffle_bags(&self.shuffle_owner(node_id), |draws| {
+            action.render_directive(alias_map, context, filters, &[], draws)
+        })
+        .map_err(|error| error.in_node(self, node_id))
+    }
+
+    // the key of the shuffle bags of the alternatives of a node, such as `market#3`
+    pub(crate) fn shuffle_owner(&self, node_id: ScenarioActionId) -> String {
+        format!("{}{}", self.id(), node_id)
     }
 
-    pub(crate) fn connections(&self, node_id: NodeIndex) -> Vec<NodeIndex> {
-        self.graph.neighbors(node_id).collect()
+    // `"haggle" (#1)` for a named node, `#1` otherwise
+    pub(crate) fn node_label(&self, node_id: ScenarioActionId) -> String {
+        match self.node_name(node_id) {
+            Some(name) => format!(r#""{}" ({})"#, name, node_id),
+            None => node_id.to_string(),
+        }
+    }
+
+    // for ids handed out by the graph itself, such as those of `node_ids`
+    pub(crate) fn action(&self, node_id: NodeIndex) -> &ScenarioAction {
+        &self.graph[node_id]
+    }
+
+    pub(crate) fn strong_connections(
+        &self,
+        node_id: NodeIndex,
+    ) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.graph.neighbors(node_id)
     }
 
     pub(crate) fn all_connections(&self, node_id: NodeIndex) -> Vec<NodeIndex> {
-        let mut connections = self.graph.neighbors(node_id).collect_vec();
+        let mut connections = self.strong_connections(node_id).collect_vec();
 
-        connections.extend(self.weak_edges.get(&node_id).unwrap_or(&Vec::default()));
+        connections.extend(self.weak_connections(node_id));
         connections
     }
 
@@ -139,31 +654,320 @@ impl ScenarioGraph {
         context: &NarrativeWorld,
         alias_map: &AliasMap,
     ) -> Vec<ScenarioActionId> {
-        self.all_connections(node_id.into())
+        self.open_transitions(node_id, context, alias_map, &Visits::default())
             .into_iter()
-            .filter(|&index| {
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    // Same as `next`, along with the weight of each choice, from heaviest to lightest.
+    // Choices of equal weight keep the order of `next`.
+    pub fn next_ranked(
+        &self,
+        node_id: ScenarioActionId,
+        context: &NarrativeWorld,
+        alias_map: &AliasMap,
+    ) -> Vec<(ScenarioActionId, f64)> {
+        let mut choices = self.open_transitions(node_id, context, alias_map, &Visits::default());
+        choices.sort_by(|(_, lhs), (_, rhs)| rhs.total_cmp(lhs));
+        choices
+    }
+
+    // Picks one of the choices of `next`, with a probability proportional to its weight.
+    // Choices are picked uniformly when no weight is positive. Returns `None` at a leaf.
+    pub fn next_random(
+        &self,
+        node_id: ScenarioActionId,
+        context: &NarrativeWorld,
+        alias_map: &AliasMap,
+        rng: &mut SeededRng,
+    ) -> Option<ScenarioActionId> {
+        let choices = self.open_transitions(node_id, context, alias_map, &Visits::default());
+        rng.weighted_index(choices.iter().map(|(_, weight)| *weight))
+            .map(|index| choices[index].0)
+    }
+
+    // Edges whose own constraints and target node constraints are satisfied.
+    // `next`, `next_ranked` and `next_random` see nodes as never visited, `Scenario` tracks actual visits.
+    pub(crate) fn open_transitions(
+        &self,
+        node_id: ScenarioActionId,
+        context: &NarrativeWorld,
+        alias_map: &AliasMap,
+        visits: &Visits,
+    ) -> Vec<(ScenarioActionId, f64)> {
+        self.transitions(node_id.into())
+            .into_iter()
+            .filter(|&(index, transition)| {
                 let node = &self.graph[index];
-                node.are_world_constraints_satisfied(context)
-                    && node.are_relation_constraints_satisfied(context, alias_map)
+                transition.can_traverse(visits.edge(node_id, index.into()))
+                    && node.are_visit_constraints_satisfied(index.into(), visits)
+                    && transition.are_world_constraints_satisfied(context)
+                    && transition.are_relation_constraints_satisfied(context, alias_map)
+                    && node.are_world_constraints_satisfied(context)
+                    && node.can_enter(context, alias_map)
             })
-            .map(ScenarioActionId::from)
+            .map(|(index, transition)| (index.into(), transition.weight))
             .collect()
     }
 
     pub fn set_start_node(&mut self, node_id: ScenarioActionId) {
-        self.start_id = node_id;
+        self.start_id = Some(node_id);
+    }
+
+    // the start node as set, unlike `start` which defaults to the first node
+    pub(crate) fn start_id(&self) -> Option<ScenarioActionId> {
+        self.start_id
+    }
+
+    pub fn clear_start_node(&mut self) {
+        self.start_id = None;
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (ScenarioActionId, &ScenarioAction)> {
+        self.graph
+            .node_indices()
+            .map(|node_id| (node_id.into(), &self.graph[node_id]))
+    }
+
+    // strong edges as (from, to, transition)
+    pub fn edges(&self) -> impl Iterator<Item = (ScenarioActionId, ScenarioActionId, &Transition)> {
+        self.graph
+            .edge_references()
+            .map(|edge| (edge.source().into(), edge.target().into(), edge.weight()))
+    }
+
+    // weak edges as (from, to, transition), ordered by origin
+    pub fn weak_edges(
+        &self,
+    ) -> impl Iterator<Item = (ScenarioActionId, ScenarioActionId, &Transition)> {
+        self.weak_edges.iter().flat_map(|(&from, edges)| {
+            edges
+                .iter()
+                .map(move |(to, transition)| (from.into(), (*to).into(), transition))
+        })
+    }
+
+    // Replaces the action of a node, keeping its edges. Returns the previous action.
+    pub fn replace(
+        &mut self,
+        node_id: ScenarioActionId,
+        story_node: ScenarioAction,
+    ) -> Option<ScenarioAction> {
+        self.graph
+            .node_weight_mut(node_id.into())
+            .map(|action| std::mem::replace(action, story_node))
+    }
+
+    // re-adds a removed node under its former id, which must be free
+    pub(crate) fn restore(
+        &mut self,
+        node_id: ScenarioActionId,
+        action: ScenarioAction,
+    ) -> Result<(), EditError> {
+        if self.contains(node_id) {
+            return Err(EditError::NodeTaken(node_id));
+        }
+        let index: NodeIndex = node_id.into();
+        // placeholders take the free ids handed out before it, and are removed once it's in place
+        let mut placeholders = vec![];
+        loop {
+            let added = self.graph.add_node(ScenarioAction::new());
+            if added == index {
+                break;
+            }
+            placeholders.push(added);
+        }
+        self.graph[index] = action;
+        for placeholder in placeholders {
+            self.graph.remove_node(placeholder);
+        }
+        Ok(())
+    }
+
+    // Removes a node along with its edges, weak edges and name. The start node is unset if removed.
+    // Other node ids are unaffected.
+    pub fn remove(&mut self, node_id: ScenarioActionId) -> Option<ScenarioAction> {
+        let index: NodeIndex = node_id.into();
+        let action = self.graph.remove_node(index)?;
+        self.weak_edges.remove(&index);
+        for edges in self.weak_edges.values_mut() {
+            edges.retain(|(to, _)| *to != index);
+        }
+        self.weak_edges.retain(|_, edges| !edges.is_empty());
+        self.node_names.retain(|_, &mut named| named != node_id);
+        if self.start_id == Some(node_id) {
+            self.start_id = None;
+        }
+        Some(action)
+    }
+
+    // Removes the newest strong edge from `from` to `to`, returning its transition
+    pub fn disconnect(
+        &mut self,
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+    ) -> Option<Transition> {
+        let edge = self.graph.find_edge(from.into(), to.into())?;
+        self.graph.remove_edge(edge)
+    }
+
+    // Removes the oldest weak edge from `from` to `to`, returning its transition
+    pub fn disconnect_weak(
+        &mut self,
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+    ) -> Option<Transition> {
+        let from = from.into();
+        let edges = self.weak_edges.get_mut(&from)?;
+        let position = edges.iter().position(|(target, _)| *target == to.into())?;
+        let (_, transition) = edges.remove(position);
+        if edges.is_empty() {
+            self.weak_edges.remove(&from);
+        }
+        Some(transition)
+    }
+
+    // Moves the strong edge from `from` to `to` between other nodes, keeping its transition.
+    // The edge is left untouched if this would create a cycle.
+    pub fn rewire(
+        &mut self,
+        (from, to): (ScenarioActionId, ScenarioActionId),
+        (new_from, new_to): (ScenarioActionId, ScenarioActionId),
+    ) -> Result<(), EditError> {
+        self.check_nodes([new_from, new_to])?;
+        let transition = self
+            .disconnect(from, to)
+            .ok_or(EditError::MissingEdge { from, to })?;
+        if let Err(error) = self.connect_with(new_from, new_to, transition.clone()) {
+            self.connect_with(from, to, transition)?;
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Moves the weak edge from `from` to `to` between other nodes, keeping its transition
+    pub fn rewire_weak(
+        &mut self,
+        (from, to): (ScenarioActionId, ScenarioActionId),
+        (new_from, new_to): (ScenarioActionId, ScenarioActionId),
+    ) -> Result<(), EditError> {
+        self.check_nodes([new_from, new_to])?;
+        let transition = self
+            .disconnect_weak(from, to)
+            .ok_or(EditError::MissingEdge { from, to })?;
+        self.connect_weak_with(new_from, new_to, transition)?;
+        Ok(())
+    }
+
+    pub(crate) fn check_nodes<I>(&self, node_ids: I) -> Result<(), EditError>
+    where
+        I: IntoIterator<Item = ScenarioActionId>,
+    {
+        match node_ids
+            .into_iter()
+            .find(|&node_id| !self.contains(node_id))
+        {
+            Some(node_id) => Err(EditError::MissingNode(node_id)),
+            None => Ok(()),
+        }
     }
 
     pub fn add(&mut self, story_node: ScenarioAction) -> ScenarioActionId {
         self.graph.add_node(story_node).into()
     }
 
+    // Same as `add`, naming the node. Nothing is added if the name is taken.
+    pub fn add_named<N>(
+        &mut self,
+        name: N,
+        story_node: ScenarioAction,
+    ) -> Result<ScenarioActionId, EditError>
+    where
+        N: Into<NodeName>,
+    {
+        let name = name.into();
+        if self.node_names.contains_key(&name) {
+            return Err(EditError::DuplicateNodeName(name));
+        }
+        let node_id = self.add(story_node);
+        self.node_names.insert(name, node_id);
+        Ok(node_id)
+    }
+
+    // Names or renames a node
+    pub fn set_node_name<N>(&mut self, node_id: ScenarioActionId, name: N) -> Result<(), EditError>
+    where
+        N: Into<NodeName>,
+    {
+        let name = name.into();
+        match self.node_names.get(&name) {
+            Some(&named) if named == node_id => Ok(()),
+            Some(_) => Err(EditError::DuplicateNodeName(name)),
+            None => {
+                self.node_names.retain(|_, &mut named| named != node_id);
+                self.node_names.insert(name, node_id);
+                Ok(())
+            }
+        }
+    }
+
+    // Returns the name the node had, if any
+    pub fn remove_node_name(&mut self, node_id: ScenarioActionId) -> Option<NodeName> {
+        let name = self.node_name(node_id)?.to_string();
+        self.node_names.remove(&name);
+        Some(name)
+    }
+
+    pub fn node_id(&self, name: &str) -> Option<ScenarioActionId> {
+        self.node_names.get(name).copied()
+    }
+
+    pub fn node_name(&self, node_id: ScenarioActionId) -> Option<&str> {
+        self.node_names
+            .iter()
+            .find(|(_, &named)| named == node_id)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub(crate) fn node_names(&self) -> &BTreeMap<NodeName, ScenarioActionId> {
+        &self.node_names
+    }
+
+    fn named(&self, name: &str) -> Result<ScenarioActionId, EditError> {
+        self.node_id(name)
+            .ok_or_else(|| EditError::UnknownNodeName(name.into()))
+    }
+
+    // `connect_with` for named nodes
+    pub fn connect_named<T>(&mut self, from: &str, to: &str, transition: T) -> Result<(), EditError>
+    where
+        T: Into<Transition>,
+    {
+        let (from, to) = (self.named(from)?, self.named(to)?);
+        self.connect_with(from, to, transition)
+    }
+
+    // `connect_weak_with` for named nodes
+    pub fn connect_weak_named<T>(
+        &mut self,
+        from: &str,
+        to: &str,
+        transition: T,
+    ) -> Result<(), EditError>
+    where
+        T: Into<Transition>,
+    {
+        let (from, to) = (self.named(from)?, self.named(to)?);
+        self.connect_weak_with(from, to, transition)
+    }
+
     pub fn connect(
         &mut self,
         from: ScenarioActionId,
         to: ScenarioActionId,
-    ) -> Result<(), CycleDetected> {
-        self.connect_weight(from, to, 0.0)
+    ) -> Result<(), EditError> {
+        self.connect_with(from, to, Transition::new())
     }
 
     pub fn connect_weight(
@@ -171,11 +975,27 @@ impl ScenarioGraph {
         parent: ScenarioActionId,
         child: ScenarioActionId,
         weight: f64,
-    ) -> Result<(), CycleDetected> {
-        let edge = self.graph.add_edge(parent.into(), child.into(), weight);
+    ) -> Result<(), EditError> {
+        self.connect_with(parent, child, weight)
+    }
+
+    // connects two nodes through an edge carrying its own weight and constraints
+    pub fn connect_with<T>(
+        &mut self,
+        parent: ScenarioActionId,
+        child: ScenarioActionId,
+        transition: T,
+    ) -> Result<(), EditError>
+    where
+        T: Into<Transition>,
+    {
+        self.check_nodes([parent, child])?;
+        let edge = self
+            .graph
+            .add_edge(parent.into(), child.into(), transition.into());
         toposort(&self.graph, None).map(|_| ()).map_err(|_| {
             self.graph.remove_edge(edge);
-            CycleDetected
+            EditError::CycleDetected
         })
     }
 
@@ -184,188 +1004,383 @@ impl ScenarioGraph {
         &mut self,
         from: ScenarioActionId,
         to: ScenarioActionId,
-    ) -> Result<(), CycleDetected> {
+    ) -> Result<(), EditError> {
+        self.connect_weak_with(from, to, Transition::new())
+    }
+
+    pub fn connect_weak_with<T>(
+        &mut self,
+        from: ScenarioActionId,
+        to: ScenarioActionId,
+        transition: T,
+    ) -> Result<(), EditError>
+    where
+        T: Into<Transition>,
+    {
+        self.check_nodes([from, to])?;
         self.weak_edges
             .entry(from.into())
-            .or_insert(Vec::default())
-            .push(to.into());
+            .or_default()
+            .push((to.into(), transition.into()));
         Ok(())
     }
 
+    // Lints the graph for problems such as an unset start node, unreachable nodes, weak edge loops
+    // without exit or undeclared aliases. Meant to be called before handing the graph to `Raconteur`.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        lint::validate(self)
+    }
+
+    // Reports why the story does or doesn't match a world: the candidates of each alias and the
+    // entities which came closest, the start node's relations, and the constraints failing along
+    // each path to a leaf. Sub-scenarios are not checked, as with `valid_alias_bindings`.
+    pub fn explain(&self, context: &NarrativeWorld) -> Explanation {
+        self.explain_with(context, &QueryOptions::default())
+    }
+
+    pub fn explain_with(&self, context: &NarrativeWorld, options: &QueryOptions) -> Explanation {
+        Explanation::new(self, context, options, None)
+    }
+
     pub fn num_alias_constraints(&self) -> usize {
-        return self
-            .aliases
+        self.aliases
             .iter()
             .map(|constrained_alias| constrained_alias.constraints.len())
-            .fold(0, |acc, val| acc + val);
+            .sum()
     }
 
-    pub fn alias_candidates(
+    pub fn alias_candidates(&self, context: &NarrativeWorld) -> Result<Vec<AliasMap>, Error> {
+        self.alias_candidates_with(context, &QueryOptions::default())
+    }
+
+    // Same as `alias_candidates`, with pinned aliases and focus entities narrowing down the candidates
+    pub fn alias_candidates_with(
         &self,
         context: &NarrativeWorld,
-    ) -> Result<Vec<AliasMap>, ConstraintsNotSatisfied> {
-        if self.aliases.is_empty() {
-            return Ok(vec![]);
-        }
-
+        options: &QueryOptions,
+    ) -> Result<Vec<AliasMap>, Error> {
         if 0 == self.graph.node_count() {
             return Ok(vec![]);
         }
 
-        // TODO: what if start node not set? set automatically to first inserted node?
-
-        // assert at least one valid alias permutation
-        let permutations = self.alias_permutations(context);
-
-        if permutations.is_empty() {
-            return Err(ConstraintsNotSatisfied);
-        }
-
-        let node = collect_tree(self.start(), self);
-        let permutation_indices = HashSet::from_iter(0..permutations.len());
-        let valid_permutation_indices =
-            valid_alias_permutations(&node, context, &permutations, &permutation_indices);
-
-        let valid_permutations = permutations
-            .into_iter()
-            .enumerate()
-            .filter_map(|(index, permutation)| {
-                valid_permutation_indices
-                    .contains(&index)
-                    .then_some(permutation)
-            })
-            .collect_vec();
+        let valid_permutations = self.valid_alias_bindings(context, options).collect_vec();
 
         let any_valid_permutation = !valid_permutations.is_empty();
         any_valid_permutation
             .then_some(valid_permutations)
-            .ok_or(ConstraintsNotSatisfied)
-    }
-
-    // return list of possible alias permutations
-    // Doesn't validate relation constraints, a those can vary from node to node and thus affect which choices are available
-    fn alias_permutations(&self, context: &NarrativeWorld) -> Vec<AliasMap> {
-        let alias_candidates: HashMap<_, _> = self
-            .aliases
-            .iter()
-            .map(|constrained_alias| {
-                let valid_entities = context
-                    .entities()
-                    .filter(|entity| constrained_alias.is_satisfied_by(entity))
-                    .map(|entity| entity.id())
-                    .collect_vec();
-                (constrained_alias.alias().clone(), valid_entities)
+            .ok_or_else(|| Error::ConstraintsNotSatisfied {
+                story: self.id().to_string(),
             })
-            .collect();
-
-        if alias_candidates.is_empty() {
-            return vec![];
-        }
+    }
 
-        let (first_alias, first_candidates) = alias_candidates.iter().next().unwrap();
-        let mut permutations = first_candidates
-            .iter()
-            .map(|id| HashMap::from([(*id, first_alias)]))
-            .collect_vec();
+    // Lazily enumerates alias bindings for which at least one leaf is reachable from the start node.
+    // Bindings are validated in batches against the graph, sharing the work of converging branches.
+    // Sub-scenarios are not checked, as they can only be resolved by `Raconteur`.
+    pub fn valid_alias_bindings<'a>(
+        &'a self,
+        context: &'a NarrativeWorld,
+        options: &'a QueryOptions,
+    ) -> impl Iterator<Item = AliasMap> + 'a {
+        self.valid_alias_bindings_in(context, options, None, 0)
+    }
 
-        for (alias, candidates) in alias_candidates.iter().skip(1) {
-            permutations = permutations
+    // Same as `valid_alias_bindings`, also requiring the sub-scenarios along the path to be playable
+    // with the entities of their parent aliases.
+    pub(crate) fn valid_alias_bindings_in<'a>(
+        &'a self,
+        context: &'a NarrativeWorld,
+        options: &'a QueryOptions,
+        library: Option<&'a dyn StoryLibrary>,
+        depth: usize,
+    ) -> impl Iterator<Item = AliasMap> + 'a {
+        let validator = PathValidator::new(self, context, library, depth);
+        let mut bindings = self.alias_bindings(context, options);
+        std::iter::from_fn(move || {
+            let batch = bindings.by_ref().take(BINDING_BATCH_SIZE).collect_vec();
+            (!batch.is_empty()).then_some(batch)
+        })
+        .flat_map(move |batch| {
+            let valid_indices = validator.valid_bindings(&batch);
+            batch
                 .into_iter()
-                .cartesian_product(candidates.iter().cloned())
-                .map(|(mut ids, id)| {
-                    ids.insert(id, alias);
-                    ids
+                .enumerate()
+                .filter_map(move |(index, binding)| {
+                    valid_indices.contains(index).then_some(binding)
                 })
-                .collect();
-        }
-        permutations.retain(|permutation| permutation.len() == self.aliases.len());
+        })
+    }
+
+    // Lazily enumerates alias bindings satisfying the alias constraints and the start node's relation constraints.
+    // Relation constraints of later nodes can vary from node to node and thus affect which choices are available,
+    // so they are left to path validation. The start node is entered right away, so its local aliases are bound too.
+    pub fn alias_bindings<'a>(
+        &'a self,
+        context: &'a NarrativeWorld,
+        options: &'a QueryOptions,
+    ) -> impl Iterator<Item = AliasMap> + 'a {
+        let start = self.graph.node_weight(self.start().into());
+        let start_relations = start
+            .map(|start| start.relation_constraints.as_slice())
+            .unwrap_or_default();
+        let start_aliases = start.map(|start| start.local_aliases()).unwrap_or_default();
+        AliasBindings::new(
+            self.aliases.iter().chain(start_aliases),
+            start_relations,
+            context,
+            options,
+        )
+    }
 
-        let mut alias_permutations = vec![];
-        for permutation in permutations {
-            let mut alias_permutation = AliasMap::default();
-            for (entity, alias) in permutation {
-                alias_permutation.associate(alias.clone(), entity);
+    // Nodes reachable from the start through strong edges which can't be played through to a leaf
+    // with any valid binding in a world
+    pub(crate) fn unplayable_nodes(&self, context: &NarrativeWorld) -> FixedBitSet {
+        let validator = PathValidator::new(self, context, None, 0);
+        let options = QueryOptions::default();
+        let mut bindings = self.alias_bindings(context, &options);
+        let mut playable = FixedBitSet::with_capacity(self.node_bound());
+        loop {
+            let batch = bindings.by_ref().take(BINDING_BATCH_SIZE).collect_vec();
+            if batch.is_empty() {
+                break;
             }
-            alias_permutations.push(alias_permutation);
+            playable.union_with(&validator.playable_nodes(&batch));
         }
+        validator
+            .order
+            .iter()
+            .map(|node_id| node_id.index())
+            .filter(|&index| !playable.contains(index))
+            .collect()
+    }
 
-        alias_permutations
+    // local aliases of nodes other than the start, only bound as the scenario is played
+    pub(crate) fn late_bound_aliases(&self) -> HashSet<&str> {
+        let start: NodeIndex = self.start().into();
+        self.graph
+            .node_indices()
+            .filter(|&node_id| node_id != start)
+            .flat_map(|node_id| self.graph[node_id].local_aliases())
+            .map(|local_alias| local_alias.alias().as_str())
+            .collect()
     }
 }
 
-struct Node<'a> {
-    pub story: &'a ScenarioAction,
-    pub children: Vec<Node<'a>>,
-    pub is_leaf: bool,
+// Memoizes, for every node reachable from the start, the bindings for which a leaf can be reached from that node.
+// A node only ever filters the bindings reaching it, so that set doesn't depend on the path taken to the node
+// and converging branches are evaluated once.
+struct PathValidator<'a> {
+    story_graph: &'a ScenarioGraph,
+    context: &'a NarrativeWorld,
+    library: Option<&'a dyn StoryLibrary>,
+    depth: usize,
+    // relations on these can't be checked before the scenario is played, and are assumed satisfied
+    late_bound_aliases: HashSet<&'a str>,
+    // post order from the start node, children come before their parents
+    order: Vec<NodeIndex>,
+    // world constraints don't depend on bindings, so they are checked once per node
+    open_nodes: FixedBitSet,
 }
 
-fn collect_tree(node_id: ScenarioActionId, story_graph: &ScenarioGraph) -> Node {
-    let mut node = Node {
-        story: story_graph.get(node_id),
-        children: vec![],
-        is_leaf: false,
-    };
+impl<'a> PathValidator<'a> {
+    fn new(
+        story_graph: &'a ScenarioGraph,
+        context: &'a NarrativeWorld,
+        library: Option<&'a dyn StoryLibrary>,
+        depth: usize,
+    ) -> Self {
+        let graph = &story_graph.graph;
+        let start = story_graph.start().into();
+        let mut order = vec![];
+        if graph.node_weight(start).is_some() {
+            let mut dfs = DfsPostOrder::new(graph, start);
+            while let Some(node_id) = dfs.next(graph) {
+                order.push(node_id);
+            }
+        }
 
-    node.is_leaf = story_graph.all_connections(node_id.into()).is_empty();
+        let mut open_nodes = FixedBitSet::with_capacity(graph.node_bound());
+        for &node_id in &order {
+            open_nodes.set(
+                node_id.index(),
+                graph[node_id].are_world_constraints_satisfied(context),
+            );
+        }
 
-    for child_id in story_graph.connections(node_id.into()) {
-        node.children
-            .push(collect_tree(child_id.into(), story_graph));
+        Self {
+            story_graph,
+            context,
+            library,
+            depth,
+            late_bound_aliases: story_graph.late_bound_aliases(),
+            order,
+            open_nodes,
+        }
     }
 
-    node
-}
+    fn are_relation_constraints_satisfied(
+        &self,
+        relations: &[AliasRelation],
+        binding: &AliasMap,
+    ) -> bool {
+        relations
+            .iter()
+            .filter(|relation| {
+                !self.late_bound_aliases.contains(relation.me.as_str())
+                    && !self.late_bound_aliases.contains(relation.other.as_str())
+            })
+            .all(|relation| relation.is_satisfied_in(self.context, binding))
+    }
 
-// returns list of indices of valid bindings
-// TODO: also return num of constraints along path to leaf
-fn valid_alias_permutations(
-    node: &Node,
-    context: &NarrativeWorld,
-    alias_binding_permutations: &[AliasMap],
-    parent_valid_indices: &HashSet<usize>,
-) -> HashSet<usize> {
-    if !node.story.are_world_constraints_satisfied(context) {
-        return HashSet::default();
-    }
-
-    let valid_indices: HashSet<_> = alias_binding_permutations
-        .iter()
-        .enumerate()
-        .filter(|(index, permutation)| {
-            parent_valid_indices.contains(index)
-                && node
-                    .story
-                    .are_relation_constraints_satisfied(context, permutation)
-        })
-        .map(|(index, _)| index)
-        .collect();
-
-    if node.children.is_empty() {
-        if node.is_leaf {
-            return valid_indices;
-        } else {
-            return HashSet::default();
+    // returns the indices of the bindings for which a leaf is reachable from the start node
+    // TODO: also return num of constraints along path to leaf
+    fn valid_bindings(&self, bindings: &[AliasMap]) -> FixedBitSet {
+        let start: NodeIndex = self.story_graph.start().into();
+        let mut reachable = self.leaf_bindings(bindings);
+        reachable
+            .get_mut(start.index())
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    // Returns the nodes lying on a path from the start to a leaf for at least one of the bindings,
+    // walking the graph from the start with the bindings able to finish from each node.
+    fn playable_nodes(&self, bindings: &[AliasMap]) -> FixedBitSet {
+        let graph = &self.story_graph.graph;
+        let leaf_bindings = self.leaf_bindings(bindings);
+        let mut arriving = vec![FixedBitSet::default(); graph.node_bound()];
+        let mut playable = FixedBitSet::with_capacity(graph.node_bound());
+
+        let start: NodeIndex = self.story_graph.start().into();
+        if let Some(valid) = leaf_bindings.get(start.index()) {
+            arriving[start.index()] = valid.clone();
+        }
+        for &node_id in self.order.iter().rev() {
+            let valid = std::mem::take(&mut arriving[node_id.index()]);
+            if valid.is_clear() {
+                continue;
+            }
+            playable.insert(node_id.index());
+            for edge in graph.edges(node_id) {
+                let transition = edge.weight();
+                if !transition.are_world_constraints_satisfied(self.context) {
+                    continue;
+                }
+                let target = edge.target().index();
+                let mut passing = valid.clone();
+                passing.intersect_with(&leaf_bindings[target]);
+                let passing = passing.ones().filter(|&index| {
+                    self.are_relation_constraints_satisfied(
+                        &transition.relation_constraints,
+                        &bindings[index],
+                    )
+                });
+                arriving[target].grow(bindings.len());
+                arriving[target].extend(passing);
+            }
         }
+        playable
     }
 
-    let mut final_valid_indices = HashSet::default();
-    for child_node in &node.children {
-        let child_valid_indices = valid_alias_permutations(
-            child_node,
-            context,
-            alias_binding_permutations,
-            &valid_indices,
-        );
-        final_valid_indices.extend(child_valid_indices);
+    // for every node, the indices of the bindings for which a leaf is reachable from that node
+    fn leaf_bindings(&self, bindings: &[AliasMap]) -> Vec<FixedBitSet> {
+        let graph = &self.story_graph.graph;
+        let mut reachable = vec![FixedBitSet::default(); graph.node_bound()];
+
+        for &node_id in &self.order {
+            let mut valid = FixedBitSet::with_capacity(bindings.len());
+            if self.open_nodes.contains(node_id.index()) {
+                let mut edges = graph.edges(node_id).peekable();
+                if edges.peek().is_none() {
+                    let is_leaf = self.story_graph.all_connections(node_id).is_empty();
+                    if is_leaf {
+                        valid.insert_range(..);
+                    }
+                }
+                for edge in edges {
+                    let transition = edge.weight();
+                    if !transition.are_world_constraints_satisfied(self.context) {
+                        continue;
+                    }
+                    let child_valid = &reachable[edge.target().index()];
+                    if transition.relation_constraints.is_empty() {
+                        valid.union_with(child_valid);
+                    } else {
+                        valid.extend(child_valid.ones().filter(|&index| {
+                            self.are_relation_constraints_satisfied(
+                                &transition.relation_constraints,
+                                &bindings[index],
+                            )
+                        }));
+                    }
+                }
+
+                // only bindings which can still reach a leaf are checked
+                let action = &graph[node_id];
+                if !action.relation_constraints.is_empty() || action.sub_scenario.is_some() {
+                    let rejected = valid
+                        .ones()
+                        .filter(|&index| {
+                            !self.are_relation_constraints_satisfied(
+                                &action.relation_constraints,
+                                &bindings[index],
+                            ) || !is_sub_scenario_playable(
+                                action,
+                                self.context,
+                                &bindings[index],
+                                self.library,
+                                self.depth,
+                            )
+                        })
+                        .collect_vec();
+                    for index in rejected {
+                        valid.set(index, false);
+                    }
+                }
+            }
+            reachable[node_id.index()] = valid;
+        }
+        reachable
     }
+}
 
-    final_valid_indices
+// Whether the sub-scenario of an action, if any, can be played with a binding.
+// Without a library to look stories up, sub-scenarios are assumed playable.
+pub(crate) fn is_sub_scenario_playable(
+    action: &ScenarioAction,
+    context: &NarrativeWorld,
+    binding: &AliasMap,
+    library: Option<&dyn StoryLibrary>,
+    depth: usize,
+) -> bool {
+    let (Some(sub_scenario), Some(library)) = (&action.sub_scenario, library) else {
+        return true;
+    };
+    if depth >= MAX_SUB_SCENARIO_DEPTH {
+        return false;
+    }
+    let (Some(story), Some(options)) = (
+        library.story(&sub_scenario.story),
+        action.sub_scenario_options(binding),
+    ) else {
+        return false;
+    };
+
+    let playable = story
+        .valid_alias_bindings_in(context, &options, Some(library), depth + 1)
+        .next()
+        .is_some();
+    playable
 }
 
 #[cfg(test)]
 mod unit_tests {
+    use itertools::Itertools;
+
     use crate::entity::EntityId;
+    use crate::lint::Diagnostic;
     use crate::prelude::{Constraint, Entity, NarrativeWorld};
+    use crate::prelude::{SeededRng, Transition};
+    use crate::scenario_graph::{AliasMap, EditError, ScenarioActionId};
 
     use crate::{scenario_action::ScenarioAction, scenario_graph::ScenarioGraph};
 
@@ -447,6 +1462,20 @@ mod unit_tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn connecting_a_missing_node_is_err() {
+        let mut graph = ScenarioGraph::new();
+
+        let a = graph.add(ScenarioAction::new());
+        let b = graph.add(ScenarioAction::new());
+        graph.remove(b);
+
+        assert_eq!(graph.connect(a, b), Err(EditError::MissingNode(b)));
+        assert_eq!(graph.connect_weak(b, a), Err(EditError::MissingNode(b)));
+        assert_eq!(graph.edges().count(), 0);
+        assert_eq!(graph.weak_connections(a.into()), []);
+    }
+
     #[test]
     fn traversing_a_graph_of_depth_2() {
         let graph = player_meets_citizen_with_two_outcomes();
@@ -454,9 +1483,9 @@ mod unit_tests {
         let mut node_index = graph.start().into();
         let mut nodes_traversed = 0;
         loop {
-            node_index = graph.connections(node_index)[0];
+            node_index = graph.all_connections(node_index)[0];
             nodes_traversed += 1;
-            if graph.connections(node_index).is_empty() {
+            if graph.all_connections(node_index).is_empty() {
                 break;
             }
         }
@@ -470,10 +1499,11 @@ mod unit_tests {
         graph.add_alias("person", []);
         let a = graph.add(ScenarioAction::new());
         let b = graph.add(ScenarioAction::new());
-        // TODO: this particular case should be caught when building the graph
         let _ = graph.connect(a, b);
         let _ = graph.connect_weak(b, a);
 
+        assert!(graph.validate().contains(&Diagnostic::NoReachableLeaf));
+
         let context = NarrativeWorld::new().with_entity(Entity::new(0));
         let result = graph.alias_candidates(&context);
 
@@ -547,8 +1577,8 @@ mod unit_tests {
         let permutations = result.unwrap();
         assert_eq!(permutations.len(), 1);
         let aliases = &permutations[0];
-        assert_eq!(aliases["player"], PROTAGONIST);
-        assert_eq!(aliases["citizen"], NEW_CITIZEN);
+        assert_eq!(aliases.get("player"), Some(PROTAGONIST));
+        assert_eq!(aliases.get("citizen"), Some(NEW_CITIZEN));
     }
 
     #[test]
@@ -594,4 +1624,297 @@ mod unit_tests {
             .iter()
             .any(|alias_map| alias_map.get("some character").unwrap() == EXCLUSIONARY));
     }
+
+    #[test]
+    fn converging_branches_are_validated_once() {
+        // 40 chained diamonds make for 2^40 distinct paths to the leaf
+        let mut graph = ScenarioGraph::new();
+        graph.add_alias("a", []);
+        graph.add_alias("b", []);
+        let mut tip = graph.add(ScenarioAction::new());
+        graph.set_start_node(tip);
+        for _ in 0..40 {
+            let left = graph.add(ScenarioAction::new().with_relation_constraints(
+                "a",
+                "b",
+                [Constraint::has_not("enemy")],
+            ));
+            let right = graph.add(ScenarioAction::new());
+            let join = graph.add(ScenarioAction::new());
+            for (from, to) in [(tip, left), (tip, right), (left, join), (right, join)] {
+                graph.connect(from, to).unwrap();
+            }
+            tip = join;
+        }
+        let leaf = graph.add(ScenarioAction::new().with_relation_constraints(
+            "a",
+            "b",
+            [Constraint::has("friend")],
+        ));
+        graph.connect(tip, leaf).unwrap();
+
+        let context = NarrativeWorld::new()
+            .with_entities((0..4).map(Entity::new))
+            .with_relation(0, 1, "friend", "")
+            .with_relation(2, 3, "friend", "")
+            .with_relation(2, 3, "enemy", "");
+
+        let candidates = graph.alias_candidates(&context).unwrap();
+        let mut pairs = candidates
+            .iter()
+            .map(|alias_map| (alias_map.get("a").unwrap(), alias_map.get("b").unwrap()))
+            .collect_vec();
+        pairs.sort();
+        assert_eq!(pairs, [(0, 1), (2, 3)]);
+    }
+
+    #[test]
+    fn a_binding_only_needs_one_valid_branch() {
+        let mut graph = ScenarioGraph::new();
+        graph.add_alias("a", []);
+        graph.add_alias("b", []);
+        let start = graph.add(ScenarioAction::new());
+        let friendly = graph.add(ScenarioAction::new().with_relation_constraints(
+            "a",
+            "b",
+            [Constraint::has("friend")],
+        ));
+        let hostile = graph.add(ScenarioAction::new().with_relation_constraints(
+            "a",
+            "b",
+            [Constraint::has("enemy")],
+        ));
+        graph.set_start_node(start);
+        graph.connect(start, friendly).unwrap();
+        graph.connect(start, hostile).unwrap();
+
+        let context = NarrativeWorld::new()
+            .with_entities((0..3).map(Entity::new))
+            .with_relation(0, 1, "friend", "")
+            .with_relation(1, 2, "enemy", "");
+
+        let candidates = graph.alias_candidates(&context).unwrap();
+        let mut pairs = candidates
+            .iter()
+            .map(|alias_map| (alias_map.get("a").unwrap(), alias_map.get("b").unwrap()))
+            .collect_vec();
+        pairs.sort();
+        assert_eq!(pairs, [(0, 1), (1, 2)]);
+    }
+
+    fn rival_ambushes_wanderer() -> (ScenarioGraph, [ScenarioActionId; 3]) {
+        let mut graph = ScenarioGraph::new();
+        graph.add_alias("wanderer", []);
+        graph.add_alias("rival", []);
+        let ambush = graph.add(ScenarioAction::new().with_description("rival ambushes wanderer"));
+        let flee = graph.add(ScenarioAction::new().with_description("rival flees"));
+        let fight = graph.add(ScenarioAction::new().with_description("rival fights"));
+        graph.set_start_node(ambush);
+        graph
+            .connect_with(ambush, flee, Transition::new().with_weight(0.6))
+            .unwrap();
+        graph
+            .connect_with(
+                ambush,
+                fight,
+                Transition::new()
+                    .with_weight(0.4)
+                    .with_relation_constraints("rival", "wanderer", [Constraint::has("grudge")]),
+            )
+            .unwrap();
+        (graph, [ambush, flee, fight])
+    }
+
+    #[test]
+    fn edges_carry_constraints_and_weights() {
+        let (graph, [ambush, flee, fight]) = rival_ambushes_wanderer();
+        let context = NarrativeWorld::new()
+            .with_entities([Entity::new(0), Entity::new(1)])
+            .with_relation(1, 0, "grudge", "");
+
+        let mut alias_map = AliasMap::default();
+        alias_map.associate("wanderer".into(), 0);
+        alias_map.associate("rival".into(), 1);
+        assert_eq!(
+            graph.next_ranked(ambush, &context, &alias_map),
+            [(flee, 0.6), (fight, 0.4)]
+        );
+
+        let mut rng = SeededRng::new(1);
+        let mut fights = 0;
+        for _ in 0..1000 {
+            if graph.next_random(ambush, &context, &alias_map, &mut rng) == Some(fight) {
+                fights += 1;
+            }
+        }
+        assert!((300..500).contains(&fights), "{}", fights);
+        assert_eq!(
+            graph.next_random(flee, &context, &alias_map, &mut rng),
+            None
+        );
+
+        let mut alias_map = AliasMap::default();
+        alias_map.associate("wanderer".into(), 1);
+        alias_map.associate("rival".into(), 0);
+        assert_eq!(graph.next(ambush, &context, &alias_map), [flee]);
+    }
+
+    #[test]
+    fn edge_constraints_restrict_valid_bindings() {
+        let (mut graph, [ambush, flee, _]) = rival_ambushes_wanderer();
+        let context = NarrativeWorld::new()
+            .with_world_property("daylight", "")
+            .with_entities([Entity::new(0), Entity::new(1)])
+            .with_relation(1, 0, "grudge", "");
+        assert_eq!(graph.alias_candidates(&context).unwrap().len(), 2);
+
+        // rivals only flee at night, leaving fights to those with a grudge
+        let mut edge = graph.graph.find_edge(ambush.into(), flee.into()).unwrap();
+        graph.graph[edge] =
+            Transition::new().with_world_constraint(Constraint::has_not("daylight"));
+        let candidates = graph.alias_candidates(&context).unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].get("rival"), Some(1));
+
+        edge = graph.graph.find_edge(ambush.into(), flee.into()).unwrap();
+        graph.graph[edge].world_constraints.clear();
+        assert_eq!(graph.alias_candidates(&context).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn local_aliases_are_not_bound_up_front() {
+        let mut graph = ScenarioGraph::new();
+        graph.add_alias("thief", [Constraint::has("thief")]);
+        let alley = graph
+            .add(ScenarioAction::new().with_local_alias("witness", [Constraint::has("villager")]));
+        let arrest = graph.add(
+            ScenarioAction::new()
+                .with_local_alias("guard", [Constraint::has("guard")])
+                .with_relation_constraints("guard", "thief", [Constraint::has("grudge")]),
+        );
+        graph.set_start_node(alley);
+        graph.connect(alley, arrest).unwrap();
+
+        let context = NarrativeWorld::new()
+            .with_entity(Entity::new(0).with("thief", ""))
+            .with_entity(Entity::new(1).with("villager", ""))
+            .with_entity(Entity::new(2).with("villager", ""));
+        let bindings = graph
+            .alias_candidates(&context)
+            .unwrap()
+            .into_iter()
+            .map(|binding| {
+                (
+                    binding.get("thief").unwrap(),
+                    binding.get("witness").unwrap(),
+                    binding.get("guard"),
+                )
+            })
+            .collect_vec();
+        // the witness is bound as the start node is entered, the guard only once arrested
+        assert_eq!(bindings, [(0, 1, None), (0, 2, None)]);
+
+        assert!(graph.next(alley, &context, &graph_binding(0, 1)).is_empty());
+        let context = context
+            .with_entity(Entity::new(3).with("guard", ""))
+            .with_relation(3, 0, "grudge", "");
+        assert_eq!(graph.next(alley, &context, &graph_binding(0, 1)), [arrest]);
+    }
+
+    fn graph_binding(thief: EntityId, witness: EntityId) -> AliasMap {
+        let mut alias_map = AliasMap::default();
+        alias_map.associate("thief".into(), thief);
+        alias_map.associate("witness".into(), witness);
+        alias_map
+    }
+
+    #[test]
+    fn graphs_saved_in_the_former_format_still_load() {
+        // saved with a set start node, plain edge weights and weak edges without transitions
+        let graph = ScenarioGraph::load("tests/resources/legacy_graph.ron").unwrap();
+        let [a, b, c] = [0, 1, 2].map(ScenarioActionId);
+        assert_eq!(graph.start(), a);
+        assert_eq!(
+            graph
+                .edges()
+                .map(|(from, to, transition)| (from, to, transition.weight))
+                .collect_vec(),
+            [(a, b, 0.0), (b, c, 2.0)]
+        );
+        assert_eq!(
+            graph.weak_edges().collect_vec(),
+            [(b, a, &Transition::new())]
+        );
+        assert_eq!(
+            graph.get(b).unwrap().directive.source(),
+            "{vendor.name} haggles with {buyer.name}"
+        );
+
+        // and are saved in the current format
+        let mut graph = graph;
+        graph
+            .connect_weak_with(
+                c,
+                a,
+                Transition::new()
+                    .with_relation_constraints("vendor", "buyer", [Constraint::has("friends")])
+                    .with_world_constraint(Constraint::equals("weather", "rain"))
+                    .with_max_traversals(2),
+            )
+            .unwrap();
+        let saved = ron::to_string(&graph).unwrap();
+        let loaded = ScenarioGraph::from_ron(&saved).unwrap();
+        assert_eq!(loaded.start(), a);
+        assert_eq!(loaded.edges().collect_vec(), graph.edges().collect_vec());
+        assert_eq!(
+            loaded.weak_edges().collect_vec(),
+            graph.weak_edges().collect_vec()
+        );
+        assert_eq!(ron::to_string(&loaded).unwrap(), saved);
+
+        graph.remove(a);
+        let loaded = ScenarioGraph::from_ron(&ron::to_string(&graph).unwrap()).unwrap();
+        assert_eq!(loaded.start_id, None);
+        assert!(loaded.get(a).is_none());
+        assert_eq!(loaded.edges().collect_vec(), graph.edges().collect_vec());
+    }
+
+    #[test]
+    fn nodes_can_be_named() {
+        let mut graph = ScenarioGraph::new();
+        let start = graph.add_named("start", ScenarioAction::new()).unwrap();
+        graph.add_named("accept", ScenarioAction::new()).unwrap();
+        let refuse = graph.add(ScenarioAction::new());
+        assert!(graph.add_named("accept", ScenarioAction::new()).is_err());
+        assert_eq!(graph.node_count(), 3);
+
+        graph.set_node_name(refuse, "decline").unwrap();
+        graph.set_node_name(refuse, "refuse").unwrap();
+        assert!(graph.set_node_name(refuse, "start").is_err());
+        assert_eq!(graph.node_id("decline"), None);
+        assert_eq!(graph.node_name(refuse), Some("refuse"));
+
+        graph.connect_named("start", "accept", 2.0).unwrap();
+        graph.connect_named("start", "refuse", 1.0).unwrap();
+        assert!(matches!(
+            graph.connect_named("start", "ignore", 1.0),
+            Err(EditError::UnknownNodeName(name)) if name == "ignore"
+        ));
+        assert!(matches!(
+            graph.connect_named("accept", "start", 1.0),
+            Err(EditError::CycleDetected)
+        ));
+        graph.set_start_node(start);
+
+        let serialized = ron::to_string(&graph).unwrap();
+        let graph: ScenarioGraph = ron::from_str(&serialized).unwrap();
+        let context = NarrativeWorld::new();
+        let names = graph
+            .next_ranked(start, &context, &AliasMap::default())
+            .into_iter()
+            .map(|(node_id, _)| graph.node_name(node_id).unwrap())
+            .collect_vec();
+        assert_eq!(names, ["accept", "refuse"]);
+        assert_eq!(graph.validate(), []);
+    }
 }
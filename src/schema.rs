@@ -1,11 +1,15 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
     entity::{EntityAlias, EntityDefMap, EntityDefName},
+    error::{self, Error},
     instruction::InstructionDefMap,
-    property::PropertyDefMap,
+    property::{PropertyDefMap, PropertyType},
     relationship::RelationshipDefMap,
 };
 
@@ -17,8 +21,9 @@ type EnumDefMap = HashMap<EnumName, EnumDef>;
 
 type FlagSet = HashSet<String>;
 
+// The words and concepts used by the narrative: enums, properties, entity types and so on
 #[derive(Default, Serialize, Deserialize)]
-struct Schema {
+pub struct Schema {
     enums: EnumDefMap,
     properties: PropertyDefMap,
     entity_types: EntityDefMap,
@@ -28,16 +33,54 @@ struct Schema {
     global_entities: HashMap<EntityAlias, EntityDefName>,
 }
 
+impl Schema {
+    pub fn load<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        error::load_ron(path.as_ref())
+    }
+
+    pub fn from_ron(content: &str) -> Result<Self, Error> {
+        error::from_ron(content)
+    }
+
+    pub fn enum_values(&self, name: &str) -> Option<&[String]> {
+        self.enums.get(name).map(Vec::as_slice)
+    }
+
+    pub fn property_type(&self, name: &str) -> Option<&PropertyType> {
+        self.properties.get(name)
+    }
+}
+
+// a name used by a schema without being defined, such as the property `product` listed by the
+// entity type `business`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndefinedName {
+    pub used_by: String,
+    pub name: String,
+}
+
 #[cfg(test)]
 mod tests {
-    use std::fs;
-
     use super::Schema;
+    use crate::{error::Error, property::PropertyType};
 
     #[test]
     fn validate_schema() {
-        let schema_content =
-            fs::read_to_string("tests/resources/schema_wishful_thinking.ron").expect("");
-        let _: Schema = ron::from_str(&schema_content).expect("");
+        let schema = Schema::load("tests/resources/schema_wishful_thinking.ron").expect("");
+        assert_eq!(schema.enum_values("size").map(<[_]>::len), Some(5));
+        assert!(matches!(
+            schema.property_type("age"),
+            Some(PropertyType::Int)
+        ));
+
+        let error = Schema::from_ron("Schema(\n    enums: {\n        \"size\": [tiny],\n").err();
+        assert!(
+            matches!(error, Some(Error::Parse { line: 3, .. })),
+            "{:?}",
+            error
+        );
     }
 }
@@ -0,0 +1,176 @@
+use std::{cell::RefCell, collections::BTreeMap, ops::Range};
+
+use serde::{Deserialize, Serialize};
+
+use crate::rng::SeededRng;
+
+// Remembers which variants of alternatives such as `{~Greetings|Hello|Well met}` were picked,
+// so that a variant isn't picked again before the others have been. Alternatives each have their own bag,
+// keyed by the node of their text and their span, and a bag is refilled in a new random order once
+// empty, without repeating the last pick. Saved along with a game, it keeps the variants from
+// repeating across sessions.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ShuffleBags {
+    rng: SeededRng,
+    bags: BTreeMap<String, Bag>,
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Bag {
+    remaining: Vec<usize>,
+    last: Option<usize>,
+}
+
+impl ShuffleBags {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: SeededRng::new(seed),
+            bags: BTreeMap::new(),
+        }
+    }
+
+    // forgets the picks, keeping the generator where it is
+    pub fn clear(&mut self) {
+        self.bags.clear();
+    }
+
+    // keeps the picks of `Draws::into_drawn`
+    pub(crate) fn commit(&mut self, drawn: ShuffleBags) {
+        self.rng = drawn.rng;
+        self.bags.extend(drawn.bags);
+    }
+}
+
+impl Bag {
+    // the index of the variant to render, out of `len` variants, `len` being at least 1
+    fn draw(&mut self, rng: &mut SeededRng, len: usize) -> usize {
+        if self.remaining.is_empty() || self.remaining.iter().any(|&index| index >= len) {
+            // Fisher-Yates, picks are popped from the end
+            self.remaining = (0..len).collect();
+            for index in (1..len).rev() {
+                self.remaining.swap(index, rng.below(index + 1));
+            }
+            if len > 1 && self.remaining.last() == self.last.as_ref() {
+                self.remaining.swap(0, len - 1);
+            }
+        }
+        let index = self.remaining.pop().unwrap_or_default();
+        self.last = Some(index);
+        index
+    }
+}
+
+// The picks drawn while rendering, kept apart from the bags until committed, such as until a choice
+// is made. Only the bags drawn from are copied, along with the generator.
+pub(crate) struct Draws<'a> {
+    bags: &'a ShuffleBags,
+    drawn: ShuffleBags,
+    // the text drawing, such as `market#3` for a node, or `market#3/retort.0` for a line within it
+    owner: String,
+}
+
+impl<'a> Draws<'a> {
+    pub(crate) fn new(bags: &'a ShuffleBags, owner: &str) -> Self {
+        Self {
+            bags,
+            drawn: ShuffleBags {
+                rng: bags.rng.clone(),
+                bags: BTreeMap::new(),
+            },
+            owner: owner.into(),
+        }
+    }
+
+    // runs `f` drawing for a variant of a line of the text
+    pub(crate) fn in_line<T>(
+        &mut self,
+        line: &str,
+        variant: usize,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let len = self.owner.len();
+        self.owner.push_str(&format!("/{}.{}", line, variant));
+        let result = f(self);
+        self.owner.truncate(len);
+        result
+    }
+
+    // the index of the variant to render for the alternatives at `span` of the text, out of `len`
+    pub(crate) fn pick(&mut self, span: &Range<usize>, len: usize) -> usize {
+        let key = format!("{}@{}..{}", self.owner, span.start, span.end);
+        let bag = self
+            .drawn
+            .bags
+            .entry(key)
+            .or_insert_with_key(|key| self.bags.bags.get(key).cloned().unwrap_or_default());
+        bag.draw(&mut self.drawn.rng, len)
+    }
+
+    pub(crate) fn into_drawn(self) -> ShuffleBags {
+        self.drawn
+    }
+}
+
+thread_local! {
+    static GLOBAL_SHUFFLE_BAGS: RefCell<ShuffleBags> = RefCell::default();
+}
+
+// runs `f` drawing for `owner` from the bags of directives rendered outside of a scenario, shared by
+// the thread, and keeps its picks
+pub(crate) fn draw_from_global_shuffle_bags<T>(owner: &str, f: impl FnOnce(&mut Draws) -> T) -> T {
+    GLOBAL_SHUFFLE_BAGS.with(|shuffle_bags| {
+        let (result, drawn) = {
+            let shuffle_bags = shuffle_bags.borrow();
+            let mut draws = Draws::new(&shuffle_bags, owner);
+            (f(&mut draws), draws.into_drawn())
+        };
+        shuffle_bags.borrow_mut().commit(drawn);
+        result
+    })
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn variants_do_not_repeat_until_the_others_are_used() {
+        let mut bags = ShuffleBags::new(7);
+        let mut previous = None;
+        for _ in 0..10 {
+            let mut draws = Draws::new(&bags, "a#0");
+            let mut picks: Vec<_> = (0..3).map(|_| draws.pick(&(0..8), 3)).collect();
+            bags.commit(draws.into_drawn());
+            assert_ne!(Some(picks[0]), previous);
+            previous = picks.last().copied();
+            picks.sort();
+            assert_eq!(picks, [0, 1, 2]);
+        }
+
+        let replay = |seed| {
+            let bags = ShuffleBags::new(seed);
+            let mut draws = Draws::new(&bags, "a#0");
+            (0..6).map(|_| draws.pick(&(0..8), 3)).collect::<Vec<_>>()
+        };
+        assert_eq!(replay(1), replay(1));
+        assert_eq!(Draws::new(&bags, "a#1").pick(&(0..4), 1), 0);
+    }
+
+    #[test]
+    fn only_committed_draws_are_kept() {
+        let mut bags = ShuffleBags::new(3);
+        let mut draws = Draws::new(&bags, "a#0");
+        draws.pick(&(0..6), 2);
+        let drawn = draws.into_drawn();
+        bags.commit(drawn);
+
+        let mut draws = Draws::new(&bags, "a#1");
+        let picked = draws.pick(&(0..8), 3);
+        let drawn = draws.into_drawn();
+        assert_eq!(drawn.bags.keys().collect::<Vec<_>>(), ["a#1@0..8"]);
+        assert_eq!(Draws::new(&bags, "a#1").pick(&(0..8), 3), picked);
+
+        bags.commit(drawn);
+        assert_ne!(Draws::new(&bags, "a#1").pick(&(0..8), 3), picked);
+    }
+}
@@ -0,0 +1,211 @@
+use std::{collections::BTreeSet, error::Error, fmt, iter::Peekable, str::FromStr};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+pub type Tag = String;
+
+// returned as is by `TagExpr::parse`, and wrapped by `Error::TagExpr` elsewhere
+#[derive(Debug)]
+pub struct TagExprError(String);
+impl TagExprError {
+    fn new<S>(message: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self(message.into())
+    }
+}
+impl fmt::Display for TagExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Tag expression error: {}", self.0)
+    }
+}
+impl Error for TagExprError {}
+
+// A boolean expression over the tags of a story, such as `tavern AND NOT (combat OR "night time")`.
+// NOT binds tighter than AND, which binds tighter than OR. Tags containing spaces are double quoted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagExpr {
+    Tag(Tag),
+    Not(Box<TagExpr>),
+    And(Box<TagExpr>, Box<TagExpr>),
+    Or(Box<TagExpr>, Box<TagExpr>),
+}
+
+impl TagExpr {
+    pub fn tag<T>(tag: T) -> Self
+    where
+        T: Into<Tag>,
+    {
+        Self::Tag(tag.into())
+    }
+
+    pub fn parse(expression: &str) -> Result<Self, TagExprError> {
+        let mut tokens = tokenize(expression)?.into_iter().peekable();
+        let expr = parse_or(&mut tokens)?;
+        match tokens.next() {
+            None => Ok(expr),
+            Some(token) => Err(TagExprError::new(format!(
+                r#"unexpected "{}" in "{}""#,
+                token, expression
+            ))),
+        }
+    }
+
+    pub fn is_satisfied_by(&self, tags: &BTreeSet<Tag>) -> bool {
+        match self {
+            TagExpr::Tag(tag) => tags.contains(tag),
+            TagExpr::Not(expr) => !expr.is_satisfied_by(tags),
+            TagExpr::And(lhs, rhs) => lhs.is_satisfied_by(tags) && rhs.is_satisfied_by(tags),
+            TagExpr::Or(lhs, rhs) => lhs.is_satisfied_by(tags) || rhs.is_satisfied_by(tags),
+        }
+    }
+}
+
+impl FromStr for TagExpr {
+    type Err = TagExprError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        Self::parse(expression)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Tag(Tag),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::And => write!(f, "AND"),
+            Token::Or => write!(f, "OR"),
+            Token::Not => write!(f, "NOT"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::Tag(tag) => write!(f, "{}", tag),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, TagExprError> {
+    static TOKEN_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r#"\s*(?:(\()|(\))|"([^"]*)"|([^\s()"]+))"#).unwrap());
+
+    let mut tokens = vec![];
+    let mut end = 0;
+    for cap in TOKEN_RE.captures_iter(expression) {
+        let whole = cap.get(0).unwrap();
+        if whole.start() != end {
+            break;
+        }
+        end = whole.end();
+
+        tokens.push(if cap.get(1).is_some() {
+            Token::Open
+        } else if cap.get(2).is_some() {
+            Token::Close
+        } else if let Some(quoted) = cap.get(3) {
+            Token::Tag(quoted.as_str().into())
+        } else {
+            match &cap[4] {
+                "AND" => Token::And,
+                "OR" => Token::Or,
+                "NOT" => Token::Not,
+                tag => Token::Tag(tag.into()),
+            }
+        });
+    }
+
+    if !expression[end..].trim().is_empty() {
+        return Err(TagExprError::new(format!(
+            r#"unterminated quote in "{}""#,
+            expression
+        )));
+    }
+    Ok(tokens)
+}
+
+type Tokens = Peekable<std::vec::IntoIter<Token>>;
+
+fn parse_or(tokens: &mut Tokens) -> Result<TagExpr, TagExprError> {
+    let mut expr = parse_and(tokens)?;
+    while tokens.next_if_eq(&Token::Or).is_some() {
+        expr = TagExpr::Or(Box::new(expr), Box::new(parse_and(tokens)?));
+    }
+    Ok(expr)
+}
+
+fn parse_and(tokens: &mut Tokens) -> Result<TagExpr, TagExprError> {
+    let mut expr = parse_not(tokens)?;
+    while tokens.next_if_eq(&Token::And).is_some() {
+        expr = TagExpr::And(Box::new(expr), Box::new(parse_not(tokens)?));
+    }
+    Ok(expr)
+}
+
+fn parse_not(tokens: &mut Tokens) -> Result<TagExpr, TagExprError> {
+    match tokens.next() {
+        Some(Token::Not) => Ok(TagExpr::Not(Box::new(parse_not(tokens)?))),
+        Some(Token::Open) => {
+            let expr = parse_or(tokens)?;
+            tokens
+                .next_if_eq(&Token::Close)
+                .map(|_| expr)
+                .ok_or(TagExprError::new("missing closing parenthesis"))
+        }
+        Some(Token::Tag(tag)) => Ok(TagExpr::Tag(tag)),
+        Some(token) => Err(TagExprError::new(format!(r#"unexpected "{}""#, token))),
+        None => Err(TagExprError::new("unexpected end of expression")),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use std::collections::BTreeSet;
+
+    use super::TagExpr;
+
+    fn tags<const N: usize>(tags: [&str; N]) -> BTreeSet<String> {
+        tags.into_iter().map(String::from).collect()
+    }
+
+    #[test]
+    fn operators_follow_precedence() {
+        let expr = TagExpr::parse("tavern AND NOT combat OR night").unwrap();
+        assert_eq!(
+            expr,
+            TagExpr::Or(
+                Box::new(TagExpr::And(
+                    Box::new(TagExpr::tag("tavern")),
+                    Box::new(TagExpr::Not(Box::new(TagExpr::tag("combat")))),
+                )),
+                Box::new(TagExpr::tag("night")),
+            )
+        );
+
+        assert!(expr.is_satisfied_by(&tags(["tavern"])));
+        assert!(!expr.is_satisfied_by(&tags(["tavern", "combat"])));
+        assert!(expr.is_satisfied_by(&tags(["tavern", "combat", "night"])));
+    }
+
+    #[test]
+    fn parentheses_and_quoted_tags() {
+        let expr: TagExpr = r#"NOT (combat OR "city gate")"#.parse().unwrap();
+        assert!(expr.is_satisfied_by(&tags(["tavern"])));
+        assert!(!expr.is_satisfied_by(&tags(["city gate"])));
+    }
+
+    #[test]
+    fn malformed_expressions_are_err() {
+        for expression in ["", "tavern AND", "(tavern", "tavern)", r#""tavern"#, "NOT"] {
+            assert!(TagExpr::parse(expression).is_err(), "{}", expression);
+        }
+    }
+}
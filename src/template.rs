@@ -0,0 +1,1026 @@
+use std::{cell::RefCell, collections::BTreeMap, error, fmt, ops::Range};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::EntityId,
+    error::Error,
+    filter::{is_choice, Filters, LIST},
+    grammar::{Article, PronounCase},
+    line::{LineName, LineVariant},
+    locale::{line_text, NodeTexts},
+    narrative_world::NarrativeWorld,
+    property::{Property, PropertyName},
+    scenario_action::Alias,
+    scenario_graph::AliasMap,
+    shuffle_bag::Draws,
+    Int,
+};
+
+// A malformed template, `span` being the byte range of the faulty part of its source
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateError {
+    pub message: String,
+    pub span: Range<usize>,
+}
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}..{}",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
+impl error::Error for TemplateError {}
+
+impl TemplateError {
+    fn new<S>(message: S, span: Range<usize>) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+// Text with placeholders such as `{<a>vendor}`, `{vendor.name|upper}`, `{location}` or `{guards.name|list}`,
+// and references to named lines such as `[bring message]`.
+// Placeholders naming an alias agree with the entities bound to it, through the grammar of the filters:
+// `{<a>vendor|subject}` is a pronoun, and `{<a>vendor|he/she/they}` or `{<a>guards|is/are}` picks
+// a variant. A bare name such as `gold` is a world property, even when an alias shares its name.
+// Alternatives such as `{~Greetings|Hello|Well met}` render one of their variants, not repeating
+// a variant until the others have been rendered.
+// A backslash escapes a delimiter or another backslash, as in `\{color}`, and text between
+// `{%raw}` and `{%endraw}` is kept as is. Delimiters can be changed with a `TemplateSyntax`.
+// It is parsed once, when built or loaded, and keeps its source around to be saved as is,
+// along with its syntax unless it's the default one.
+// A template built or loaded from a malformed string fails to render, and is reported by
+// `ScenarioGraph::validate`.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(into = "SavedTemplate", from = "SavedTemplate")]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+    error: Option<TemplateError>,
+    // `None` for the default syntax
+    syntax: Option<TemplateSyntax>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Segment {
+    Text(String),
+    Placeholder {
+        path: Path,
+        filters: Vec<String>,
+        span: Range<usize>,
+    },
+    // `{~Greetings|Hello|Well met}`, one of the variants picked from a shuffle bag
+    Alternatives {
+        variants: Vec<String>,
+        span: Range<usize>,
+    },
+    // rendered as is when the action has no line of that name
+    Line {
+        name: LineName,
+        span: Range<usize>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Path {
+    // `<a>alias`, the id of the entity bound to the alias
+    Entity(Alias),
+    // `alias.property`
+    Property(Alias, PropertyName),
+    // `property`, a world property
+    World(PropertyName),
+}
+
+impl Path {
+    pub(crate) fn alias(&self) -> Option<&str> {
+        match self {
+            Path::Entity(alias) | Path::Property(alias, _) => Some(alias),
+            Path::World(_) => None,
+        }
+    }
+}
+
+impl Template {
+    // parses with the syntax in scope, the default syntax unless within `TemplateSyntax::scope`
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        SYNTAX.with(|syntax| Self::parse_with(source, &syntax.borrow()))
+    }
+
+    pub fn parse_with(source: &str, syntax: &TemplateSyntax) -> Result<Self, TemplateError> {
+        let mut template = Self::build(source, syntax);
+        match template.error.take() {
+            Some(error) => Err(error),
+            None => Ok(template),
+        }
+    }
+
+    // keeps malformed sources, along with why they couldn't be parsed
+    fn build(source: &str, syntax: &TemplateSyntax) -> Self {
+        let (segments, error) = match parse(source, syntax) {
+            Ok(segments) => (segments, None),
+            Err(error) => (vec![], Some(error)),
+        };
+        Self {
+            source: source.into(),
+            segments,
+            error,
+            syntax: (*syntax != TemplateSyntax::default()).then(|| syntax.clone()),
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.source.is_empty()
+    }
+
+    // why the source couldn't be parsed, if it couldn't
+    pub fn error(&self) -> Option<&TemplateError> {
+        self.error.as_ref()
+    }
+
+    // aliases named by the placeholders
+    pub(crate) fn aliases(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Placeholder { path, .. } => path.alias(),
+            _ => None,
+        })
+    }
+
+    // Names of the filters used by the placeholders, along with whether they follow an alias such
+    // as in `{<a>npc|subject}`, as pronouns and choices apply to nothing else
+    pub(crate) fn filters(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.segments.iter().flat_map(|segment| {
+            let (path, filters) = match segment {
+                Segment::Placeholder { path, filters, .. } => (Some(path), filters.as_slice()),
+                _ => (None, &[][..]),
+            };
+            let to_alias = matches!(path, Some(Path::Entity(_)));
+            filters
+                .iter()
+                .enumerate()
+                .map(move |(index, filter)| (filter.as_str(), to_alias && index == 0))
+        })
+    }
+
+    // Renders the template for the entities bound to the aliases, picking the variant of each line
+    // referenced, in the first of the translations which has it. Lines can't reference other lines.
+    // Alternatives are drawn for the owner of the draws, such as a node, at their span.
+    pub(crate) fn render(
+        &self,
+        alias_map: &AliasMap,
+        context: &NarrativeWorld,
+        lines: &BTreeMap<LineName, Vec<LineVariant>>,
+        filters: &Filters,
+        translations: &[&NodeTexts],
+        draws: &mut Draws,
+    ) -> Result<Vec<TextSegment>, Error> {
+        if let Some(error) = &self.error {
+            return Err(error.clone().into());
+        }
+        let mut rendered = vec![];
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => push_text(&mut rendered, text),
+                Segment::Placeholder {
+                    path,
+                    filters: filter_names,
+                    span,
+                } => {
+                    for segment in
+                        placeholder_segments(path, filter_names, alias_map, context, filters)
+                            .map_err(|error| error.at(None, None, Some(span.clone())))?
+                    {
+                        match segment {
+                            TextSegment::Text(text) => push_text(&mut rendered, &text),
+                            segment => rendered.push(segment),
+                        }
+                    }
+                }
+                Segment::Alternatives { variants, span } => {
+                    let index = draws.pick(span, variants.len());
+                    push_text(&mut rendered, &variants[index]);
+                }
+                Segment::Line { name, span } => match lines.get(name) {
+                    None => push_text(&mut rendered, &self.source[span.clone()]),
+                    Some(variants) => {
+                        let (index, variant) = variants
+                            .iter()
+                            .find_position(|variant| variant.applies(context, alias_map))
+                            .ok_or_else(|| {
+                                Error::alias(format!(r#"No variant of line "{}" applies"#, name))
+                                    .at(None, None, Some(span.clone()))
+                            })?;
+                        let text = line_text(translations, name, index).unwrap_or(&variant.text);
+                        for segment in draws
+                            .in_line(name, index, |draws| {
+                                text.render(
+                                    alias_map,
+                                    context,
+                                    &BTreeMap::new(),
+                                    filters,
+                                    &[],
+                                    draws,
+                                )
+                            })
+                            .map_err(|error| error.in_line(name, index))?
+                        {
+                            match segment {
+                                TextSegment::Text(text) => push_text(&mut rendered, &text),
+                                segment => rendered.push(segment),
+                            }
+                        }
+                    }
+                },
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+// A part of a rendered template, for games building rich text such as clickable names
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextSegment {
+    // literal text, as well as words picked by the grammar and alternatives
+    Text(String),
+    // `{<a>vendor}`, or one of the members of `{<a>guards|list}`
+    Entity {
+        alias: Alias,
+        id: EntityId,
+    },
+    // `{vendor.name}`, or `{gold}` whose alias is `None`, once filtered
+    Property {
+        alias: Option<Alias>,
+        name: PropertyName,
+        value: Property,
+    },
+}
+
+// renders the segment as text, entities as their id
+impl fmt::Display for TextSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextSegment::Text(text) => write!(f, "{}", text),
+            TextSegment::Entity { id, .. } => write!(f, "{}", id),
+            TextSegment::Property { value, .. } => write!(f, "{}", value),
+        }
+    }
+}
+
+// the rendered segments as text
+pub(crate) fn concat(segments: &[TextSegment]) -> String {
+    segments.iter().map(TextSegment::to_string).collect()
+}
+
+// merges adjacent text
+fn push_text(segments: &mut Vec<TextSegment>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    match segments.last_mut() {
+        Some(TextSegment::Text(last)) => last.push_str(text),
+        _ => segments.push(TextSegment::Text(text.into())),
+    }
+}
+
+// keeps malformed sources, for builders which can't fail
+impl From<&str> for Template {
+    fn from(source: &str) -> Self {
+        SYNTAX.with(|syntax| Self::build(source, &syntax.borrow()))
+    }
+}
+
+impl From<String> for Template {
+    fn from(source: String) -> Self {
+        Self::from(source.as_str())
+    }
+}
+
+// A template as saved: its source, along with its syntax unless it's the default one.
+// A source alone is parsed with the syntax in scope, as templates are built.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SavedTemplate {
+    Source(String),
+    WithSyntax {
+        source: String,
+        syntax: TemplateSyntax,
+    },
+}
+
+impl From<Template> for SavedTemplate {
+    fn from(template: Template) -> Self {
+        match template.syntax {
+            None => SavedTemplate::Source(template.source),
+            Some(syntax) => SavedTemplate::WithSyntax {
+                source: template.source,
+                syntax,
+            },
+        }
+    }
+}
+
+impl From<SavedTemplate> for Template {
+    fn from(saved: SavedTemplate) -> Self {
+        match saved {
+            SavedTemplate::Source(source) => Self::from(source),
+            SavedTemplate::WithSyntax { source, syntax } => Self::build(&source, &syntax),
+        }
+    }
+}
+
+impl From<Template> for String {
+    fn from(template: Template) -> Self {
+        template.source
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+// The delimiters of placeholders and line references, such as `<<` and `>>` for games whose own
+// markup uses braces. Raw blocks use the placeholder delimiters, as in `<<%raw>>` and `<<%endraw>>`.
+// Delimiters are checked whether set or loaded: they can't be empty, start with the `\` escape,
+// or be used twice.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Delimiters")]
+pub struct TemplateSyntax {
+    placeholder: (String, String),
+    line: (String, String),
+}
+
+// a syntax as loaded, before its delimiters are checked
+#[derive(Deserialize)]
+struct Delimiters {
+    placeholder: (String, String),
+    line: (String, String),
+}
+
+impl TryFrom<Delimiters> for TemplateSyntax {
+    type Error = Error;
+
+    fn try_from(delimiters: Delimiters) -> Result<Self, Error> {
+        Self {
+            placeholder: delimiters.placeholder,
+            line: delimiters.line,
+        }
+        .checked()
+    }
+}
+
+impl Default for TemplateSyntax {
+    fn default() -> Self {
+        Self {
+            placeholder: ("{".into(), "}".into()),
+            line: ("[".into(), "]".into()),
+        }
+    }
+}
+
+impl TemplateSyntax {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // builder methods, reporting an `Error::Template` for delimiters which are empty, start with
+    // the `\` escape or clash with the other delimiters
+
+    pub fn with_placeholder<S>(mut self, open: S, close: S) -> Result<Self, Error>
+    where
+        S: Into<String>,
+    {
+        self.placeholder = (open.into(), close.into());
+        self.checked()
+    }
+
+    pub fn with_line<S>(mut self, open: S, close: S) -> Result<Self, Error>
+    where
+        S: Into<String>,
+    {
+        self.line = (open.into(), close.into());
+        self.checked()
+    }
+
+    // the parser can't make progress on an empty delimiter, nor tell apart delimiters which clash
+    fn checked(self) -> Result<Self, Error> {
+        let delimiters = [
+            &self.placeholder.0,
+            &self.placeholder.1,
+            &self.line.0,
+            &self.line.1,
+        ];
+        let message = if delimiters.iter().any(|delimiter| delimiter.is_empty()) {
+            Some("Delimiters can't be empty".to_string())
+        } else if let Some(delimiter) = delimiters
+            .iter()
+            .find(|delimiter| delimiter.starts_with('\\'))
+        {
+            Some(format!(
+                r#"Delimiter "{}" can't start with the "\" escape"#,
+                delimiter
+            ))
+        } else {
+            delimiters
+                .iter()
+                .duplicates()
+                .next()
+                .map(|delimiter| format!(r#"Delimiter "{}" is used twice"#, delimiter))
+        };
+        match message {
+            Some(message) => Err(Error::Template {
+                location: Box::default(),
+                error: TemplateError::new(message, 0..0),
+            }),
+            None => Ok(self),
+        }
+    }
+
+    // Templates built or loaded within `f` use this syntax, such as
+    // `syntax.scope(|| ron::from_str::<ScenarioGraph>(&content))`
+    pub fn scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        // restores the previous syntax, even if `f` panics
+        struct Restore(Option<TemplateSyntax>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                if let Some(syntax) = self.0.take() {
+                    SYNTAX.with(|current| *current.borrow_mut() = syntax);
+                }
+            }
+        }
+
+        let _restore = Restore(Some(SYNTAX.with(|current| current.replace(self.clone()))));
+        f()
+    }
+}
+
+thread_local! {
+    static SYNTAX: RefCell<TemplateSyntax> = RefCell::default();
+}
+
+const RAW: &str = "%raw";
+const END_RAW: &str = "%endraw";
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_identifier(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(is_word)
+}
+
+fn parse(source: &str, syntax: &TemplateSyntax) -> Result<Vec<Segment>, TemplateError> {
+    let (open, close) = (syntax.placeholder.0.as_str(), syntax.placeholder.1.as_str());
+    let (line_open, line_close) = (syntax.line.0.as_str(), syntax.line.1.as_str());
+    let raw_open = format!("{}{}{}", open, RAW, close);
+    let raw_close = format!("{}{}{}", open, END_RAW, close);
+
+    let mut segments = vec![];
+    let mut text = String::new();
+    let push = |segments: &mut Vec<Segment>, text: &mut String, segment| {
+        if !text.is_empty() {
+            segments.push(Segment::Text(std::mem::take(text)));
+        }
+        segments.push(segment);
+    };
+
+    let mut start = 0;
+    while let Some(c) = source[start..].chars().next() {
+        let rest = &source[start..];
+        if let Some(escaped) = rest.strip_prefix('\\') {
+            // a backslash escapes a delimiter or itself, and is text otherwise
+            let token = ["\\", open, close, line_open, line_close]
+                .into_iter()
+                .find(|&token| escaped.starts_with(token));
+            match token {
+                Some(token) => {
+                    text.push_str(token);
+                    start += 1 + token.len();
+                }
+                None => {
+                    text.push(c);
+                    start += 1;
+                }
+            }
+        } else if rest.starts_with(&raw_open) {
+            let content = start + raw_open.len();
+            let Some(len) = source[content..].find(&raw_close) else {
+                return Err(TemplateError::new(
+                    "Unclosed raw block",
+                    start..source.len(),
+                ));
+            };
+            text.push_str(&source[content..content + len]);
+            start = content + len + raw_close.len();
+        } else if rest.starts_with(open) {
+            let content = start + open.len();
+            let Some(len) = source[content..].find(close) else {
+                return Err(TemplateError::new(
+                    "Unclosed placeholder",
+                    start..source.len(),
+                ));
+            };
+            let end = content + len + close.len();
+            let span = start..end;
+            let content = &source[content..content + len];
+            let segment = match content.strip_prefix('~') {
+                Some(variants) => Segment::Alternatives {
+                    variants: variants.split('|').map(String::from).collect(),
+                    span,
+                },
+                None => {
+                    let (path, filters) = parse_placeholder(content, span.clone())?;
+                    Segment::Placeholder {
+                        path,
+                        filters,
+                        span,
+                    }
+                }
+            };
+            push(&mut segments, &mut text, segment);
+            start = end;
+        } else if rest.starts_with(line_open) {
+            // a line name can't span lines nor hold delimiters, the bracket is text otherwise
+            let name_start = start + line_open.len();
+            let name = &source[name_start..];
+            let name_len = [line_open, line_close, open, close, "\n"]
+                .into_iter()
+                .filter_map(|token| name.find(token))
+                .min()
+                .filter(|&len| len > 0 && name[len..].starts_with(line_close));
+            match name_len {
+                Some(len) => {
+                    let end = name_start + len + line_close.len();
+                    let segment = Segment::Line {
+                        name: name[..len].into(),
+                        span: start..end,
+                    };
+                    push(&mut segments, &mut text, segment);
+                    start = end;
+                }
+                None => {
+                    text.push_str(line_open);
+                    start = name_start;
+                }
+            }
+        } else {
+            text.push(c);
+            start += c.len_utf8();
+        }
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
+
+// a path followed by filters, such as `villagers.name|list`
+fn parse_placeholder(
+    content: &str,
+    span: Range<usize>,
+) -> Result<(Path, Vec<String>), TemplateError> {
+    let mut parts = content.split('|');
+    let path = parts.next().unwrap_or_default();
+    let path = if let Some(alias) = path.strip_prefix("<a>") {
+        is_identifier(alias).then(|| Path::Entity(alias.into()))
+    } else if let Some((alias, property_name)) = path.split_once('.') {
+        (is_identifier(alias) && is_identifier(property_name))
+            .then(|| Path::Property(alias.into(), property_name.into()))
+    } else {
+        is_identifier(path).then(|| Path::World(path.into()))
+    }
+    .ok_or_else(|| {
+        TemplateError::new(
+            format!(r#"Malformed property path "{}""#, path),
+            span.clone(),
+        )
+    })?;
+
+    let filters = parts
+        .map(|filter| {
+            (is_identifier(filter) || is_choice(filter))
+                .then(|| filter.to_string())
+                .ok_or_else(|| {
+                    TemplateError::new(format!(r#"Malformed filter "{}""#, filter), span.clone())
+                })
+        })
+        .try_collect()?;
+    Ok((path, filters))
+}
+
+// the value of a placeholder, with one property per member for group aliases until they're joined
+enum Value {
+    One(Property),
+    Many(Vec<Property>),
+    // the entities bound to an alias, rendered as their ids unless the grammar picks a word for them
+    Entities { ids: Vec<EntityId>, group: bool },
+    // the members of a group alias joined by `list`
+    Joined(Vec<EntityId>),
+    // picked by the grammar, such as a pronoun
+    Word(String),
+}
+
+impl Value {
+    // entities as their ids, for filters
+    fn into_properties(self) -> Self {
+        let id = |entity_id: EntityId| Property::Int(entity_id as Int);
+        match self {
+            Value::Entities { ids, group: true } => Value::Many(ids.into_iter().map(id).collect()),
+            Value::Entities { ids, group: false } => Value::One(id(ids[0])),
+            Value::Joined(ids) => Value::One(Property::String(list(
+                &ids.iter().map(EntityId::to_string).collect_vec(),
+            ))),
+            value => value,
+        }
+    }
+}
+
+fn placeholder_segments(
+    path: &Path,
+    filters: &[String],
+    alias_map: &AliasMap,
+    context: &NarrativeWorld,
+    registry: &Filters,
+) -> Result<Vec<TextSegment>, Error> {
+    let alias = path.alias().unwrap_or_default();
+
+    // unbound optional aliases render as nothing
+    if alias_map.is_unbound(alias) {
+        return Ok(vec![]);
+    }
+
+    let grammar = registry.grammar();
+    let mut value = path_value(path, alias_map, context)?;
+    for filter_name in filters {
+        let case = PronounCase::from_name(filter_name);
+        value = match (value, filter_name.as_str()) {
+            (Value::Entities { ids, .. }, _) if case.is_some() || is_choice(filter_name) => {
+                let entities: Vec<_> = ids
+                    .iter()
+                    .map(|&entity_id| {
+                        context.entity(entity_id).ok_or_else(|| {
+                            Error::alias(format!(
+                                r#"Entity "{}" bound to "{}" is missing"#,
+                                entity_id, alias
+                            ))
+                        })
+                    })
+                    .try_collect()?;
+                Value::Word(match case {
+                    Some(case) => grammar.pronoun(&entities, case),
+                    None => grammar
+                        .agree(&entities, &filter_name.split('/').collect_vec())
+                        .map_err(|message| {
+                            Error::filter(
+                                filter_name,
+                                format!(r#"failed on "{}": {}"#, alias, message),
+                            )
+                        })?
+                        .to_string(),
+                })
+            }
+            (_, _) if is_choice(filter_name) => {
+                return Err(Error::filter(
+                    filter_name,
+                    format!(
+                        r#"must follow an alias, such as in "{{<a>npc|{}}}""#,
+                        filter_name
+                    ),
+                ))
+            }
+            // a single entity is a list of one
+            (Value::Entities { ids, .. }, LIST) => Value::Joined(ids),
+            (value @ (Value::One(_) | Value::Word(_) | Value::Joined(_)), LIST) => value,
+            (Value::Many(values), LIST) => Value::One(Property::String(list(
+                &values.iter().map(Property::to_string).collect_vec(),
+            ))),
+            (Value::Word(word), _) => Value::Word(
+                apply_filter(filter_name, Property::String(word), registry)?.to_string(),
+            ),
+            (value, _) => match value.into_properties() {
+                Value::Many(values) => Value::Many(
+                    values
+                        .into_iter()
+                        .map(|value| apply_filter(filter_name, value, registry))
+                        .try_collect()?,
+                ),
+                Value::One(value) => Value::One(apply_filter(filter_name, value, registry)?),
+                _ => unreachable!("entities are turned into properties"),
+            },
+        };
+    }
+
+    match (value, path) {
+        (Value::Word(word), _) => Ok(vec![TextSegment::Text(word)]),
+        (Value::Entities { ids, group: false }, _) => Ok(vec![TextSegment::Entity {
+            alias: alias.into(),
+            id: ids[0],
+        }]),
+        (Value::Joined(ids), _) => {
+            let last = ids.len().saturating_sub(1);
+            let mut segments = vec![];
+            for (index, id) in ids.into_iter().enumerate() {
+                match index {
+                    0 => {}
+                    _ if index == last => segments.push(TextSegment::Text(" and ".into())),
+                    _ => segments.push(TextSegment::Text(", ".into())),
+                }
+                segments.push(TextSegment::Entity {
+                    alias: alias.into(),
+                    id,
+                });
+            }
+            Ok(segments)
+        }
+        (Value::One(value), Path::Property(alias, name)) => Ok(vec![TextSegment::Property {
+            alias: Some(alias.clone()),
+            name: name.clone(),
+            value,
+        }]),
+        (Value::One(value), Path::World(name)) => Ok(vec![TextSegment::Property {
+            alias: None,
+            name: name.clone(),
+            value,
+        }]),
+        // the id of an entity, once filtered
+        (Value::One(value), Path::Entity(_)) => Ok(vec![TextSegment::Text(value.to_string())]),
+        (Value::Many(_) | Value::Entities { .. }, _) => Err(Error::alias(format!(
+            r#"Group alias "{}" must be joined, such as with "|list""#,
+            alias
+        ))),
+    }
+}
+
+// an article of the grammar, or a filter of the registry
+fn apply_filter(filter_name: &str, value: Property, registry: &Filters) -> Result<Property, Error> {
+    if let Some(article) = Article::from_name(filter_name) {
+        return Ok(Property::String(
+            registry.grammar().article(article, &value.to_string()),
+        ));
+    }
+    let filter = registry
+        .get(filter_name)
+        .ok_or_else(|| Error::filter(filter_name, "is unknown"))?;
+    filter(&value).map_err(|message| {
+        Error::filter(
+            filter_name,
+            format!(r#"failed on "{}": {}"#, value, message),
+        )
+    })
+}
+
+fn path_value(path: &Path, alias_map: &AliasMap, context: &NarrativeWorld) -> Result<Value, Error> {
+    let missing_property = |entity_id: EntityId, alias: &str, property_name: &str| {
+        Error::alias(format!(
+            r#"Entity "{}" bound to "{}" is missing the property "{}""#,
+            entity_id, alias, property_name
+        ))
+    };
+    let entity_property = |entity_id: EntityId, alias: &str, property_name: &str| {
+        context
+            .entity(entity_id)
+            .ok_or_else(|| {
+                Error::alias(format!(
+                    r#"Entity "{}" bound to "{}" is missing"#,
+                    entity_id, alias
+                ))
+            })?
+            .get(property_name)
+            .cloned()
+            .ok_or_else(|| missing_property(entity_id, alias, property_name))
+    };
+
+    match path {
+        Path::Entity(alias) | Path::Property(alias, _) if alias_map.group(alias).is_some() => {
+            // `count` is the number of members, other properties are listed for every member
+            let members = alias_map.group(alias).unwrap();
+            match path {
+                Path::Property(_, property_name) if property_name == "count" => {
+                    Ok(Value::One(Property::Int(members.len() as Int)))
+                }
+                Path::Property(_, property_name) => members
+                    .iter()
+                    .map(|&entity_id| entity_property(entity_id, alias, property_name))
+                    .try_collect()
+                    .map(Value::Many),
+                _ => Ok(Value::Entities {
+                    ids: members.to_vec(),
+                    group: true,
+                }),
+            }
+        }
+        Path::Entity(alias) => alias_map
+            .get(alias)
+            .ok_or_else(|| Error::alias(format!(r#"missing alias "{}""#, alias)))
+            .map(|entity_id| Value::Entities {
+                ids: vec![entity_id],
+                group: false,
+            }),
+        Path::Property(alias, property_name) => {
+            let entity_id = alias_map
+                .get(alias)
+                .ok_or_else(|| Error::alias(format!(r#"missing alias "{}""#, alias)))?;
+            entity_property(entity_id, alias, property_name).map(Value::One)
+        }
+        Path::World(property_name) => context
+            .world_property(property_name)
+            .cloned()
+            .map(Value::One)
+            .ok_or_else(|| Error::alias(format!(r#"Missing world property "{}""#, property_name))),
+    }
+}
+
+// "a", "a and b", "a, b and c"
+fn list(texts: &[String]) -> String {
+    match texts {
+        [] => String::new(),
+        [text] => text.clone(),
+        [init @ .., last] => format!("{} and {}", init.join(", "), last),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn templates_are_parsed_into_segments() {
+        let template =
+            Template::parse("speak {<a>vendor} \"[greeting], {player.name|list}\" in {location} [")
+                .unwrap();
+        assert_eq!(
+            template.segments,
+            [
+                Segment::Text("speak ".into()),
+                Segment::Placeholder {
+                    path: Path::Entity("vendor".into()),
+                    filters: vec![],
+                    span: 6..17,
+                },
+                Segment::Text(" \"".into()),
+                Segment::Line {
+                    name: "greeting".into(),
+                    span: 19..29,
+                },
+                Segment::Text(", ".into()),
+                Segment::Placeholder {
+                    path: Path::Property("player".into(), "name".into()),
+                    filters: vec!["list".into()],
+                    span: 31..49,
+                },
+                Segment::Text("\" in ".into()),
+                Segment::Placeholder {
+                    path: Path::World("location".into()),
+                    filters: vec![],
+                    span: 54..64,
+                },
+                Segment::Text(" [".into()),
+            ]
+        );
+        assert_eq!(template.aliases().collect_vec(), ["vendor", "player"]);
+
+        assert_eq!(
+            Template::parse("{~Hello|Well met|}").unwrap().segments,
+            [Segment::Alternatives {
+                variants: vec!["Hello".into(), "Well met".into(), "".into()],
+                span: 0..18,
+            }]
+        );
+    }
+
+    #[test]
+    fn malformed_templates_are_reported_with_their_span() {
+        assert_eq!(
+            Template::parse("Hi {player.name").unwrap_err(),
+            TemplateError::new("Unclosed placeholder", 3..15)
+        );
+        assert_eq!(
+            Template::parse("Hi {player name}!").unwrap_err(),
+            TemplateError::new(r#"Malformed property path "player name""#, 3..16)
+        );
+        assert_eq!(
+            Template::parse("{a.b.c}").unwrap_err().message,
+            r#"Malformed property path "a.b.c""#
+        );
+        assert_eq!(
+            Template::parse("{guards.name|}").unwrap_err().message,
+            r#"Malformed filter """#
+        );
+
+        let template = Template::from("Hi {player name}!");
+        assert_eq!(template.source(), "Hi {player name}!");
+        assert!(template.error().is_some());
+        // loaded as built, and saved as is
+        let loaded = ron::from_str::<Template>(r#""Hi {player name}!""#).unwrap();
+        assert_eq!(loaded, template);
+        assert_eq!(ron::to_string(&loaded).unwrap(), r#""Hi {player name}!""#);
+        assert_eq!(
+            ron::from_str::<Template>(r#""Hi {player.name}!""#).unwrap(),
+            Template::parse("Hi {player.name}!").unwrap()
+        );
+    }
+
+    #[test]
+    fn delimiters_can_be_escaped_or_changed() {
+        let text = |template: Template| match template.segments.as_slice() {
+            [Segment::Text(text)] => text.clone(),
+            segments => panic!("not text: {:?}", segments),
+        };
+        assert_eq!(
+            text(Template::parse(r"\{color} \[b\] C:\path \\").unwrap()),
+            r"{color} [b] C:\path \"
+        );
+        assert_eq!(
+            text(Template::parse(r#"{%raw}{"hp": [1, 2]}{%endraw}"#).unwrap()),
+            r#"{"hp": [1, 2]}"#
+        );
+        assert_eq!(
+            Template::parse("{%raw}{").unwrap_err(),
+            TemplateError::new("Unclosed raw block", 0..7)
+        );
+
+        let syntax = TemplateSyntax::new()
+            .with_placeholder("<<", ">>")
+            .and_then(|syntax| syntax.with_line("[[", "]]"))
+            .unwrap();
+        let template = Template::parse_with(
+            "{red}<<vendor.name>>{/red} [b] [[ask]] <<%raw>><<x>><<%endraw>>",
+            &syntax,
+        )
+        .unwrap();
+        assert_eq!(
+            template.segments,
+            [
+                Segment::Text("{red}".into()),
+                Segment::Placeholder {
+                    path: Path::Property("vendor".into(), "name".into()),
+                    filters: vec![],
+                    span: 5..20,
+                },
+                Segment::Text("{/red} [b] ".into()),
+                Segment::Line {
+                    name: "ask".into(),
+                    span: 31..38,
+                },
+                Segment::Text(" <<x>>".into()),
+            ]
+        );
+
+        // loading within the scope of the syntax, and with the default syntax outside of it
+        let loaded: Template = syntax.scope(|| ron::from_str(r#""<<gold>> {gold}""#).unwrap());
+        assert_eq!(loaded.segments.len(), 2);
+        assert_eq!(Template::parse("{gold}").unwrap().segments.len(), 1);
+
+        // saved along with its syntax, to be loaded outside of its scope
+        let saved = ron::to_string(&loaded).unwrap();
+        assert_eq!(ron::from_str::<Template>(&saved).unwrap(), loaded);
+        let saved = ron::to_string(&template).unwrap();
+        assert_eq!(ron::from_str::<Template>(&saved).unwrap(), template);
+    }
+
+    #[test]
+    fn delimiters_must_be_set_and_distinct() {
+        let message = |syntax: Result<TemplateSyntax, Error>| syntax.unwrap_err().to_string();
+        assert_eq!(
+            message(TemplateSyntax::new().with_line("", "]")),
+            "Malformed template: Delimiters can't be empty"
+        );
+        assert_eq!(
+            message(TemplateSyntax::new().with_placeholder("{", "")),
+            "Malformed template: Delimiters can't be empty"
+        );
+        assert_eq!(
+            message(TemplateSyntax::new().with_line("{", "}")),
+            r#"Malformed template: Delimiter "{" is used twice"#
+        );
+        assert_eq!(
+            message(TemplateSyntax::new().with_placeholder("%", "%")),
+            r#"Malformed template: Delimiter "%" is used twice"#
+        );
+        assert_eq!(
+            message(TemplateSyntax::new().with_placeholder(r"\(", ")")),
+            r#"Malformed template: Delimiter "\(" can't start with the "\" escape"#
+        );
+
+        // and when loaded
+        assert!(
+            ron::from_str::<TemplateSyntax>(r#"(placeholder: ("{", "}"), line: ("", "]"))"#)
+                .is_err()
+        );
+        let saved = r#"(source: "[a]", syntax: (placeholder: ("{", "}"), line: ("", "]")))"#;
+        assert!(ron::from_str::<Template>(saved).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constraint::{AliasRelation, Constraint},
+    narrative_world::NarrativeWorld,
+    scenario_action::Alias,
+    scenario_graph::AliasMap,
+};
+
+// An edge between two scenario actions. It can only be taken when its constraints are satisfied,
+// and its weight ranks it against the other edges of its source node.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub weight: f64,
+    pub relation_constraints: Vec<AliasRelation>,
+    pub world_constraints: Vec<Constraint>,
+    // how many times a scenario may go through this edge, unbounded if `None`
+    #[serde(default)]
+    pub max_traversals: Option<usize>,
+}
+
+impl Transition {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // builder methods
+
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn with_max_traversals(mut self, traversals: usize) -> Self {
+        self.max_traversals = Some(traversals);
+        self
+    }
+
+    pub fn with_relation_constraints<A, C>(mut self, me: A, other: A, constraints: C) -> Self
+    where
+        A: Into<Alias>,
+        C: IntoIterator<Item = Constraint>,
+    {
+        self.relation_constraints
+            .push(AliasRelation::new(me, other, constraints));
+        self
+    }
+
+    pub fn with_world_constraint(mut self, constraint: Constraint) -> Self {
+        self.world_constraints.push(constraint);
+        self
+    }
+
+    pub(crate) fn can_traverse(&self, traversals: usize) -> bool {
+        self.max_traversals.is_none_or(|max| traversals < max)
+    }
+
+    pub(crate) fn are_world_constraints_satisfied(&self, context: &NarrativeWorld) -> bool {
+        self.world_constraints
+            .iter()
+            .all(|constraint| constraint.is_satisfied_by(context.properties()))
+    }
+
+    pub(crate) fn are_relation_constraints_satisfied(
+        &self,
+        context: &NarrativeWorld,
+        alias_entities: &AliasMap,
+    ) -> bool {
+        self.relation_constraints
+            .iter()
+            .all(|relation| relation.is_satisfied_in(context, alias_entities))
+    }
+}
+
+impl From<f64> for Transition {
+    fn from(weight: f64) -> Self {
+        Self::new().with_weight(weight)
+    }
+}
@@ -0,0 +1,56 @@
+use std::{collections::HashMap, ops::Range};
+
+use serde::{Deserialize, Serialize};
+
+use crate::scenario_graph::ScenarioActionId;
+
+// How many times the nodes and edges of a scenario were traversed so far.
+// Scoped to a single `Scenario`, so that loops over weak edges can be bounded.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Visits {
+    nodes: HashMap<ScenarioActionId, usize>,
+    edges: HashMap<(ScenarioActionId, ScenarioActionId), usize>,
+}
+
+impl Visits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn node(&self, node_id: ScenarioActionId) -> usize {
+        self.nodes.get(&node_id).copied().unwrap_or_default()
+    }
+
+    pub fn edge(&self, from: ScenarioActionId, to: ScenarioActionId) -> usize {
+        self.edges.get(&(from, to)).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn enter(&mut self, node_id: ScenarioActionId) {
+        *self.nodes.entry(node_id).or_default() += 1;
+    }
+
+    pub(crate) fn traverse(&mut self, from: ScenarioActionId, to: ScenarioActionId) {
+        *self.edges.entry((from, to)).or_default() += 1;
+        self.enter(to);
+    }
+}
+
+// Restricts entering a node to a range of visits, either of the node itself or of another node.
+// `0..1` only offers a node which hasn't been visited yet.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VisitConstraint {
+    // `None` stands for the constrained node itself
+    pub node: Option<ScenarioActionId>,
+    pub visits: Range<usize>,
+}
+
+impl VisitConstraint {
+    pub fn new(node: Option<ScenarioActionId>, visits: Range<usize>) -> Self {
+        Self { node, visits }
+    }
+
+    pub(crate) fn is_satisfied_by(&self, target: ScenarioActionId, visits: &Visits) -> bool {
+        self.visits
+            .contains(&visits.node(self.node.unwrap_or(target)))
+    }
+}
@@ -109,7 +109,7 @@ mod story_graph_tests {
             )
             .expect(err_msg);
 
-        raconteur.insert(graph);
+        raconteur.insert(graph).unwrap();
 
         let context = NarrativeWorld::new()
             .with_world_property("location type", "city")
@@ -153,14 +153,15 @@ mod story_graph_tests {
 
         let scenarios = raconteur.query(&context);
 
-        assert!(!scenarios.is_empty());
-        let scenario = scenarios[0];
-        assert_eq!(scenario.alias_map.size(), 8);
+        // 2 rich citizens, 2 poor men and 2 little girls
+        assert_eq!(scenarios.len(), 8);
+        assert_eq!(scenarios[0].alias_map.size(), 3);
 
+        let story_graph = raconteur.get(scenarios[0].id()).unwrap();
         // TODO: problem, if the story world changes a leaf node might not be reachable. What to do in that case? Simply drop the story?
-        for scenario in scenarios {
-            // TODO: traverse scenario
-            let mut node_id = scenario.start();
+        for scenario in &scenarios {
+            let alias_map = &scenario.alias_map;
+            let mut node_id = story_graph.start();
             while !story_graph.next(node_id, &context, alias_map).is_empty() {
                 node_id = story_graph.next(node_id, &context, alias_map)[0];
             }
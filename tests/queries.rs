@@ -19,25 +19,27 @@ mod query_tests {
 
     fn guy_no_like_girl() -> Raconteur {
         let mut raconteur = Raconteur::new();
-        raconteur.insert({
-            let mut graph = ScenarioGraph::new();
-
-            graph.add_alias("guy", []);
-            graph.add_alias("girl", []);
-            let idx = graph.add(
-                ScenarioAction::new()
-                    .with_description("low_opinion")
-                    .with_relation_constraints(
-                        "guy",
-                        "girl",
-                        [Constraint::is_in_range("opinion", 0..1)],
-                    ),
-            );
-
-            graph.set_start_node(idx);
-
-            graph
-        });
+        raconteur
+            .insert({
+                let mut graph = ScenarioGraph::new();
+
+                graph.add_alias("guy", []);
+                graph.add_alias("girl", []);
+                let idx = graph.add(
+                    ScenarioAction::new()
+                        .with_description("low_opinion")
+                        .with_relation_constraints(
+                            "guy",
+                            "girl",
+                            [Constraint::is_in_range("opinion", 0..1)],
+                        ),
+                );
+
+                graph.set_start_node(idx);
+
+                graph
+            })
+            .unwrap();
 
         raconteur
     }
@@ -45,25 +47,27 @@ mod query_tests {
     fn guy_like_girl() -> Raconteur {
         let mut raconteur = Raconteur::new();
 
-        raconteur.insert({
-            let mut graph = ScenarioGraph::new();
-            graph.add_alias("guy", []);
-            graph.add_alias("girl", []);
-
-            let idx = graph.add(
-                ScenarioAction::new()
-                    .with_description("guy_like_girl")
-                    .with_relation_constraints(
-                        "guy",
-                        "girl",
-                        [Constraint::is_in_range("opinion", 1..4)],
-                    ),
-            );
-
-            graph.set_start_node(idx);
-
-            graph
-        });
+        raconteur
+            .insert({
+                let mut graph = ScenarioGraph::new();
+                graph.add_alias("guy", []);
+                graph.add_alias("girl", []);
+
+                let idx = graph.add(
+                    ScenarioAction::new()
+                        .with_description("guy_like_girl")
+                        .with_relation_constraints(
+                            "guy",
+                            "girl",
+                            [Constraint::is_in_range("opinion", 1..4)],
+                        ),
+                );
+
+                graph.set_start_node(idx);
+
+                graph
+            })
+            .unwrap();
 
         raconteur
     }
@@ -80,11 +84,27 @@ mod query_tests {
         let raconteur: Raconteur = guy_like_girl();
         let story_candidates = raconteur.query(&query());
         let first_story = &story_candidates[0];
-        let story_graph = raconteur.get(first_story.id());
-        let start_node = story_graph.get(story_graph.start());
+        let story_graph = raconteur.get(first_story.id()).unwrap();
+        let start_node = story_graph.get(story_graph.start()).unwrap();
         assert_eq!(start_node.description, "guy_like_girl");
     }
 
+    #[test]
+    fn a_match_with_pinned_aliases() {
+        let raconteur = guy_like_girl();
+
+        let options = QueryOptions::new().with_pinned_alias("girl", GIRL_ID);
+        let story_candidates = raconteur.query_with(&query(), &options).collect::<Vec<_>>();
+        assert_eq!(story_candidates.len(), 1);
+        assert_eq!(story_candidates[0].alias_map.get("guy"), Some(GUY_ID));
+
+        let options = QueryOptions::new().with_pinned_alias("guy", GIRL_ID);
+        assert_eq!(raconteur.query_with(&query(), &options).count(), 0);
+
+        let options = QueryOptions::new().with_focus(GUY_ID);
+        assert_eq!(raconteur.query_with(&query(), &options).count(), 1);
+    }
+
     #[test]
     fn many_matches() {
         const MAX_MONEY: Real = 100000.0;
@@ -94,58 +114,62 @@ mod query_tests {
 
         let mut raconteur = Raconteur::default();
         // wealthy player
-        raconteur.insert({
-            let mut graph = ScenarioGraph::new();
-            graph.add_alias(
-                "baking_man",
-                [
-                    Constraint::has("important"),
-                    Constraint::equals("job", "baker"),
-                ],
-            );
-            graph.add_alias(
-                "player",
-                [
-                    Constraint::has("player"),
-                    Constraint::is_in_range_float("money", 10.0..MAX_MONEY), // TODO: at least, at most?
-                ],
-            );
-            let node_idx = graph.add(
-                ScenarioAction::new()
-                    .with_world_constraint(Constraint::equals("location", "bakery")),
-            );
-
-            graph.set_start_node(node_idx);
-
-            graph
-        });
+        raconteur
+            .insert({
+                let mut graph = ScenarioGraph::new();
+                graph.add_alias(
+                    "baking_man",
+                    [
+                        Constraint::has("important"),
+                        Constraint::equals("job", "baker"),
+                    ],
+                );
+                graph.add_alias(
+                    "player",
+                    [
+                        Constraint::has("player"),
+                        Constraint::is_in_range_float("money", 10.0..MAX_MONEY), // TODO: at least, at most?
+                    ],
+                );
+                let node_idx = graph.add(
+                    ScenarioAction::new()
+                        .with_world_constraint(Constraint::equals("location", "bakery")),
+                );
+
+                graph.set_start_node(node_idx);
+
+                graph
+            })
+            .unwrap();
 
         // Poor player
-        raconteur.insert({
-            let mut graph = ScenarioGraph::new();
-            graph.add_alias(
-                "baking_man",
-                [
-                    Constraint::has("important"),
-                    Constraint::equals("job", "baker"),
-                ],
-            );
-            graph.add_alias(
-                "player",
-                [
-                    Constraint::has("player"),
-                    Constraint::is_in_range_float("money", 0.0..20.0),
-                ],
-            );
-            let node_idx = graph.add(
-                ScenarioAction::new()
-                    .with_world_constraint(Constraint::equals("location", "bakery")),
-            );
-
-            graph.set_start_node(node_idx);
-
-            graph
-        });
+        raconteur
+            .insert({
+                let mut graph = ScenarioGraph::new();
+                graph.add_alias(
+                    "baking_man",
+                    [
+                        Constraint::has("important"),
+                        Constraint::equals("job", "baker"),
+                    ],
+                );
+                graph.add_alias(
+                    "player",
+                    [
+                        Constraint::has("player"),
+                        Constraint::is_in_range_float("money", 0.0..20.0),
+                    ],
+                );
+                let node_idx = graph.add(
+                    ScenarioAction::new()
+                        .with_world_constraint(Constraint::equals("location", "bakery")),
+                );
+
+                graph.set_start_node(node_idx);
+
+                graph
+            })
+            .unwrap();
 
         let query_player_wealthy = NarrativeWorld::new()
             .with_entities([
@@ -163,10 +187,9 @@ mod query_tests {
 
         assert_eq!(stories.len(), 1);
         let first_story = &stories[0];
-        assert_eq!(first_story.alias_candidates.len(), 1);
-        let aliases = &first_story.alias_candidates[0];
-        assert_eq!(aliases["player"], PLAYER_ID);
-        assert_eq!(aliases["baking_man"], BAKER_ID);
+        let aliases = &first_story.alias_map;
+        assert_eq!(aliases.get("player"), Some(PLAYER_ID));
+        assert_eq!(aliases.get("baking_man"), Some(BAKER_ID));
 
         let query_player_poor = NarrativeWorld::new()
             .with_entities([
@@ -182,10 +205,9 @@ mod query_tests {
 
         assert_eq!(stories.len(), 1);
         let first_story = &stories[0];
-        assert_eq!(first_story.alias_candidates.len(), 1);
-        let aliases = &first_story.alias_candidates[0];
-        assert_eq!(aliases["player"], PLAYER_ID);
-        assert_eq!(aliases["baking_man"], BAKER_ID);
+        let aliases = &first_story.alias_map;
+        assert_eq!(aliases.get("player"), Some(PLAYER_ID));
+        assert_eq!(aliases.get("baking_man"), Some(BAKER_ID));
 
         let query_player_average_wealth = NarrativeWorld::new()
             .with_entities([
@@ -203,4 +225,48 @@ mod query_tests {
 
         assert_eq!(stories.len(), 2);
     }
+
+    #[test]
+    fn villagers_gather_around_the_mayor() {
+        let mut raconteur = Raconteur::new();
+        raconteur.insert({
+            let mut graph = ScenarioGraph::new();
+            graph.add_alias("mayor", [Constraint::has("mayor")]);
+            graph.add_group_alias("villagers", [Constraint::has("villager")], 2..usize::MAX);
+            let idx = graph.add(
+                ScenarioAction::new()
+                    .with_quantified_relation_constraints(
+                        Quantifier::Any,
+                        "villagers",
+                        "mayor",
+                        [Constraint::has("voted")],
+                    )
+                    .with_directive(
+                        "{villagers.count} villagers gather around {mayor.name}: {villagers.name|list}",
+                    ),
+            );
+            graph.set_start_node(idx);
+            graph
+        }).unwrap();
+
+        let village = NarrativeWorld::new()
+            .with_entities([
+                Entity::new(0).with("name", "Ada").with("mayor", ""),
+                Entity::new(1).with("name", "Bo").with("villager", ""),
+                Entity::new(2).with("name", "Cy").with("villager", ""),
+                Entity::new(3).with("name", "Di").with("villager", ""),
+            ])
+            .with_relation(2, 0, "voted", "");
+
+        let stories = raconteur.query(&village);
+        assert_eq!(stories.len(), 1);
+        let directive = raconteur
+            .get(stories[0].id())
+            .unwrap()
+            .get(stories[0].current())
+            .unwrap()
+            .directive(&stories[0].alias_map, &village)
+            .unwrap();
+        assert_eq!(directive, "3 villagers gather around Ada: Bo, Cy and Di");
+    }
 }